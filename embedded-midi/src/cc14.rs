@@ -0,0 +1,278 @@
+//! 14-bit Control Change values
+//! Controllers 0-31 carry the MSB of the value, controllers 32-63 the matching LSB.
+//! Timestamps are in milliseconds, from any monotonic source (e.g. `runtime::now_millis()`).
+
+use core::iter::FromIterator;
+
+use heapless::{LinearMap, Vec};
+
+use crate::{Control, MidiChannel, MidiError, MidiMessage, Packet, PacketList, U14, U7};
+
+/// Number of controllers that have a 14-bit LSB counterpart
+pub const CC14_CONTROLS: u8 = 32;
+
+/// Offset from MSB controller number to LSB controller number
+pub const CC14_LSB_OFFSET: u8 = 32;
+
+/// Returns true if the controller is the MSB of a 14-bit pair
+pub fn is_cc14_msb(cc: Control) -> bool {
+    cc.0 < CC14_CONTROLS
+}
+
+/// Returns true if the controller is the LSB of a 14-bit pair
+pub fn is_cc14_lsb(cc: Control) -> bool {
+    (CC14_LSB_OFFSET..CC14_LSB_OFFSET + CC14_CONTROLS).contains(&cc.0)
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Cc14Value {
+    pub channel: MidiChannel,
+    /// MSB controller number (0-31)
+    pub control: Control,
+    pub value: U14,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Cc14Slot {
+    msb: U7,
+    lsb: U7,
+    // time the MSB was received, if still waiting for LSB
+    pending_since: Option<u64>,
+    // an LSB was seen for this controller, it is really 14-bit
+    paired: bool,
+}
+
+/// Pairs MSB and LSB Control Changes of all channels into 14-bit values
+/// `N` is the max number of (channel, controller) pairs tracked at once
+/// Untracked controllers are decoded as MSB-only values
+#[derive(Debug)]
+pub struct Cc14Decoder<const N: usize> {
+    slots: LinearMap<(u8, u8), Cc14Slot, N>,
+    timeout_millis: u64,
+    msb_fallback: bool,
+}
+
+impl<const N: usize> Default for Cc14Decoder<N> {
+    fn default() -> Self {
+        Cc14Decoder {
+            slots: LinearMap::new(),
+            timeout_millis: 5,
+            msb_fallback: true,
+        }
+    }
+}
+
+impl<const N: usize> Cc14Decoder<N> {
+    /// How long to wait for the LSB after an MSB before emitting the MSB-only value (default: 5ms)
+    /// Zero emits values on MSB immediately, then again on LSB
+    pub fn with_timeout(mut self, millis: u64) -> Self {
+        self.timeout_millis = millis;
+        self
+    }
+
+    /// Emit values on MSB immediately for controllers that never sent an LSB (default: true)
+    /// Otherwise, MSB-only controllers are only emitted after timeout by `poll()`
+    pub fn with_msb_fallback(mut self, msb_fallback: bool) -> Self {
+        self.msb_fallback = msb_fallback;
+        self
+    }
+
+    /// Forget all controller values and pairings
+    pub fn reset(&mut self) {
+        self.slots.clear();
+    }
+
+    /// Feed a message to the decoder
+    /// Returns Some(value) when a 14-bit value is complete
+    /// Messages other than Control Changes 0-63 are ignored
+    pub fn advance(&mut self, message: &MidiMessage, now_millis: u64) -> Option<Cc14Value> {
+        let (channel, cc, value) = match *message {
+            MidiMessage::ControlChange(ch, cc, value) => (ch, cc, value),
+            _ => return None,
+        };
+        if is_cc14_msb(cc) {
+            self.msb(channel, cc, value, now_millis)
+        } else if is_cc14_lsb(cc) {
+            self.lsb(channel, U7(cc.0 - CC14_LSB_OFFSET), value)
+        } else {
+            None
+        }
+    }
+
+    fn msb(&mut self, channel: MidiChannel, control: Control, msb: U7, now_millis: u64) -> Option<Cc14Value> {
+        let key = (channel.0, control.0);
+        if !self.slots.contains_key(&key) {
+            let slot = Cc14Slot { msb, lsb: U7::MIN, pending_since: None, paired: false };
+            if self.slots.insert(key, slot).is_err() {
+                // no room to wait for LSB
+                return Some(Cc14Value { channel, control, value: U14::from((U7::MIN, msb)) });
+            }
+        }
+        let immediate = self.timeout_millis == 0;
+        let fallback = self.msb_fallback;
+        let slot = self.slots.get_mut(&key)?;
+        slot.msb = msb;
+        // new MSB resets LSB
+        slot.lsb = U7::MIN;
+        if immediate || (fallback && !slot.paired) {
+            slot.pending_since = None;
+            Some(Cc14Value { channel, control, value: U14::from((slot.lsb, slot.msb)) })
+        } else {
+            slot.pending_since = Some(now_millis);
+            None
+        }
+    }
+
+    fn lsb(&mut self, channel: MidiChannel, control: Control, lsb: U7) -> Option<Cc14Value> {
+        let key = (channel.0, control.0);
+        if !self.slots.contains_key(&key) {
+            // LSB without MSB, assume MSB zero
+            let slot = Cc14Slot { msb: U7::MIN, lsb, pending_since: None, paired: true };
+            if self.slots.insert(key, slot).is_err() {
+                return None;
+            }
+        }
+        let slot = self.slots.get_mut(&key)?;
+        slot.lsb = lsb;
+        slot.paired = true;
+        slot.pending_since = None;
+        Some(Cc14Value { channel, control, value: U14::from((slot.lsb, slot.msb)) })
+    }
+
+    /// Emit an MSB-only value for which the LSB did not arrive in time
+    /// Should be called periodically, until it returns None
+    pub fn poll(&mut self, now_millis: u64) -> Option<Cc14Value> {
+        let timeout = self.timeout_millis;
+        for ((channel, control), slot) in self.slots.iter_mut() {
+            if let Some(since) = slot.pending_since {
+                if now_millis.saturating_sub(since) >= timeout {
+                    slot.pending_since = None;
+                    return Some(Cc14Value {
+                        channel: MidiChannel(*channel),
+                        control: U7(*control),
+                        value: U14::from((slot.lsb, slot.msb)),
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Produces the MSB & LSB Control Change pair for 14-bit values
+#[derive(Debug, Default)]
+pub struct Cc14Encoder {
+    // last MSB sent, per channel and controller
+    last_msb: [[Option<U7>; CC14_CONTROLS as usize]; 16],
+    running_msb: bool,
+    msb_only: bool,
+}
+
+impl Cc14Encoder {
+    /// Skip the MSB if it is the same as last sent for this controller (default: false)
+    /// Receivers keep the MSB, but some reset the LSB on MSB, so the LSB is always sent
+    pub fn with_running_msb(mut self, running_msb: bool) -> Self {
+        self.running_msb = running_msb;
+        self
+    }
+
+    /// Only send the MSB, for 7-bit receivers (default: false)
+    pub fn with_msb_only(mut self, msb_only: bool) -> Self {
+        self.msb_only = msb_only;
+        self
+    }
+
+    /// Forget last MSBs sent, next values will send their MSB again
+    pub fn reset(&mut self) {
+        self.last_msb = Default::default();
+    }
+
+    /// `control` is the MSB controller number (0-31)
+    pub fn encode(&mut self, value: &Cc14Value) -> Result<PacketList, MidiError> {
+        let Cc14Value { channel, control, value } = *value;
+        if !is_cc14_msb(control) {
+            return Err(MidiError::InvalidControl);
+        }
+        let cc = |cc: u8, value: U7| Packet::from(MidiMessage::ControlChange(channel, U7(cc), value));
+        let (lsb, msb) = value.into();
+        let mut packets: Vec<Packet, 2> = Vec::new();
+
+        let last_msb = &mut self.last_msb[(channel.0 & 0x0F) as usize][control.0 as usize];
+        if self.msb_only || !self.running_msb || *last_msb != Some(msb) {
+            let _ = packets.push(cc(control.0, msb));
+            *last_msb = Some(msb);
+        }
+        if !self.msb_only {
+            let _ = packets.push(cc(control.0 + CC14_LSB_OFFSET, lsb));
+        }
+        Ok(PacketList::from_iter(packets))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+    use core::convert::TryFrom;
+
+    fn cc(ch: u8, cc: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange(channel(ch), U7(cc), U7(value))
+    }
+
+    fn cc14(ch: u8, control: u8, value: u16) -> Option<Cc14Value> {
+        Some(Cc14Value { channel: channel(ch), control: U7(control), value: U14(value) })
+    }
+
+    #[test]
+    fn decode_pair() {
+        let mut decoder: Cc14Decoder<4> = Cc14Decoder::default();
+        // first MSB is emitted right away, controller not known to be 14-bit yet
+        assert_eq!(cc14(1, 10, 0x100), decoder.advance(&cc(1, 10, 2), 0));
+        assert_eq!(cc14(1, 10, 0x105), decoder.advance(&cc(1, 42, 5), 1));
+        // now paired, MSB waits for LSB
+        assert_eq!(None, decoder.advance(&cc(1, 10, 3), 2));
+        assert_eq!(None, decoder.poll(3));
+        assert_eq!(cc14(1, 10, 0x181), decoder.advance(&cc(1, 42, 1), 3));
+        assert_eq!(None, decoder.advance(&cc(1, 74, 1), 4));
+    }
+
+    #[test]
+    fn decode_timeout() {
+        let mut decoder: Cc14Decoder<4> = Cc14Decoder::default().with_timeout(10).with_msb_fallback(false);
+        assert_eq!(None, decoder.advance(&cc(2, 1, 0x7F), 100));
+        assert_eq!(None, decoder.poll(105));
+        assert_eq!(cc14(2, 1, 0x3F80), decoder.poll(110));
+        assert_eq!(None, decoder.poll(120));
+    }
+
+    #[test]
+    fn decode_no_room() {
+        let mut decoder: Cc14Decoder<1> = Cc14Decoder::default().with_msb_fallback(false);
+        assert_eq!(None, decoder.advance(&cc(1, 1, 1), 0));
+        assert_eq!(cc14(1, 2, 0x80), decoder.advance(&cc(1, 2, 1), 0));
+    }
+
+    #[test]
+    fn encode_roundtrip() {
+        let mut encoder = Cc14Encoder::default().with_running_msb(true);
+        let mut decoder: Cc14Decoder<4> = Cc14Decoder::default().with_msb_fallback(false);
+        let value = cc14(3, 7, 0x1234).unwrap();
+
+        let packets = encoder.encode(&value).unwrap();
+        assert_eq!(2, packets.len());
+        let decoded: Vec<Cc14Value, 2> = packets.iter()
+            .filter_map(|p| decoder.advance(&MidiMessage::try_from(*p).unwrap(), 0))
+            .collect();
+        assert_eq!(&[value], decoded.as_slice());
+
+        // MSB unchanged
+        assert_eq!(1, encoder.encode(&value).unwrap().len());
+        encoder.reset();
+        assert_eq!(2, encoder.encode(&value).unwrap().len());
+
+        let mut encoder = Cc14Encoder::default().with_msb_only(true);
+        assert_eq!(1, encoder.encode(&value).unwrap().len());
+        assert!(encoder.encode(&cc14(3, 40, 0).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+//! Tempo & transport tracking from incoming MIDI clock
+//! Timestamps are in microseconds, from any monotonic source (e.g. `runtime::now_micros()`).
+
+use crate::MidiMessage;
+
+/// MIDI clock resolution, in clocks per quarter note
+pub const CLOCKS_PER_BEAT: u32 = 24;
+
+/// Song Position Pointer resolution, in clocks per "MIDI beat" (sixteenth note)
+pub const CLOCKS_PER_SIXTEENTH: u32 = 6;
+
+/// Consecutive out of range intervals after which they are taken as the new tempo
+const MAX_OUTLIERS: u8 = 3;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Transport {
+    Stopped,
+    Playing,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockEvent {
+    /// Playback started from the beginning of the song
+    Started,
+    /// Playback resumed from current song position
+    Continued,
+    Stopped,
+    /// Song position was set, in sixteenths
+    Located(u32),
+    /// Playback reached a new sixteenth
+    Sixteenth(u32),
+}
+
+/// Follows MIDI clock, transport and song position messages
+#[derive(Debug)]
+pub struct ClockTracker {
+    transport: Transport,
+    // song position of the next clock received while playing
+    next_clock: u32,
+    last_clock: Option<u32>,
+    last_tick_micros: Option<u64>,
+    // filtered clock interval
+    interval_micros: Option<u32>,
+    smoothing: u8,
+    outliers: u8,
+    timeout_micros: u64,
+}
+
+impl Default for ClockTracker {
+    fn default() -> Self {
+        ClockTracker {
+            transport: Transport::Stopped,
+            next_clock: 0,
+            last_clock: None,
+            last_tick_micros: None,
+            interval_micros: None,
+            smoothing: 3,
+            outliers: 0,
+            timeout_micros: 1_000_000,
+        }
+    }
+}
+
+impl ClockTracker {
+    /// Weight of the average against new clock intervals, as a power of two (default: 3, 1/8 of new interval)
+    /// Higher values filter more jitter but follow tempo changes slower
+    pub fn with_smoothing(mut self, shift: u8) -> Self {
+        self.smoothing = shift.min(8);
+        self
+    }
+
+    /// Tempo is unknown if no clock is received for this long (default: 1s)
+    pub fn with_timeout(mut self, micros: u64) -> Self {
+        self.timeout_micros = micros;
+        self
+    }
+
+    /// Feed a message to the tracker
+    /// Returns Some(event) when transport or song position changed
+    pub fn advance(&mut self, message: &MidiMessage, now_micros: u64) -> Option<ClockEvent> {
+        match *message {
+            MidiMessage::TimingClock => self.tick(now_micros),
+            MidiMessage::Start => {
+                self.transport = Transport::Playing;
+                self.next_clock = 0;
+                self.last_clock = None;
+                Some(ClockEvent::Started)
+            }
+            MidiMessage::Continue => {
+                self.transport = Transport::Playing;
+                Some(ClockEvent::Continued)
+            }
+            MidiMessage::Stop => {
+                self.transport = Transport::Stopped;
+                Some(ClockEvent::Stopped)
+            }
+            MidiMessage::SongPositionPointer(lsb, msb) => {
+                let sixteenths = (u8::from(msb) as u32) << 7 | u8::from(lsb) as u32;
+                self.next_clock = sixteenths * CLOCKS_PER_SIXTEENTH;
+                self.last_clock = None;
+                Some(ClockEvent::Located(sixteenths))
+            }
+            _ => None,
+        }
+    }
+
+    fn tick(&mut self, now_micros: u64) -> Option<ClockEvent> {
+        if let Some(last) = self.last_tick_micros {
+            let elapsed = now_micros.saturating_sub(last);
+            if elapsed > self.timeout_micros {
+                self.interval_micros = None;
+            } else {
+                self.filter(elapsed as u32);
+            }
+        }
+        self.last_tick_micros = Some(now_micros);
+
+        if self.transport != Transport::Playing {
+            return None;
+        }
+        let clock = self.next_clock;
+        self.last_clock = Some(clock);
+        self.next_clock += 1;
+        if clock.is_multiple_of(CLOCKS_PER_SIXTEENTH) {
+            Some(ClockEvent::Sixteenth(clock / CLOCKS_PER_SIXTEENTH))
+        } else {
+            None
+        }
+    }
+
+    fn filter(&mut self, interval: u32) {
+        let average = match self.interval_micros {
+            None => {
+                self.interval_micros = Some(interval);
+                return;
+            }
+            Some(average) => average,
+        };
+        if interval > average * 2 || interval < average / 2 {
+            // ignore isolated hiccups, but follow sudden tempo changes
+            self.outliers += 1;
+            if self.outliers >= MAX_OUTLIERS {
+                self.outliers = 0;
+                self.interval_micros = Some(interval);
+            }
+            return;
+        }
+        self.outliers = 0;
+        let delta = (interval as i64 - average as i64) >> self.smoothing;
+        self.interval_micros = Some((average as i64 + delta) as u32);
+    }
+
+    pub fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.transport == Transport::Playing
+    }
+
+    /// Filtered duration of a clock, if clock is running
+    pub fn clock_micros(&self, now_micros: u64) -> Option<u32> {
+        let last = self.last_tick_micros?;
+        if now_micros.saturating_sub(last) > self.timeout_micros {
+            return None;
+        }
+        self.interval_micros.filter(|i| *i > 0)
+    }
+
+    /// Estimated tempo in beats (quarter notes) per minute, if clock is running
+    pub fn bpm(&self, now_micros: u64) -> Option<f32> {
+        let interval = self.clock_micros(now_micros)?;
+        Some(60_000_000.0 / (interval as f32 * CLOCKS_PER_BEAT as f32))
+    }
+
+    /// Song position of last clock received, in clocks since start of song
+    pub fn position_clocks(&self) -> u32 {
+        self.last_clock.unwrap_or(self.next_clock)
+    }
+
+    /// Song position, in sixteenths since start of song
+    pub fn position_sixteenths(&self) -> u32 {
+        self.position_clocks() / CLOCKS_PER_SIXTEENTH
+    }
+
+    /// Progress within the current beat, from 0.0 to 1.0 (excluded)
+    /// Interpolated between clocks using estimated tempo
+    pub fn beat_phase(&self, now_micros: u64) -> f32 {
+        let clock_in_beat = (self.position_clocks() % CLOCKS_PER_BEAT) as f32;
+        let since_clock = match (self.last_clock, self.last_tick_micros, self.clock_micros(now_micros)) {
+            (Some(_), Some(last), Some(interval)) if self.is_playing() => {
+                (now_micros.saturating_sub(last) as f32 / interval as f32).min(0.999)
+            }
+            _ => 0.0,
+        };
+        (clock_in_beat + since_clock) / CLOCKS_PER_BEAT as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Packet, U7};
+    use core::convert::TryFrom;
+
+    // 120 BPM
+    const INTERVAL: u64 = 20_833;
+
+    #[test]
+    fn tempo_with_jitter() {
+        let mut clock = ClockTracker::default();
+        let mut now = 0;
+        for i in 0..96 {
+            let jitter = if i % 2 == 0 { 500 } else { 0 };
+            clock.advance(&MidiMessage::TimingClock, now + jitter);
+            now += INTERVAL;
+        }
+        let bpm = clock.bpm(now).unwrap();
+        assert!((bpm - 120.0).abs() < 1.5, "{}", bpm);
+
+        // a single late clock barely moves the estimate
+        clock.advance(&MidiMessage::TimingClock, now + INTERVAL * 3);
+        assert!((clock.bpm(now).unwrap() - 120.0).abs() < 1.5);
+
+        // clock stopped
+        assert_eq!(None, clock.bpm(now + 2_000_000));
+    }
+
+    #[test]
+    fn transport_and_position() {
+        let mut clock = ClockTracker::default();
+        assert_eq!(None, clock.advance(&MidiMessage::TimingClock, 0));
+        assert_eq!(Some(ClockEvent::Started), clock.advance(&MidiMessage::Start, 10));
+        assert_eq!(Some(ClockEvent::Sixteenth(0)), clock.advance(&MidiMessage::TimingClock, INTERVAL));
+        for i in 1..6 {
+            assert_eq!(None, clock.advance(&MidiMessage::TimingClock, INTERVAL * (i + 1)));
+        }
+        assert_eq!(Some(ClockEvent::Sixteenth(1)), clock.advance(&MidiMessage::TimingClock, INTERVAL * 7));
+        assert_eq!(1, clock.position_sixteenths());
+
+        assert_eq!(Some(ClockEvent::Stopped), clock.advance(&MidiMessage::Stop, INTERVAL * 8));
+        assert_eq!(None, clock.advance(&MidiMessage::TimingClock, INTERVAL * 8));
+        assert_eq!(6, clock.position_clocks());
+
+        // 0x101 sixteenths, round trip through the packet parser
+        let spp = MidiMessage::try_from(Packet::from(MidiMessage::SongPositionPointer(U7(1), U7(2)))).unwrap();
+        assert_eq!(Some(ClockEvent::Located(0x101)), clock.advance(&spp, INTERVAL * 9));
+        assert_eq!(0x101, clock.position_sixteenths());
+        assert_eq!(Some(ClockEvent::Continued), clock.advance(&MidiMessage::Continue, INTERVAL * 9));
+        assert!(clock.is_playing());
+        assert_eq!(Some(ClockEvent::Sixteenth(0x101)), clock.advance(&MidiMessage::TimingClock, INTERVAL * 10));
+    }
+
+    #[test]
+    fn beat_phase() {
+        let mut clock = ClockTracker::default();
+        clock.advance(&MidiMessage::Start, 0);
+        for i in 0..13 {
+            clock.advance(&MidiMessage::TimingClock, INTERVAL * i);
+        }
+        // 12th clock + half a clock
+        let phase = clock.beat_phase(INTERVAL * 12 + INTERVAL / 2);
+        assert!((phase - 12.5 / 24.0).abs() < 0.01, "{}", phase);
+        clock.advance(&MidiMessage::Stop, INTERVAL * 13);
+        assert_eq!(0.5, clock.beat_phase(INTERVAL * 13));
+    }
+}
@@ -0,0 +1,140 @@
+//! Packet parsing, serialization and counters shared by byte-oriented ports (UART, stream)
+
+use crate::{CableNumber, MidiError, Packet, PacketList, PacketParser, PacketSerializer, PortStats};
+
+/// Converts received bytes to packets and packets to transmit to bytes, keeping port counters
+/// Ports only move bytes between the codec and their device
+pub struct ByteCodec<const TX_LEN: usize> {
+    serializer: PacketSerializer<TX_LEN>,
+    parser: PacketParser,
+    cable_number: CableNumber,
+    stats: PortStats,
+    clock: Option<fn() -> u64>,
+}
+
+impl<const TX_LEN: usize> Default for ByteCodec<TX_LEN> {
+    fn default() -> Self {
+        ByteCodec {
+            serializer: PacketSerializer::default(),
+            parser: PacketParser::default(),
+            cable_number: 0,
+            stats: PortStats::default(),
+            clock: None,
+        }
+    }
+}
+
+impl<const TX_LEN: usize> ByteCodec<TX_LEN> {
+    pub fn with_running_status(mut self, running_status: bool) -> Self {
+        self.serializer = self.serializer.with_running_status(running_status);
+        self
+    }
+
+    pub fn with_cable_num(mut self, cable_number: CableNumber) -> Self {
+        self.cable_number = cable_number;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn stats(&self) -> PortStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) -> PortStats {
+        self.stats.take()
+    }
+
+    /// Count a device error, returned for convenience
+    pub fn port_error(&mut self) -> MidiError {
+        self.stats.error(&MidiError::PortError);
+        MidiError::PortError
+    }
+
+    /// Packet held by the parser after an interrupted sysex, to return before reading more bytes
+    pub fn take_pending(&mut self) -> Option<Packet> {
+        let packet = self.parser.take_pending()?;
+        Some(self.packet_in(packet))
+    }
+
+    /// Parse a received byte, returns a packet once complete
+    pub fn advance(&mut self, byte: u8) -> Result<Option<Packet>, MidiError> {
+        match self.parser.advance(byte) {
+            Ok(packet) => Ok(packet.map(|packet| self.packet_in(packet))),
+            Err(err) => {
+                self.stats.error(&err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Serialize packets for output, stops at the first packet that does not fit
+    /// Packets left out are not counted as dropped, callers usually retry them once the device caught up
+    pub fn push(&mut self, packets: &PacketList) -> Result<(), MidiError> {
+        let mut result = Ok(());
+        for packet in packets.iter() {
+            if let Err(err) = self.serializer.push(*packet) {
+                result = Err(err);
+                break;
+            }
+            self.stats.packet_out(packet);
+        }
+        if !packets.is_empty() {
+            self.touch();
+        }
+        result
+    }
+
+    /// Next byte to write to device
+    pub fn next_byte(&mut self) -> Option<u8> {
+        self.serializer.next_byte()
+    }
+
+    /// True if all serialized bytes were taken
+    pub fn is_empty(&self) -> bool {
+        self.serializer.is_empty()
+    }
+
+    fn packet_in(&mut self, packet: Packet) -> Packet {
+        self.stats.packet_in(&packet);
+        self.touch();
+        packet.with_cable_num(self.cable_number)
+    }
+
+    fn touch(&mut self) {
+        if let Some(clock) = self.clock {
+            self.stats.touch(clock());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{channel, MidiMessage, Note, U7};
+
+    #[test]
+    fn parse_and_serialize() {
+        fn clock() -> u64 { 7 }
+        let mut codec: ByteCodec<4> = ByteCodec::default().with_cable_num(2).with_clock(clock);
+        for byte in [0xF0, 0x42] {
+            assert_eq!(Ok(None), codec.advance(byte));
+        }
+        // tune request interrupting the sysex is held until the error was reported
+        assert_eq!(Err(MidiError::SysexInterrupted), codec.advance(0xF6));
+        assert_eq!(Some(Packet::from(MidiMessage::TuneRequest).with_cable_num(2)), codec.take_pending());
+        assert_eq!(None, codec.take_pending());
+        assert_eq!(1, codec.stats().packets_in);
+        assert_eq!(1, codec.stats().parse_errors);
+        assert_eq!(Some(7), codec.stats().last_activity);
+
+        let note_on = Packet::from(MidiMessage::NoteOn(channel(1), Note::C4, U7(100)));
+        assert_eq!(Err(MidiError::BufferFull), codec.push(&PacketList::from_iter([note_on, note_on])));
+        assert_eq!(1, codec.stats().packets_out);
+        assert_eq!(0, codec.stats().dropped);
+        assert_eq!(Some(0x90), codec.next_byte());
+    }
+}
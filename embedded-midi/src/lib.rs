@@ -1,6 +1,10 @@
 #![no_std]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 use core::array::TryFromSliceError;
+use core::future::Future;
 use core::iter::FromIterator;
 use core::ops::{Deref, DerefMut};
 
@@ -9,7 +13,8 @@ use heapless::Vec;
 use usb_device::UsbError;
 
 pub use message::{MidiMessage, note_off, note_on, program_change};
-pub use note::Note;
+pub use mode::ChannelMode;
+pub use note::{MiddleC, Note, NoteName};
 pub use packet::{CableNumber, CodeIndexNumber, Packet};
 
 pub use status::Status;
@@ -18,9 +23,19 @@ pub use u4::U4;
 pub use u6::U6;
 pub use u7::U7;
 pub use parser::{PacketParser};
+pub use serializer::PacketSerializer;
+pub use serial::SerialMidiPort;
 pub use status::is_channel_status;
 pub use status::is_non_status;
+pub use status::is_realtime;
 pub use ports::*;
+pub use queue::PacketQueue;
+pub use loopback::{VirtualCable, VirtualPort};
+pub use router::{Route, Router};
+#[cfg(feature = "std")]
+pub use stream::StreamMidiPort;
+pub use stats::PortStats;
+pub use ump::{Ump, Midi2Message, UmpPacker, UmpSplitter};
 
 mod u4;
 mod u6;
@@ -29,11 +44,34 @@ mod u14;
 mod status;
 mod note;
 mod message;
+pub mod mode;
 mod packet;
 mod parser;
+mod serializer;
+mod serial;
+mod codec;
 mod ports;
-
-#[derive(Clone, Copy, Debug)]
+mod queue;
+mod text;
+mod loopback;
+#[cfg(feature = "std")]
+mod stream;
+mod stats;
+pub mod ump;
+pub mod smf;
+pub mod nrpn;
+pub mod cc14;
+pub mod mtc;
+pub mod clock;
+pub mod tuning;
+pub mod universal;
+pub mod theory;
+pub mod mpe;
+pub mod state;
+pub mod program;
+pub mod router;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// MIDI channel, stored as 0-15
 pub struct MidiChannel(pub u8);
@@ -132,28 +170,55 @@ pub trait Transmit {
     fn transmit(&mut self, packet: PacketList) -> Result<(), MidiError>;
 }
 
-#[derive(Debug)]
+/// Wait for reception of MIDI packets
+pub trait AsyncReceive {
+    /// Resolves when the next packet is received
+    fn recv(&mut self) -> impl Future<Output=Result<Packet, MidiError>>;
+}
+
+/// Send a list of packets, waiting for room in output buffer
+pub trait AsyncTransmit {
+    /// Resolves once all packets were queued for output
+    fn send(&mut self, packets: PacketList) -> impl Future<Output=Result<(), MidiError>>;
+}
+
+#[derive(Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum MidiError {
     SysexInterrupted,
     InvalidStatus(u8),
     BadPacket(Packet),
+    /// Header word of UMP
+    BadUmp(u32),
+    /// Message has no equivalent in target protocol
+    Untranslatable,
     NoModeForParameter,
     SysexOutOfBounds,
+    InvalidSysex,
     InvalidCodeIndexNumber,
     InvalidCableNumber,
     InvalidChannel,
     InvalidProgram,
+    InvalidControl,
     InvalidNote,
     InvalidVelocity,
     InvalidInteger,
+    InvalidChunk,
+    InvalidMetaEvent(u8),
+    UnsupportedFormat,
+    /// Malformed text form of a message
+    InvalidText,
+    TruncatedData,
+    /// Event is earlier than the previous one
+    OutOfOrder,
 
     // External errors
     TryFromSliceError,
     PortError,
     BufferFull,
     TooManyPorts,
+    TooManyRoutes,
     InvalidPort,
     DroppedPacket,
     UnknownInterface(MidiInterface),
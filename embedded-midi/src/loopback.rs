@@ -0,0 +1,230 @@
+//! In-memory MIDI ports, for running apps and routes without hardware
+//! A `VirtualCable` connects two `VirtualPort` ends, packets transmitted by one end are received by the other.
+
+use heapless::Deque;
+use spin::mutex::SpinMutex;
+
+use crate::{MidiError, MidiPorts, Packet, PacketList, PortDirection, PortHandle, PortInfo, PortStats, Receive, Transmit};
+
+/// Packets in flight, with time they can be received
+type Lane<const N: usize> = SpinMutex<Deque<(Packet, u64), N>>;
+
+/// Pair of lanes, one per direction, holding at most N packets each
+pub struct VirtualCable<const N: usize> {
+    lanes: [Lane<N>; 2],
+    latency: u64,
+    capacity: usize,
+    clock: Option<fn() -> u64>,
+}
+
+impl<const N: usize> Default for VirtualCable<N> {
+    fn default() -> Self {
+        VirtualCable {
+            lanes: [SpinMutex::new(Deque::new()), SpinMutex::new(Deque::new())],
+            latency: 0,
+            capacity: N,
+            clock: None,
+        }
+    }
+}
+
+impl<const N: usize> VirtualCable<N> {
+    /// Clock used to delay packets, e.g. millis since boot
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Delay before transmitted packets can be received, in clock units
+    /// Ignored if cable has no clock
+    pub fn with_latency(mut self, latency: u64) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Max packets in flight per direction, up to N
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.min(N);
+        self
+    }
+
+    /// Both ends of the cable
+    pub fn ends(&self) -> (VirtualPort<'_, N>, VirtualPort<'_, N>) {
+        (VirtualPort::new(self, 0), VirtualPort::new(self, 1))
+    }
+
+    /// Packets in flight in both directions
+    pub fn in_flight(&self) -> usize {
+        self.lanes.iter().map(|lane| lane.lock().len()).sum()
+    }
+
+    fn now(&self) -> u64 {
+        self.clock.map_or(0, |clock| clock())
+    }
+}
+
+/// One end of a `VirtualCable`
+pub struct VirtualPort<'a, const N: usize> {
+    cable: &'a VirtualCable<N>,
+    side: usize,
+    // registry (In, Out) ports bridged with `sync()`
+    handles: Option<(PortHandle, PortHandle)>,
+    stats: PortStats,
+}
+
+impl<'a, const N: usize> VirtualPort<'a, N> {
+    fn new(cable: &'a VirtualCable<N>, side: usize) -> Self {
+        VirtualPort { cable, side, handles: None, stats: PortStats::default() }
+    }
+
+    fn inbound(&self) -> &'a Lane<N> {
+        &self.cable.lanes[self.side]
+    }
+
+    fn outbound(&self) -> &'a Lane<N> {
+        &self.cable.lanes[1 - self.side]
+    }
+
+    pub fn stats(&self) -> PortStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) -> PortStats {
+        self.stats.take()
+    }
+
+    /// Acquire an `In` and an `Out` port in registry, both using info's id and name
+    /// Returns (In, Out) handles
+    pub fn register(&mut self, ports: &impl MidiPorts, info: PortInfo) -> Result<(PortHandle, PortHandle), MidiError> {
+        let port_in = ports.acquire_port(PortInfo { direction: PortDirection::In, ..info })?;
+        let port_out = match ports.acquire_port(PortInfo { direction: PortDirection::Out, ..info }) {
+            Ok(handle) => handle,
+            Err(err) => {
+                ports.release_port(&port_in);
+                return Err(err);
+            }
+        };
+        self.handles = Some((port_in, port_out));
+        Ok((port_in, port_out))
+    }
+
+    /// Move received packets to registry `In` port and registry `Out` port packets to cable
+    /// Stops when either side is full, remaining packets are moved on next sync
+    pub fn sync(&mut self, ports: &impl MidiPorts) -> Result<(), MidiError> {
+        let (port_in, port_out) = self.handles.ok_or(MidiError::InvalidPort)?;
+        while ports.space(&port_in)? > 0 {
+            match self.receive()? {
+                Some(packet) => ports.write(&port_in, packet)?,
+                None => break,
+            }
+        }
+        while self.outbound().lock().len() < self.cable.capacity {
+            match ports.read(&port_out)? {
+                Some(packet) => self.transmit(PacketList::single(packet))?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, const N: usize> Receive for VirtualPort<'a, N> {
+    /// Packets still delayed by latency are not received
+    fn receive(&mut self) -> Result<Option<Packet>, MidiError> {
+        let now = self.cable.now();
+        let mut lane = self.inbound().lock();
+        match lane.front() {
+            Some((_, due)) if *due <= now => {}
+            _ => return Ok(None),
+        }
+        let packet = lane.pop_front().map(|(packet, _)| packet);
+        if let Some(packet) = &packet {
+            self.stats.packet_in(packet);
+            if self.cable.clock.is_some() {
+                self.stats.touch(now);
+            }
+        }
+        Ok(packet)
+    }
+}
+
+impl<'a, const N: usize> Transmit for VirtualPort<'a, N> {
+    /// Packets beyond cable capacity are dropped and Err(BufferFull) is returned
+    fn transmit(&mut self, packets: PacketList) -> Result<(), MidiError> {
+        let now = self.cable.now();
+        let due = now.saturating_add(self.cable.latency);
+        let mut lane = self.outbound().lock();
+        for packet in packets.iter() {
+            if lane.len() >= self.cable.capacity || lane.push_back((*packet, due)).is_err() {
+                self.stats.error(&MidiError::BufferFull);
+                return Err(MidiError::BufferFull);
+            }
+            self.stats.packet_out(packet);
+            self.stats.depth(lane.len());
+        }
+        if self.cable.clock.is_some() && !packets.is_empty() {
+            self.stats.touch(now);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use crate::{channel, MidiMessage, MidiRegistry, Note, PortId, U7};
+
+    fn note_on(note: Note) -> Packet {
+        Packet::from(MidiMessage::NoteOn(channel(1), note, U7(100)))
+    }
+
+    #[test]
+    fn loopback() {
+        let cable: VirtualCable<8> = VirtualCable::default().with_capacity(2);
+        let (mut a, mut b) = cable.ends();
+        a.transmit(PacketList::single(note_on(Note::C4))).unwrap();
+        b.transmit(PacketList::single(note_on(Note::D4))).unwrap();
+        assert_eq!(2, cable.in_flight());
+        assert_eq!(Some(note_on(Note::C4)), b.receive().unwrap());
+        assert_eq!(Some(note_on(Note::D4)), a.receive().unwrap());
+        assert_eq!(None, a.receive().unwrap());
+
+        let packets = PacketList::from_iter([note_on(Note::C4), note_on(Note::D4), note_on(Note::E4)]);
+        assert_eq!(Err(MidiError::BufferFull), a.transmit(packets));
+        assert_eq!(1, a.stats().dropped);
+        assert_eq!(3, a.stats().packets_out);
+        assert_eq!(2, cable.in_flight());
+    }
+
+    #[test]
+    fn latency() {
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        fn now() -> u64 { NOW.load(Ordering::Relaxed) }
+
+        let cable: VirtualCable<8> = VirtualCable::default().with_clock(now).with_latency(5);
+        let (mut a, mut b) = cable.ends();
+        a.transmit(PacketList::single(note_on(Note::C4))).unwrap();
+        NOW.store(4, Ordering::Relaxed);
+        assert_eq!(None, b.receive().unwrap());
+        NOW.store(5, Ordering::Relaxed);
+        assert_eq!(Some(note_on(Note::C4)), b.receive().unwrap());
+        assert_eq!(Some(5), b.stats().last_activity);
+    }
+
+    #[test]
+    fn registry() {
+        let registry: MidiRegistry<4> = MidiRegistry::default();
+        let cable: VirtualCable<8> = VirtualCable::default();
+        let (mut device, mut synth) = cable.ends();
+        let (port_in, port_out) = synth.register(&registry, PortInfo::new(PortId::Serial(2), PortDirection::In).with_name("Synth")).unwrap();
+        assert_eq!(Some(port_in), registry.find_port(PortId::Serial(2), PortDirection::In));
+        assert_eq!(Some(port_out), registry.find_port(PortId::Serial(2), PortDirection::Out));
+
+        device.transmit(PacketList::single(note_on(Note::C4))).unwrap();
+        registry.write(&port_out, note_on(Note::D4)).unwrap();
+        synth.sync(&registry).unwrap();
+        assert_eq!(Some(note_on(Note::C4)), registry.read(&port_in).unwrap());
+        assert_eq!(Some(note_on(Note::D4)), device.receive().unwrap());
+    }
+}
@@ -4,8 +4,8 @@ use CodeIndexNumber::{SystemCommonLen1, SystemCommonLen2, SystemCommonLen3};
 use crate::{MidiChannel, Note, Velocity, Pressure, Program, Control, U7, Bend, CodeIndexNumber, Packet, Status, MidiError, Cull};
 use crate::status::{SYSEX_END, is_non_status, SYSEX_START};
 
-#[derive(Clone, Copy, Debug)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+/// Formats and parses as text, see `text` module
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[allow(unused)]
 pub enum MidiMessage {
     NoteOff(MidiChannel, Note, Velocity),
@@ -106,7 +106,7 @@ impl TryFrom<Packet> for MidiMessage {
             (SystemCommonLen2, Some(Status::TimeCodeQuarterFrame), _, payload) => Ok(TimeCodeQuarterFrame(U7::cull(payload[1]))),
             (SystemCommonLen2, Some(Status::SongSelect), _, payload) => Ok(SongSelect(U7::cull(payload[1]))),
             (SystemCommonLen2, Some(Status::MeasureEnd), _, payload) => Ok(MeasureEnd(U7::cull(payload[1]))),
-            (SystemCommonLen3, Some(Status::SystemReset), _, payload) => Ok(SongPositionPointer(U7::cull(payload[1]), U7::cull(payload[1]))),
+            (SystemCommonLen3, Some(Status::SongPositionPointer), _, payload) => Ok(SongPositionPointer(U7::cull(payload[1]), U7::cull(payload[2]))),
 
             (_, Some(Status::NoteOff), Some(channel), payload) => Ok(NoteOff(channel, Note::try_from(payload[1])?, Velocity::try_from(payload[2])?)),
             (_, Some(Status::NoteOn), Some(channel), payload) => Ok(NoteOn(channel, Note::try_from(payload[1])?, Velocity::try_from(payload[2])?)),
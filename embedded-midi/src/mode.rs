@@ -0,0 +1,149 @@
+//! Channel Mode messages
+//! Control Changes 120 to 127 are not controllers but commands to the receiving channel.
+
+use core::convert::TryFrom;
+
+use crate::{Control, MidiChannel, MidiError, MidiMessage, Packet, U7};
+
+pub const CC_ALL_SOUND_OFF: Control = U7(120);
+pub const CC_RESET_ALL_CONTROLLERS: Control = U7(121);
+pub const CC_LOCAL_CONTROL: Control = U7(122);
+pub const CC_ALL_NOTES_OFF: Control = U7(123);
+pub const CC_OMNI_OFF: Control = U7(124);
+pub const CC_OMNI_ON: Control = U7(125);
+pub const CC_MONO_ON: Control = U7(126);
+pub const CC_POLY_ON: Control = U7(127);
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChannelMode {
+    /// Mute immediately, ignoring release and sustain
+    AllSoundOff,
+    ResetAllControllers,
+    /// Connect or disconnect the device's keyboard from its sound engine
+    LocalControl(bool),
+    /// Release all notes, sustain pedal still applies
+    AllNotesOff,
+    OmniOff,
+    OmniOn,
+    /// Number of channels to use, 0 for as many channels as voices
+    MonoOn(u8),
+    PolyOn,
+}
+
+impl ChannelMode {
+    /// True if control number is a Channel Mode message rather than a controller
+    pub fn is_mode_control(control: Control) -> bool {
+        control.0 >= CC_ALL_SOUND_OFF.0
+    }
+
+    /// Parse Channel Mode from Control Change, values of commands that take none are ignored
+    pub fn from_control(control: Control, value: U7) -> Option<Self> {
+        Some(match control {
+            CC_ALL_SOUND_OFF => ChannelMode::AllSoundOff,
+            CC_RESET_ALL_CONTROLLERS => ChannelMode::ResetAllControllers,
+            CC_LOCAL_CONTROL => ChannelMode::LocalControl(value.0 != 0),
+            CC_ALL_NOTES_OFF => ChannelMode::AllNotesOff,
+            CC_OMNI_OFF => ChannelMode::OmniOff,
+            CC_OMNI_ON => ChannelMode::OmniOn,
+            CC_MONO_ON => ChannelMode::MonoOn(value.0.min(16)),
+            CC_POLY_ON => ChannelMode::PolyOn,
+            _ => return None,
+        })
+    }
+
+    /// Control number and value of the Control Change
+    pub fn to_control(&self) -> (Control, U7) {
+        match *self {
+            ChannelMode::AllSoundOff => (CC_ALL_SOUND_OFF, U7::MIN),
+            ChannelMode::ResetAllControllers => (CC_RESET_ALL_CONTROLLERS, U7::MIN),
+            ChannelMode::LocalControl(on) => (CC_LOCAL_CONTROL, if on { U7::MAX } else { U7::MIN }),
+            ChannelMode::AllNotesOff => (CC_ALL_NOTES_OFF, U7::MIN),
+            ChannelMode::OmniOff => (CC_OMNI_OFF, U7::MIN),
+            ChannelMode::OmniOn => (CC_OMNI_ON, U7::MIN),
+            ChannelMode::MonoOn(channels) => (CC_MONO_ON, U7(channels.min(16))),
+            ChannelMode::PolyOn => (CC_POLY_ON, U7::MIN),
+        }
+    }
+
+    /// Turns all notes off on the receiving channel, as required by the spec for mode changes
+    pub fn is_notes_off(&self) -> bool {
+        !matches!(self, ChannelMode::ResetAllControllers | ChannelMode::LocalControl(_))
+    }
+
+    pub fn to_message(&self, channel: MidiChannel) -> MidiMessage {
+        let (control, value) = self.to_control();
+        MidiMessage::ControlChange(channel, control, value)
+    }
+}
+
+impl MidiMessage {
+    /// Channel and mode if message is a Channel Mode message
+    pub fn channel_mode(&self) -> Option<(MidiChannel, ChannelMode)> {
+        match *self {
+            MidiMessage::ControlChange(channel, control, value) => Some((channel, ChannelMode::from_control(control, value)?)),
+            _ => None,
+        }
+    }
+}
+
+impl From<(MidiChannel, ChannelMode)> for MidiMessage {
+    fn from((channel, mode): (MidiChannel, ChannelMode)) -> Self {
+        mode.to_message(channel)
+    }
+}
+
+impl From<(MidiChannel, ChannelMode)> for Packet {
+    fn from(mode: (MidiChannel, ChannelMode)) -> Self {
+        Packet::from(MidiMessage::from(mode))
+    }
+}
+
+impl TryFrom<MidiMessage> for (MidiChannel, ChannelMode) {
+    type Error = MidiError;
+
+    fn try_from(message: MidiMessage) -> Result<Self, Self::Error> {
+        message.channel_mode().ok_or(MidiError::InvalidControl)
+    }
+}
+
+impl TryFrom<Packet> for (MidiChannel, ChannelMode) {
+    type Error = MidiError;
+
+    fn try_from(packet: Packet) -> Result<Self, Self::Error> {
+        MidiMessage::try_from(packet)?.channel_mode().ok_or(MidiError::BadPacket(packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    #[test]
+    fn packet_round_trip() {
+        let modes = [
+            ChannelMode::AllSoundOff, ChannelMode::ResetAllControllers,
+            ChannelMode::LocalControl(false), ChannelMode::LocalControl(true),
+            ChannelMode::AllNotesOff, ChannelMode::OmniOff, ChannelMode::OmniOn,
+            ChannelMode::MonoOn(4), ChannelMode::PolyOn,
+        ];
+        for mode in modes {
+            let packet = Packet::from((channel(3), mode));
+            assert_eq!(Ok((channel(3), mode)), <(MidiChannel, ChannelMode)>::try_from(packet));
+        }
+        assert_eq!(&[0x0B, 0xB0, 0x7A, 0x7F], Packet::from((channel(1), ChannelMode::LocalControl(true))).bytes());
+    }
+
+    #[test]
+    fn not_channel_mode() {
+        let volume = MidiMessage::ControlChange(channel(1), U7(7), U7(100));
+        assert_eq!(None, volume.channel_mode());
+        assert_eq!(Err(MidiError::InvalidControl), <(MidiChannel, ChannelMode)>::try_from(volume));
+        let note = Packet::from(MidiMessage::NoteOn(channel(1), crate::Note::C4, U7(1)));
+        assert_eq!(Err(MidiError::BadPacket(note)), <(MidiChannel, ChannelMode)>::try_from(note));
+        assert!(!ChannelMode::is_mode_control(U7(119)));
+        assert!(ChannelMode::AllSoundOff.is_notes_off());
+        assert!(!ChannelMode::LocalControl(false).is_notes_off());
+    }
+}
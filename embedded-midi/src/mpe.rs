@@ -0,0 +1,457 @@
+//! MIDI Polyphonic Expression (MPE)
+//! Each sounding note gets its own member channel, so pitch bend, channel pressure and CC74 (timbre)
+//! apply to that note only. Zone-wide messages go to the manager channel: channel 1 for the lower zone,
+//! channel 16 for the upper zone. Zones are configured with RPN 6 sent to the manager channel.
+
+use heapless::Vec;
+
+use crate::nrpn::{ParamDecoder, ParamEncoder, ParamEvent, ParamValue};
+use crate::{Bend, MidiChannel, MidiMessage, Note, PacketList, Pressure, U14, U7};
+
+/// MPE Configuration Message parameter number
+pub const RPN_MPE_CONFIGURATION: U14 = U14(6);
+
+/// Timbre, third dimension of MPE expression
+pub const CC_TIMBRE: U7 = U7(74);
+
+const LOWER_MANAGER: u8 = 0;
+const UPPER_MANAGER: u8 = 15;
+
+/// Max number of member channels in a zone
+pub const MAX_MEMBERS: u8 = 15;
+
+/// Max number of notes tracked by the allocator and collapser
+const MAX_NOTES: usize = 32;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Zone {
+    /// Manager on channel 1, members from channel 2 up
+    Lower,
+    /// Manager on channel 16, members from channel 15 down
+    Upper,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ZoneConfig {
+    pub zone: Zone,
+    /// Zero disables the zone
+    pub members: u8,
+}
+
+impl ZoneConfig {
+    pub fn new(zone: Zone, members: u8) -> Self {
+        ZoneConfig { zone, members: members.min(MAX_MEMBERS) }
+    }
+
+    pub fn manager_channel(&self) -> MidiChannel {
+        match self.zone {
+            Zone::Lower => MidiChannel(LOWER_MANAGER),
+            Zone::Upper => MidiChannel(UPPER_MANAGER),
+        }
+    }
+
+    /// Member channels, nearest to manager channel first
+    pub fn member_channels(&self) -> impl Iterator<Item=MidiChannel> {
+        let zone = self.zone;
+        (1..=self.members).map(move |i| match zone {
+            Zone::Lower => MidiChannel(LOWER_MANAGER + i),
+            Zone::Upper => MidiChannel(UPPER_MANAGER - i),
+        })
+    }
+
+    pub fn is_member(&self, channel: MidiChannel) -> bool {
+        match self.zone {
+            Zone::Lower => channel.0 > LOWER_MANAGER && channel.0 <= LOWER_MANAGER + self.members,
+            Zone::Upper => channel.0 < UPPER_MANAGER && channel.0 >= UPPER_MANAGER - self.members,
+        }
+    }
+
+    /// True if channel is the manager or a member channel of this zone
+    pub fn contains(&self, channel: MidiChannel) -> bool {
+        self.members > 0 && (channel == self.manager_channel() || self.is_member(channel))
+    }
+
+    /// MPE Configuration Message, as an RPN event
+    pub fn to_param_event(&self) -> ParamEvent {
+        let value = U14::from((U7::MIN, U7(self.members)));
+        ParamEvent::Rpn(self.manager_channel(), RPN_MPE_CONFIGURATION, ParamValue::Absolute(value))
+    }
+
+    /// MPE Configuration Message, as RPN Control Changes
+    pub fn to_packets(&self) -> PacketList {
+        ParamEncoder::default().encode(&self.to_param_event())
+    }
+
+    /// Parse MPE Configuration Message from RPN event, use `ParamDecoder` to get events from messages
+    pub fn from_param_event(event: &ParamEvent) -> Option<Self> {
+        match *event {
+            ParamEvent::Rpn(channel, RPN_MPE_CONFIGURATION, ParamValue::Absolute(value)) => {
+                let (_lsb, msb): (U7, U7) = value.into();
+                let zone = match channel.0 {
+                    LOWER_MANAGER => Zone::Lower,
+                    UPPER_MANAGER => Zone::Upper,
+                    _ => return None,
+                };
+                Some(ZoneConfig::new(zone, msb.0))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Lower and upper zones sharing the 16 channels
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MpeLayout {
+    pub lower: ZoneConfig,
+    pub upper: ZoneConfig,
+}
+
+impl Default for MpeLayout {
+    /// No MPE, both zones disabled
+    fn default() -> Self {
+        MpeLayout {
+            lower: ZoneConfig::new(Zone::Lower, 0),
+            upper: ZoneConfig::new(Zone::Upper, 0),
+        }
+    }
+}
+
+impl MpeLayout {
+    /// Apply zone configuration, shrinking the other zone if they overlap
+    pub fn configure(&mut self, config: ZoneConfig) {
+        // both manager channels are always reserved
+        let available = MAX_MEMBERS - 1;
+        match config.zone {
+            Zone::Lower => {
+                self.lower = config;
+                self.upper.members = self.upper.members.min(available.saturating_sub(config.members));
+            }
+            Zone::Upper => {
+                self.upper = config;
+                self.lower.members = self.lower.members.min(available.saturating_sub(config.members));
+            }
+        }
+    }
+
+    /// Zone of channel, if any
+    pub fn zone_of(&self, channel: MidiChannel) -> Option<ZoneConfig> {
+        [self.lower, self.upper].into_iter().find(|zone| zone.contains(channel))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct MemberState {
+    notes: u8,
+    last_used: u32,
+}
+
+/// Assigns a member channel to each new note, to drive an MPE synth from a non-MPE source
+/// Channels without sounding notes are used first, least recently used first
+#[derive(Debug)]
+pub struct ChannelAllocator {
+    zone: ZoneConfig,
+    members: [MemberState; 16],
+    notes: Vec<(Note, MidiChannel), MAX_NOTES>,
+    counter: u32,
+}
+
+impl ChannelAllocator {
+    pub fn new(zone: ZoneConfig) -> Self {
+        ChannelAllocator {
+            zone,
+            members: [MemberState::default(); 16],
+            notes: Vec::new(),
+            counter: 0,
+        }
+    }
+
+    pub fn zone(&self) -> ZoneConfig {
+        self.zone
+    }
+
+    /// Member channel of sounding note
+    pub fn channel_of(&self, note: Note) -> Option<MidiChannel> {
+        self.notes.iter().rev().find(|(n, _)| *n == note).map(|(_, ch)| *ch)
+    }
+
+    fn allocate(&mut self) -> MidiChannel {
+        let members = &self.members;
+        let channel = self.zone.member_channels()
+            .min_by_key(|ch| {
+                let state = members[ch.0 as usize];
+                (state.notes, state.last_used)
+            })
+            .unwrap_or(self.zone.manager_channel());
+        self.counter = self.counter.wrapping_add(1);
+        let state = &mut self.members[channel.0 as usize];
+        state.notes = state.notes.saturating_add(1);
+        state.last_used = self.counter;
+        channel
+    }
+
+    /// Start note on a member channel
+    /// Per-note expression should be sent before the note on, using `channel_of()`
+    /// Returns the note off of the note being retriggered or stolen to make room, to send before the note on
+    pub fn note_on(&mut self, note: Note, velocity: U7) -> (Option<MidiMessage>, MidiMessage) {
+        let released = match self.notes.iter().position(|(n, _)| *n == note) {
+            // retriggered note
+            Some(idx) => Some(self.notes.remove(idx)),
+            // oldest note
+            None if self.notes.is_full() => Some(self.notes.remove(0)),
+            None => None,
+        };
+        let note_off = released.map(|(old_note, old_channel)| {
+            self.release(old_channel);
+            MidiMessage::NoteOff(old_channel, old_note, U7::MIN)
+        });
+        let channel = self.allocate();
+        let _ = self.notes.push((note, channel));
+        (note_off, MidiMessage::NoteOn(channel, note, velocity))
+    }
+
+    /// Stop note on its member channel, None if note was not sounding
+    pub fn note_off(&mut self, note: Note, velocity: U7) -> Option<MidiMessage> {
+        let idx = self.notes.iter().position(|(n, _)| *n == note)?;
+        let (_, channel) = self.notes.remove(idx);
+        self.release(channel);
+        Some(MidiMessage::NoteOff(channel, note, velocity))
+    }
+
+    fn release(&mut self, channel: MidiChannel) {
+        let state = &mut self.members[channel.0 as usize];
+        state.notes = state.notes.saturating_sub(1);
+    }
+
+    pub fn pitch_bend(&self, note: Note, bend: Bend) -> Option<MidiMessage> {
+        Some(MidiMessage::PitchBend(self.channel_of(note)?, bend))
+    }
+
+    pub fn pressure(&self, note: Note, pressure: Pressure) -> Option<MidiMessage> {
+        Some(MidiMessage::ChannelPressure(self.channel_of(note)?, pressure))
+    }
+
+    pub fn timbre(&self, note: Note, value: U7) -> Option<MidiMessage> {
+        Some(MidiMessage::ControlChange(self.channel_of(note)?, CC_TIMBRE, value))
+    }
+
+    /// Convert single channel input to MPE output
+    /// Notes are allocated member channels, poly pressure becomes member channel pressure,
+    /// other channel messages go to the manager channel and system messages are unchanged
+    /// A note on may be preceded by the note off of a retriggered or stolen note
+    pub fn map(&mut self, message: &MidiMessage) -> Vec<MidiMessage, 2> {
+        let manager = self.zone.manager_channel();
+        let mapped = match *message {
+            MidiMessage::NoteOn(_, note, U7(0)) => self.note_off(note, U7::MIN),
+            MidiMessage::NoteOn(_, note, velocity) => {
+                let (note_off, note_on) = self.note_on(note, velocity);
+                return note_off.into_iter().chain(Some(note_on)).collect();
+            }
+            MidiMessage::NoteOff(_, note, velocity) => self.note_off(note, velocity),
+            MidiMessage::NotePressure(_, note, pressure) => self.pressure(note, pressure),
+            MidiMessage::ChannelPressure(_, pressure) => Some(MidiMessage::ChannelPressure(manager, pressure)),
+            MidiMessage::ProgramChange(_, program) => Some(MidiMessage::ProgramChange(manager, program)),
+            MidiMessage::ControlChange(_, cc, value) => Some(MidiMessage::ControlChange(manager, cc, value)),
+            MidiMessage::PitchBend(_, bend) => Some(MidiMessage::PitchBend(manager, bend)),
+            other => Some(other),
+        };
+        mapped.into_iter().collect()
+    }
+}
+
+/// Merges MPE input into a single channel, for non-MPE synths
+/// Per-note expression of the most recent sounding note is followed, like a mono synth with last note priority
+#[derive(Debug)]
+pub struct MpeCollapser {
+    layout: MpeLayout,
+    output: MidiChannel,
+    // sounding notes and their channel, most recent last
+    notes: Vec<(Note, MidiChannel), MAX_NOTES>,
+    params: ParamDecoder,
+}
+
+impl MpeCollapser {
+    pub fn new(layout: MpeLayout, output: MidiChannel) -> Self {
+        MpeCollapser { layout, output, notes: Vec::new(), params: ParamDecoder::default() }
+    }
+
+    pub fn layout(&self) -> MpeLayout {
+        self.layout
+    }
+
+    fn latest_channel(&self) -> Option<MidiChannel> {
+        self.notes.last().map(|(_, ch)| *ch)
+    }
+
+    /// Convert MPE input to single channel output
+    /// Returns None for messages that should be dropped, e.g. expression of notes that are not the latest
+    /// MPE Configuration Messages received update the layout
+    pub fn map(&mut self, message: &MidiMessage) -> Option<MidiMessage> {
+        if let Some(config) = self.params.advance(message).as_ref().and_then(ZoneConfig::from_param_event) {
+            self.layout.configure(config);
+        }
+        let channel = match *message {
+            MidiMessage::NoteOff(ch, ..) | MidiMessage::NoteOn(ch, ..) | MidiMessage::NotePressure(ch, ..)
+            | MidiMessage::ChannelPressure(ch, _) | MidiMessage::ProgramChange(ch, _)
+            | MidiMessage::ControlChange(ch, ..) | MidiMessage::PitchBend(ch, _) => ch,
+            other => return Some(other),
+        };
+        let zone = match self.layout.zone_of(channel) {
+            Some(zone) => zone,
+            // not MPE, pass through as is
+            None => return Some(*message),
+        };
+        let out = self.output;
+        if channel == zone.manager_channel() {
+            return Some(with_channel(message, out));
+        }
+        match *message {
+            MidiMessage::NoteOn(ch, note, velocity) if velocity.0 > 0 => {
+                self.remove(note);
+                if self.notes.is_full() {
+                    self.notes.remove(0);
+                }
+                let _ = self.notes.push((note, ch));
+                Some(MidiMessage::NoteOn(out, note, velocity))
+            }
+            MidiMessage::NoteOn(_, note, _) | MidiMessage::NoteOff(_, note, _) => {
+                self.remove(note);
+                Some(with_channel(message, out))
+            }
+            // expression follows latest note only
+            _ if self.latest_channel() == Some(channel) => Some(with_channel(message, out)),
+            // expression sent just before note on
+            _ if self.notes.is_empty() => Some(with_channel(message, out)),
+            _ => None,
+        }
+    }
+
+    fn remove(&mut self, note: Note) {
+        if let Some(idx) = self.notes.iter().position(|(n, _)| *n == note) {
+            self.notes.remove(idx);
+        }
+    }
+}
+
+fn with_channel(message: &MidiMessage, channel: MidiChannel) -> MidiMessage {
+    match *message {
+        MidiMessage::NoteOff(_, note, velocity) => MidiMessage::NoteOff(channel, note, velocity),
+        MidiMessage::NoteOn(_, note, velocity) => MidiMessage::NoteOn(channel, note, velocity),
+        MidiMessage::NotePressure(_, note, pressure) => MidiMessage::NotePressure(channel, note, pressure),
+        MidiMessage::ChannelPressure(_, pressure) => MidiMessage::ChannelPressure(channel, pressure),
+        MidiMessage::ProgramChange(_, program) => MidiMessage::ProgramChange(channel, program),
+        MidiMessage::ControlChange(_, cc, value) => MidiMessage::ControlChange(channel, cc, value),
+        MidiMessage::PitchBend(_, bend) => MidiMessage::PitchBend(channel, bend),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+    use crate::nrpn::ParamDecoder;
+    use core::convert::TryFrom;
+
+    #[test]
+    fn configuration_message() {
+        let config = ZoneConfig::new(Zone::Upper, 7);
+        let packets = config.to_packets();
+        assert_eq!(3, packets.len());
+        let mut decoder = ParamDecoder::default();
+        let event = packets.iter()
+            .filter_map(|p| decoder.advance(&MidiMessage::try_from(*p).unwrap()))
+            .last()
+            .unwrap();
+        assert_eq!(Some(config), ZoneConfig::from_param_event(&event));
+        assert_eq!(Some(channel(15)), config.member_channels().next());
+        assert!(config.contains(channel(9)));
+        assert!(!config.contains(channel(8)));
+    }
+
+    #[test]
+    fn layout_overlap() {
+        let mut layout = MpeLayout::default();
+        layout.configure(ZoneConfig::new(Zone::Lower, 15));
+        assert_eq!(15, layout.lower.members);
+        layout.configure(ZoneConfig::new(Zone::Upper, 4));
+        assert_eq!(10, layout.lower.members);
+        assert_eq!(Some(layout.upper), layout.zone_of(channel(12)));
+        assert_eq!(Some(layout.lower), layout.zone_of(channel(11)));
+    }
+
+    #[test]
+    fn allocate_channels() {
+        let mut alloc = ChannelAllocator::new(ZoneConfig::new(Zone::Lower, 2));
+        assert_eq!((None, MidiMessage::NoteOn(channel(2), Note::C4, U7(100))), alloc.note_on(Note::C4, U7(100)));
+        assert_eq!((None, MidiMessage::NoteOn(channel(3), Note::E4, U7(100))), alloc.note_on(Note::E4, U7(100)));
+        // all channels busy, least recently used
+        assert_eq!((None, MidiMessage::NoteOn(channel(2), Note::G4, U7(100))), alloc.note_on(Note::G4, U7(100)));
+        assert_eq!(Some(MidiMessage::PitchBend(channel(3), U14(0x3000))), alloc.pitch_bend(Note::E4, U14(0x3000)));
+        assert_eq!(Some(MidiMessage::NoteOff(channel(3), Note::E4, U7(0))), alloc.note_off(Note::E4, U7(0)));
+        assert_eq!(None, alloc.note_off(Note::E4, U7(0)));
+        // free channel first
+        assert_eq!((None, MidiMessage::NoteOn(channel(3), Note::A4, U7(100))), alloc.note_on(Note::A4, U7(100)));
+
+        let cc = MidiMessage::ControlChange(channel(5), U7(7), U7(100));
+        assert_eq!(&[MidiMessage::ControlChange(channel(1), U7(7), U7(100))], alloc.map(&cc).as_slice());
+        let pressure = MidiMessage::NotePressure(channel(5), Note::A4, U7(20));
+        assert_eq!(&[MidiMessage::ChannelPressure(channel(3), U7(20))], alloc.map(&pressure).as_slice());
+        assert_eq!(Some(CC_TIMBRE), match alloc.timbre(Note::C4, U7(1)) {
+            Some(MidiMessage::ControlChange(_, cc, _)) => Some(cc),
+            _ => None,
+        });
+    }
+
+    #[test]
+    fn retrigger_releases_previous_channel() {
+        let mut alloc = ChannelAllocator::new(ZoneConfig::new(Zone::Lower, 3));
+        alloc.note_on(Note::C4, U7(100));
+        let retrigger = alloc.map(&MidiMessage::NoteOn(channel(1), Note::C4, U7(90)));
+        assert_eq!(&[
+            MidiMessage::NoteOff(channel(2), Note::C4, U7(0)),
+            MidiMessage::NoteOn(channel(3), Note::C4, U7(90)),
+        ], retrigger.as_slice());
+        assert_eq!(Some(MidiMessage::NoteOff(channel(3), Note::C4, U7(0))), alloc.note_off(Note::C4, U7(0)));
+        assert_eq!(None, alloc.note_off(Note::C4, U7(0)));
+    }
+
+    #[test]
+    fn steal_oldest_note() {
+        let mut alloc = ChannelAllocator::new(ZoneConfig::new(Zone::Lower, 15));
+        let mut notes = (0..).map(|n| Note::try_from(n).unwrap());
+        for note in notes.by_ref().take(MAX_NOTES) {
+            assert_eq!(None, alloc.note_on(note, U7(100)).0);
+        }
+        let (stolen, _) = alloc.note_on(notes.next().unwrap(), U7(100));
+        assert_eq!(Some(MidiMessage::NoteOff(channel(2), Note::try_from(0).unwrap(), U7(0))), stolen);
+        assert_eq!(None, alloc.channel_of(Note::try_from(0).unwrap()));
+    }
+
+    #[test]
+    fn collapse() {
+        let mut layout = MpeLayout::default();
+        layout.configure(ZoneConfig::new(Zone::Lower, 15));
+        let mut collapser = MpeCollapser::new(layout, channel(1));
+        let out = |c: &mut MpeCollapser, m: MidiMessage| c.map(&m);
+
+        assert_eq!(Some(MidiMessage::NoteOn(channel(1), Note::C4, U7(90))), out(&mut collapser, MidiMessage::NoteOn(channel(2), Note::C4, U7(90))));
+        assert_eq!(Some(MidiMessage::NoteOn(channel(1), Note::E4, U7(90))), out(&mut collapser, MidiMessage::NoteOn(channel(3), Note::E4, U7(90))));
+        // only latest note expression is followed
+        assert_eq!(None, out(&mut collapser, MidiMessage::PitchBend(channel(2), U14(0))));
+        assert_eq!(Some(MidiMessage::PitchBend(channel(1), U14(0))), out(&mut collapser, MidiMessage::PitchBend(channel(3), U14(0))));
+        assert_eq!(Some(MidiMessage::NoteOff(channel(1), Note::E4, U7(0))), out(&mut collapser, MidiMessage::NoteOff(channel(3), Note::E4, U7(0))));
+        assert_eq!(Some(MidiMessage::ChannelPressure(channel(1), U7(5))), out(&mut collapser, MidiMessage::ChannelPressure(channel(2), U7(5))));
+        // manager channel
+        assert_eq!(Some(MidiMessage::ControlChange(channel(1), U7(64), U7(127))), out(&mut collapser, MidiMessage::ControlChange(channel(1), U7(64), U7(127))));
+
+        // configuration message disables zone, channels pass through
+        for packet in ZoneConfig::new(Zone::Lower, 0).to_packets().iter() {
+            collapser.map(&MidiMessage::try_from(*packet).unwrap());
+        }
+        assert_eq!(0, collapser.layout().lower.members);
+        assert_eq!(Some(MidiMessage::NoteOn(channel(2), Note::C4, U7(90))), out(&mut collapser, MidiMessage::NoteOn(channel(2), Note::C4, U7(90))));
+    }
+}
@@ -0,0 +1,412 @@
+//! MIDI Time Code (MTC)
+//! A running SMPTE position is sent as 8 quarter frame messages, each carrying a nibble of the time.
+//! A complete time thus takes two frames to transmit, and locating is done with a full frame sysex.
+//! Timestamps are in milliseconds, from any monotonic source (e.g. `runtime::now_millis()`).
+
+use core::convert::TryFrom;
+
+use crate::status::{SYSEX_END, SYSEX_START};
+use crate::{MidiError, MidiMessage, U7};
+
+/// Number of quarter frame pieces in a complete time
+const PIECES: u8 = 8;
+
+/// A complete time spans this many frames
+const FRAMES_PER_CYCLE: i32 = 2;
+
+/// Universal Realtime sysex header
+const REALTIME_ID: u8 = 0x7F;
+/// Sub-IDs of MTC Full Frame message
+const SUB_ID_MTC: u8 = 0x01;
+const SUB_ID_FULL_FRAME: u8 = 0x01;
+
+/// "All call" device ID
+pub const ALL_DEVICES: u8 = 0x7F;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum FrameRate {
+    Fps24 = 0,
+    Fps25 = 1,
+    /// 29.97 fps, drop frame
+    Fps30Drop = 2,
+    Fps30 = 3,
+}
+
+impl TryFrom<u8> for FrameRate {
+    type Error = MidiError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => FrameRate::Fps24,
+            1 => FrameRate::Fps25,
+            2 => FrameRate::Fps30Drop,
+            3 => FrameRate::Fps30,
+            _ => return Err(MidiError::InvalidInteger),
+        })
+    }
+}
+
+impl FrameRate {
+    /// Nominal number of frames per second, as counted in timecode
+    pub fn fps(&self) -> u32 {
+        match self {
+            FrameRate::Fps24 => 24,
+            FrameRate::Fps25 => 25,
+            FrameRate::Fps30Drop | FrameRate::Fps30 => 30,
+        }
+    }
+
+    /// Real duration of a quarter frame, for generator scheduling
+    pub fn quarter_frame_micros(&self) -> u32 {
+        match self {
+            // 30000 / 1001 frames per second
+            FrameRate::Fps30Drop => 1_001_000 / (30 * 4),
+            _ => 1_000_000 / (self.fps() * 4),
+        }
+    }
+
+    /// Number of frames in 24 hours
+    pub fn frames_per_day(&self) -> u32 {
+        match self {
+            // 2 frames dropped every minute, except every tenth minute
+            FrameRate::Fps30Drop => 24 * 6 * (10 * 60 * 30 - 9 * 2),
+            _ => 24 * 60 * 60 * self.fps(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    Forward,
+    Reverse,
+}
+
+/// SMPTE time, hours:minutes:seconds:frames
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub rate: FrameRate,
+}
+
+impl Timecode {
+    pub fn zero(rate: FrameRate) -> Self {
+        Timecode { hours: 0, minutes: 0, seconds: 0, frames: 0, rate }
+    }
+
+    /// Absolute frame number since 00:00:00:00
+    pub fn to_frames(&self) -> u32 {
+        let fps = self.rate.fps();
+        let nominal = ((self.hours as u32 * 60 + self.minutes as u32) * 60 + self.seconds as u32) * fps + self.frames as u32;
+        match self.rate {
+            FrameRate::Fps30Drop => {
+                let minutes = self.hours as u32 * 60 + self.minutes as u32;
+                nominal - 2 * (minutes - minutes / 10)
+            }
+            _ => nominal,
+        }
+    }
+
+    /// Timecode of absolute frame number, wrapping around after 24 hours
+    pub fn from_frames(frames: u32, rate: FrameRate) -> Self {
+        let mut frames = frames % rate.frames_per_day();
+        if rate == FrameRate::Fps30Drop {
+            // add back dropped frame numbers
+            const FRAMES_PER_10_MIN: u32 = 10 * 60 * 30 - 9 * 2;
+            const FRAMES_PER_MIN: u32 = 60 * 30 - 2;
+            let tens = frames / FRAMES_PER_10_MIN;
+            let rem = frames % FRAMES_PER_10_MIN;
+            frames += 9 * 2 * tens;
+            if rem >= 2 {
+                frames += 2 * ((rem - 2) / FRAMES_PER_MIN);
+            }
+        }
+        let fps = rate.fps();
+        Timecode {
+            hours: (frames / (fps * 3600)) as u8,
+            minutes: (frames / (fps * 60) % 60) as u8,
+            seconds: (frames / fps % 60) as u8,
+            frames: (frames % fps) as u8,
+            rate,
+        }
+    }
+
+    /// Move forward or backward by a number of frames, wrapping around after 24 hours
+    pub fn add_frames(&self, delta: i32) -> Self {
+        let day = self.rate.frames_per_day() as i64;
+        let frames = (self.to_frames() as i64 + delta as i64).rem_euclid(day);
+        Timecode::from_frames(frames as u32, self.rate)
+    }
+
+    /// Nibble carried by quarter frame piece (0-7)
+    fn piece(&self, piece: u8) -> u8 {
+        match piece & 0x7 {
+            0 => self.frames & 0xF,
+            1 => (self.frames >> 4) & 0x1,
+            2 => self.seconds & 0xF,
+            3 => (self.seconds >> 4) & 0x3,
+            4 => self.minutes & 0xF,
+            5 => (self.minutes >> 4) & 0x3,
+            6 => self.hours & 0xF,
+            _ => ((self.hours >> 4) & 0x1) | ((self.rate as u8) << 1),
+        }
+    }
+
+    /// Assemble timecode from the 8 quarter frame nibbles
+    fn from_pieces(pieces: &[u8; PIECES as usize]) -> Self {
+        Timecode {
+            frames: pieces[0] | (pieces[1] & 0x1) << 4,
+            seconds: pieces[2] | (pieces[3] & 0x3) << 4,
+            minutes: pieces[4] | (pieces[5] & 0x3) << 4,
+            hours: pieces[6] | (pieces[7] & 0x1) << 4,
+            // 2 bits can only hold valid rates
+            rate: FrameRate::try_from((pieces[7] >> 1) & 0x3).unwrap_or(FrameRate::Fps24),
+        }
+    }
+
+    /// Full Frame sysex message, used to locate without running timecode
+    pub fn to_full_frame(&self, device_id: u8) -> [u8; 10] {
+        [
+            SYSEX_START, REALTIME_ID, device_id & 0x7F, SUB_ID_MTC, SUB_ID_FULL_FRAME,
+            (self.hours & 0x1F) | ((self.rate as u8) << 5),
+            self.minutes & 0x3F,
+            self.seconds & 0x3F,
+            self.frames & 0x1F,
+            SYSEX_END,
+        ]
+    }
+
+    /// Parse a complete Full Frame sysex message, for any device ID
+    pub fn from_full_frame(sysex: &[u8]) -> Option<Self> {
+        match *sysex {
+            [SYSEX_START, REALTIME_ID, _, SUB_ID_MTC, SUB_ID_FULL_FRAME, hr, mn, sc, fr, SYSEX_END] => Some(Timecode {
+                hours: hr & 0x1F,
+                minutes: mn & 0x3F,
+                seconds: sc & 0x3F,
+                frames: fr & 0x1F,
+                rate: FrameRate::try_from((hr >> 5) & 0x3).ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Assembles timecode from quarter frame messages, to chase an MTC source
+/// Pieces must arrive in sequence, a gap or a dropout restarts assembly
+#[derive(Debug)]
+pub struct MtcDecoder {
+    pieces: [u8; PIECES as usize],
+    // bit set for each piece received in the current sequence
+    received: u8,
+    last_piece: Option<u8>,
+    last_millis: u64,
+    dropout_millis: u64,
+    direction: Direction,
+    timecode: Option<Timecode>,
+}
+
+impl Default for MtcDecoder {
+    fn default() -> Self {
+        MtcDecoder {
+            pieces: [0; PIECES as usize],
+            received: 0,
+            last_piece: None,
+            last_millis: 0,
+            dropout_millis: 100,
+            direction: Direction::Forward,
+            timecode: None,
+        }
+    }
+}
+
+impl MtcDecoder {
+    /// Consider timecode stopped if no quarter frame is received for this long (default: 100ms)
+    pub fn with_dropout(mut self, millis: u64) -> Self {
+        self.dropout_millis = millis;
+        self
+    }
+
+    /// Feed a message to the decoder
+    /// Returns Some(timecode) when a complete time has been assembled, every two frames
+    /// The time is compensated for the two frames it took to transmit
+    pub fn advance(&mut self, message: &MidiMessage, now_millis: u64) -> Option<Timecode> {
+        let value = match *message {
+            MidiMessage::TimeCodeQuarterFrame(value) => value.0,
+            _ => return None,
+        };
+        let piece = value >> 4 & 0x7;
+
+        if now_millis.saturating_sub(self.last_millis) > self.dropout_millis {
+            self.received = 0;
+            self.last_piece = None;
+        }
+        self.last_millis = now_millis;
+
+        match self.last_piece {
+            Some(last) if piece == (last + 1) % PIECES => self.direction = Direction::Forward,
+            Some(last) if piece == (last + PIECES - 1) % PIECES => self.direction = Direction::Reverse,
+            // out of sequence, start over
+            Some(_) => self.received = 0,
+            None => {}
+        }
+        self.last_piece = Some(piece);
+        self.pieces[piece as usize] = value & 0xF;
+        self.received |= 1 << piece;
+
+        let last_of_cycle = match self.direction {
+            Direction::Forward => PIECES - 1,
+            Direction::Reverse => 0,
+        };
+        if self.received != 0xFF || piece != last_of_cycle {
+            return None;
+        }
+        self.received = 0;
+        let elapsed = match self.direction {
+            Direction::Forward => FRAMES_PER_CYCLE,
+            Direction::Reverse => -FRAMES_PER_CYCLE,
+        };
+        let timecode = Timecode::from_pieces(&self.pieces).add_frames(elapsed);
+        self.timecode = Some(timecode);
+        Some(timecode)
+    }
+
+    /// Locate to the time of a Full Frame sysex message
+    /// Returns the new time if the sysex was a valid Full Frame message
+    pub fn full_frame(&mut self, sysex: &[u8]) -> Option<Timecode> {
+        let timecode = Timecode::from_full_frame(sysex)?;
+        self.received = 0;
+        self.last_piece = None;
+        self.timecode = Some(timecode);
+        Some(timecode)
+    }
+
+    /// Last time assembled or located to
+    pub fn timecode(&self) -> Option<Timecode> {
+        self.timecode
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// True if quarter frames are still being received
+    pub fn is_running(&self, now_millis: u64) -> bool {
+        self.last_piece.is_some() && now_millis.saturating_sub(self.last_millis) <= self.dropout_millis
+    }
+}
+
+/// Produces quarter frame messages from a running position
+/// `next_quarter_frame()` should be called every `FrameRate::quarter_frame_micros()`
+#[derive(Debug)]
+pub struct MtcGenerator {
+    // time being sent, at piece 0
+    timecode: Timecode,
+    next_piece: u8,
+}
+
+impl MtcGenerator {
+    pub fn new(start: Timecode) -> Self {
+        MtcGenerator { timecode: start, next_piece: 0 }
+    }
+
+    /// Jump to another time, restarting quarter frame sequence
+    /// Receivers should also be sent the Full Frame message for the new time
+    pub fn locate(&mut self, timecode: Timecode) {
+        self.timecode = timecode;
+        self.next_piece = 0;
+    }
+
+    /// Time currently being sent
+    pub fn timecode(&self) -> Timecode {
+        self.timecode
+    }
+
+    /// Next quarter frame message
+    pub fn next_quarter_frame(&mut self) -> MidiMessage {
+        let piece = self.next_piece;
+        let message = MidiMessage::TimeCodeQuarterFrame(U7(piece << 4 | self.timecode.piece(piece)));
+        self.next_piece += 1;
+        if self.next_piece == PIECES {
+            self.next_piece = 0;
+            self.timecode = self.timecode.add_frames(FRAMES_PER_CYCLE);
+        }
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tc(hours: u8, minutes: u8, seconds: u8, frames: u8, rate: FrameRate) -> Timecode {
+        Timecode { hours, minutes, seconds, frames, rate }
+    }
+
+    #[test]
+    fn drop_frame_numbers() {
+        let rate = FrameRate::Fps30Drop;
+        assert_eq!(1800, tc(0, 1, 0, 2, rate).to_frames());
+        assert_eq!(tc(0, 1, 0, 2, rate), Timecode::from_frames(1800, rate));
+        assert_eq!(tc(0, 0, 59, 29, rate), Timecode::from_frames(1799, rate));
+        assert_eq!(tc(0, 10, 0, 0, rate), Timecode::from_frames(17982, rate));
+        assert_eq!(tc(0, 10, 0, 0, rate), tc(0, 9, 59, 29, rate).add_frames(1));
+        for n in [0, 1, 1799, 1800, 17981, 17982, 107891, 2589407] {
+            assert_eq!(n, Timecode::from_frames(n, rate).to_frames());
+        }
+        // wraparound
+        assert_eq!(tc(23, 59, 59, 23, FrameRate::Fps24), Timecode::zero(FrameRate::Fps24).add_frames(-1));
+    }
+
+    #[test]
+    fn generate_decode() {
+        let start = tc(1, 2, 3, 4, FrameRate::Fps25);
+        let mut generator = MtcGenerator::new(start);
+        let mut decoder = MtcDecoder::default();
+        for i in 0..7 {
+            assert_eq!(None, decoder.advance(&generator.next_quarter_frame(), i));
+        }
+        assert_eq!(Some(tc(1, 2, 3, 6, FrameRate::Fps25)), decoder.advance(&generator.next_quarter_frame(), 8));
+        assert_eq!(tc(1, 2, 3, 6, FrameRate::Fps25), generator.timecode());
+        assert!(decoder.is_running(10));
+        assert!(!decoder.is_running(1000));
+    }
+
+    #[test]
+    fn reverse_and_dropout() {
+        let start = tc(0, 0, 10, 0, FrameRate::Fps30);
+        let mut decoder = MtcDecoder::default();
+        for piece in (1..8).rev() {
+            let qf = MidiMessage::TimeCodeQuarterFrame(U7(piece << 4 | start.piece(piece)));
+            assert_eq!(None, decoder.advance(&qf, 0));
+        }
+        let qf = MidiMessage::TimeCodeQuarterFrame(U7(start.piece(0)));
+        assert_eq!(Some(tc(0, 0, 9, 28, FrameRate::Fps30)), decoder.advance(&qf, 0));
+        assert_eq!(Direction::Reverse, decoder.direction());
+
+        // pieces from before the dropout are discarded
+        let mut generator = MtcGenerator::new(start);
+        for _ in 0..4 {
+            decoder.advance(&generator.next_quarter_frame(), 0);
+        }
+        for _ in 0..4 {
+            assert_eq!(None, decoder.advance(&generator.next_quarter_frame(), 500));
+        }
+    }
+
+    #[test]
+    fn full_frame() {
+        let time = tc(10, 20, 30, 15, FrameRate::Fps30Drop);
+        let sysex = time.to_full_frame(ALL_DEVICES);
+        assert_eq!([0xF0, 0x7F, 0x7F, 0x01, 0x01, 0x4A, 20, 30, 15, 0xF7], sysex);
+        let mut decoder = MtcDecoder::default();
+        assert_eq!(Some(time), decoder.full_frame(&sysex));
+        assert_eq!(Some(time), decoder.timecode());
+        assert_eq!(None, decoder.full_frame(&sysex[1..]));
+    }
+}
@@ -3,11 +3,14 @@
 
 use crate::u7::U7;
 use crate::{Cull, MidiError};
+use crate::tuning::{frequency_note, note_frequency};
 
 use num_enum::UnsafeFromPrimitive;
 use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
 
-#[derive(Debug, Copy, Clone, UnsafeFromPrimitive)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, UnsafeFromPrimitive)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum Note {
@@ -147,10 +150,19 @@ impl TryFrom<u8> for Note {
     type Error = MidiError;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if value > Note::MAX as u8 {
+            return Err(MidiError::InvalidNote);
+        }
         Ok(unsafe {Note::unchecked_transmute_from(value)})
     }
 }
 
+impl From<Note> for u8 {
+    fn from(value: Note) -> u8 {
+        value as u8
+    }
+}
+
 impl From<Note> for U7 {
     fn from(value: Note) -> U7 {
         let byte = value as u8;
@@ -270,10 +282,202 @@ impl Note {
     pub const Ab9: Note = Note::Gs9;
 }
 
+pub(crate) const SHARP_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+const FLAT_NAMES: [&str; 12] = ["C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B"];
+
+/// Octave number of middle C (note 60), which varies between manufacturers
+/// Note variants are named using the C4 convention
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MiddleC {
+    /// Yamaha and some DAWs, lowest note is C-2
+    C3,
+    /// Scientific pitch notation, lowest note is C-1
+    #[default]
+    C4,
+}
+
+impl MiddleC {
+    fn octave_offset(&self) -> i8 {
+        match self {
+            MiddleC::C3 => -2,
+            MiddleC::C4 => -1,
+        }
+    }
+}
+
+impl Note {
+    /// Highest valid MIDI note
+    pub const MAX: Note = Note::G9;
+    pub const MIN: Note = Note::C1m;
+
+    /// Position of the note in its octave, 0 for C up to 11 for B
+    pub fn pitch_class(self) -> u8 {
+        u8::from(self) % 12
+    }
+
+    pub fn octave(self, middle_c: MiddleC) -> i8 {
+        (u8::from(self) / 12) as i8 + middle_c.octave_offset()
+    }
+
+    /// Returns None if transposed note would be out of range
+    pub fn checked_transpose(self, semitones: i8) -> Option<Note> {
+        let note = u8::from(self) as i16 + semitones as i16;
+        if (0..=Note::MAX as i16).contains(&note) {
+            Note::try_from(note as u8).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Transposed note is clamped to MIDI note range
+    pub fn saturating_transpose(self, semitones: i8) -> Note {
+        let note = (u8::from(self) as i16 + semitones as i16).clamp(0, Note::MAX as i16);
+        Note::try_from(note as u8).unwrap_or(Note::MAX)
+    }
+
+    /// Semitones from this note up to other note, negative if other note is lower
+    pub fn interval(self, other: Note) -> i8 {
+        (u8::from(other) as i16 - u8::from(self) as i16) as i8
+    }
+
+    /// Equal-tempered frequency, with A4 at 440Hz
+    pub fn frequency(self) -> f32 {
+        note_frequency(self, 0.0)
+    }
+
+    /// Nearest note to frequency, None if out of MIDI note range
+    pub fn from_frequency(hz: f32) -> Option<Note> {
+        frequency_note(hz).map(|(note, _cents)| note)
+    }
+
+    /// Name of the note for display, using sharps and the C4 convention by default
+    pub fn name(self) -> NoteName {
+        NoteName { note: self, middle_c: MiddleC::C4, flats: false }
+    }
+
+    /// Parse a note name like "C#4", "Bb3" or "C-1"
+    /// Accidentals are '#' and 'b', the note letter is case insensitive
+    pub fn parse(name: &str, middle_c: MiddleC) -> Result<Note, MidiError> {
+        let mut chars = name.trim().char_indices().peekable();
+        let pitch_class: i16 = match chars.next().map(|(_, c)| c.to_ascii_uppercase()) {
+            Some('C') => 0,
+            Some('D') => 2,
+            Some('E') => 4,
+            Some('F') => 5,
+            Some('G') => 7,
+            Some('A') => 9,
+            Some('B') => 11,
+            _ => return Err(MidiError::InvalidNote),
+        };
+        let accidental = match chars.peek() {
+            Some((_, '#')) => 1,
+            Some((_, 'b')) => -1,
+            _ => 0,
+        };
+        if accidental != 0 {
+            chars.next();
+        }
+        let octave_at = chars.peek().map(|(idx, _)| *idx).ok_or(MidiError::InvalidNote)?;
+        let octave = i8::from_str(&name.trim()[octave_at..]).map_err(|_| MidiError::InvalidNote)?;
+        let note = (octave as i16 - middle_c.octave_offset() as i16) * 12 + pitch_class + accidental;
+        if !(0..=Note::MAX as i16).contains(&note) {
+            return Err(MidiError::InvalidNote);
+        }
+        Note::try_from(note as u8)
+    }
+}
+
+/// Parses note names using the C4 convention
+impl FromStr for Note {
+    type Err = MidiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Note::parse(s, MiddleC::C4)
+    }
+}
+
+/// Displays note names using sharps and the C4 convention
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.name(), f)
+    }
+}
+
+/// Configurable note name formatting
+#[derive(Copy, Clone, Debug)]
+pub struct NoteName {
+    note: Note,
+    middle_c: MiddleC,
+    flats: bool,
+}
+
+impl NoteName {
+    pub fn with_middle_c(mut self, middle_c: MiddleC) -> Self {
+        self.middle_c = middle_c;
+        self
+    }
+
+    /// Name black keys as flats instead of sharps
+    pub fn with_flats(mut self, flats: bool) -> Self {
+        self.flats = flats;
+        self
+    }
+}
+
+impl fmt::Display for NoteName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = if self.flats { &FLAT_NAMES } else { &SHARP_NAMES };
+        write!(f, "{}{}", names[self.note.pitch_class() as usize], self.note.octave(self.middle_c))
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+
+    #[test]
+    fn note_names() {
+        extern crate std;
+        use std::string::ToString;
+
+        assert_eq!("C4", Note::C4.to_string());
+        assert_eq!("C#-1", Note::Cs1m.to_string());
+        assert_eq!("Bb3", Note::Bb3.name().with_flats(true).to_string());
+        assert_eq!("C3", Note::C4.name().with_middle_c(MiddleC::C3).to_string());
+
+        assert_eq!(Ok(Note::Cs4), "C#4".parse());
+        assert_eq!(Ok(Note::Bb3), "Bb3".parse());
+        assert_eq!(Ok(Note::C1m), "c-1".parse());
+        assert_eq!(Ok(Note::G9), "G9".parse());
+        assert_eq!(Ok(Note::C4), Note::parse("C3", MiddleC::C3));
+        assert!("G#9".parse::<Note>().is_err());
+        assert!("Cb-1".parse::<Note>().is_err());
+        assert!("H4".parse::<Note>().is_err());
+        assert!("C".parse::<Note>().is_err());
+    }
+
+    #[test]
+    fn note_arithmetic() {
+        assert_eq!(Some(Note::E4), Note::C4.checked_transpose(4));
+        assert_eq!(Some(Note::A3), Note::C4.checked_transpose(-3));
+        assert_eq!(None, Note::G9.checked_transpose(1));
+        assert_eq!(None, Note::C1m.checked_transpose(-1));
+        assert_eq!(Note::G9, Note::C9.saturating_transpose(127));
+        assert_eq!(Note::C1m, Note::C0.saturating_transpose(-128));
+        assert_eq!(7, Note::C4.interval(Note::G4));
+        assert_eq!(-12, Note::C4.interval(Note::C3));
+        assert_eq!(9, Note::A4.pitch_class());
+        assert_eq!(Err(MidiError::InvalidNote), Note::try_from(128));
+    }
+
+    #[test]
+    fn note_frequency() {
+        assert!((Note::A4.frequency() - 440.0).abs() < 0.01);
+        assert_eq!(Some(Note::A4), Note::from_frequency(450.0));
+        assert_eq!(Some(Note::C4), Note::from_frequency(261.6));
+    }
     macro_rules! note_test {
         ($($id:ident:$value:expr,)*) => {
             $(
@@ -0,0 +1,284 @@
+//! Registered (RPN) and Non-Registered (NRPN) Parameter Numbers
+//! Parameters are selected using CC 101/100 (RPN) or CC 99/98 (NRPN),
+//! then set using Data Entry CC 6/38 or nudged using Data Increment/Decrement CC 96/97.
+
+use core::iter::FromIterator;
+
+use heapless::Vec;
+
+use crate::{Control, MidiChannel, MidiMessage, Packet, PacketList, U14, U7};
+
+pub const CC_DATA_ENTRY_MSB: u8 = 6;
+pub const CC_DATA_ENTRY_LSB: u8 = 38;
+pub const CC_DATA_INCREMENT: u8 = 96;
+pub const CC_DATA_DECREMENT: u8 = 97;
+pub const CC_NRPN_LSB: u8 = 98;
+pub const CC_NRPN_MSB: u8 = 99;
+pub const CC_RPN_LSB: u8 = 100;
+pub const CC_RPN_MSB: u8 = 101;
+
+/// Selecting this RPN deselects any parameter, preventing accidental data entry
+pub const RPN_NULL: U14 = U14::MAX;
+
+/// Returns true if the controller is used to select or set RPN/NRPN parameters
+pub fn is_param_control(cc: Control) -> bool {
+    matches!(cc.0,
+        CC_DATA_ENTRY_MSB | CC_DATA_ENTRY_LSB | CC_DATA_INCREMENT | CC_DATA_DECREMENT
+        | CC_NRPN_LSB | CC_NRPN_MSB | CC_RPN_LSB | CC_RPN_MSB)
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParamValue {
+    /// Data Entry
+    Absolute(U14),
+    /// Data Increment, by amount
+    Increment(U7),
+    /// Data Decrement, by amount
+    Decrement(U7),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParamEvent {
+    /// Registered parameter - channel, parameter number, value
+    Rpn(MidiChannel, U14, ParamValue),
+    /// Non-Registered parameter - channel, parameter number, value
+    Nrpn(MidiChannel, U14, ParamValue),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum ParamKind {
+    Rpn,
+    Nrpn,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct ChannelParam {
+    // currently selected parameter
+    kind: Option<ParamKind>,
+    number_msb: U7,
+    number_lsb: U7,
+    data_msb: U7,
+}
+
+impl Default for ChannelParam {
+    fn default() -> Self {
+        ChannelParam {
+            kind: None,
+            number_msb: U7::MIN,
+            number_lsb: U7::MIN,
+            data_msb: U7::MIN,
+        }
+    }
+}
+
+/// Assembles RPN & NRPN events from the Control Change messages of all 16 channels
+#[derive(Debug, Default)]
+pub struct ParamDecoder {
+    channels: [ChannelParam; 16],
+    wait_for_lsb: bool,
+}
+
+impl ParamDecoder {
+    /// Only emit absolute values once Data Entry LSB (CC 38) is received (default: false)
+    /// By default, events are emitted on Data Entry MSB (CC 6) with the LSB reset to 0, then again on LSB
+    pub fn with_wait_for_lsb(mut self, wait_for_lsb: bool) -> Self {
+        self.wait_for_lsb = wait_for_lsb;
+        self
+    }
+
+    /// Feed a message to the decoder
+    /// Returns Some(event) when a parameter value was set
+    /// Messages other than parameter Control Changes are ignored
+    pub fn advance(&mut self, message: &MidiMessage) -> Option<ParamEvent> {
+        let (ch, cc, value) = match *message {
+            MidiMessage::ControlChange(ch, cc, value) => (ch, cc, value),
+            _ => return None,
+        };
+        let state = &mut self.channels[(ch.0 & 0x0F) as usize];
+        let select = |state: &mut ChannelParam, kind| {
+            if state.kind != Some(kind) {
+                // switching from RPN to NRPN or vice-versa, forget other half
+                state.number_msb = U7::MIN;
+                state.number_lsb = U7::MIN;
+            }
+            state.kind = Some(kind);
+            state.data_msb = U7::MIN;
+        };
+        let param_value = match cc.0 {
+            CC_RPN_MSB => {
+                select(state, ParamKind::Rpn);
+                state.number_msb = value;
+                return None;
+            }
+            CC_RPN_LSB => {
+                select(state, ParamKind::Rpn);
+                state.number_lsb = value;
+                return None;
+            }
+            CC_NRPN_MSB => {
+                select(state, ParamKind::Nrpn);
+                state.number_msb = value;
+                return None;
+            }
+            CC_NRPN_LSB => {
+                select(state, ParamKind::Nrpn);
+                state.number_lsb = value;
+                return None;
+            }
+            CC_DATA_ENTRY_MSB => {
+                state.data_msb = value;
+                if self.wait_for_lsb {
+                    return None;
+                }
+                ParamValue::Absolute(U14::from((U7::MIN, value)))
+            }
+            CC_DATA_ENTRY_LSB => ParamValue::Absolute(U14::from((value, state.data_msb))),
+            CC_DATA_INCREMENT => ParamValue::Increment(value),
+            CC_DATA_DECREMENT => ParamValue::Decrement(value),
+            _ => return None,
+        };
+        let number = U14::from((state.number_lsb, state.number_msb));
+        match state.kind? {
+            ParamKind::Rpn if number == RPN_NULL => None,
+            ParamKind::Rpn => Some(ParamEvent::Rpn(ch, number, param_value)),
+            ParamKind::Nrpn => Some(ParamEvent::Nrpn(ch, number, param_value)),
+        }
+    }
+}
+
+/// Produces the Control Change sequence for RPN & NRPN events
+#[derive(Debug, Default)]
+pub struct ParamEncoder {
+    // last parameter selected, per channel
+    selected: [Option<(ParamKind, U14)>; 16],
+    running_param: bool,
+    send_lsb: bool,
+}
+
+impl ParamEncoder {
+    /// Skip parameter selection CCs if the same parameter was selected last on this channel,
+    /// like running status does for status bytes (default: false)
+    pub fn with_running_param(mut self, running_param: bool) -> Self {
+        self.running_param = running_param;
+        self
+    }
+
+    /// Send Data Entry LSB (CC 38) after MSB, for full 14-bit resolution (default: false)
+    pub fn with_lsb(mut self, send_lsb: bool) -> Self {
+        self.send_lsb = send_lsb;
+        self
+    }
+
+    /// Forget last selected parameters, next events will select their parameter again
+    pub fn reset(&mut self) {
+        self.selected = Default::default();
+    }
+
+    pub fn encode(&mut self, event: &ParamEvent) -> PacketList {
+        let (ch, kind, number, value) = match *event {
+            ParamEvent::Rpn(ch, number, value) => (ch, ParamKind::Rpn, number, value),
+            ParamEvent::Nrpn(ch, number, value) => (ch, ParamKind::Nrpn, number, value),
+        };
+        let cc = |cc: u8, value: U7| Packet::from(MidiMessage::ControlChange(ch, U7(cc), value));
+        let mut packets: Vec<Packet, 4> = Vec::new();
+
+        let selected = &mut self.selected[(ch.0 & 0x0F) as usize];
+        if !self.running_param || *selected != Some((kind, number)) {
+            let (lsb, msb) = number.into();
+            let (cc_msb, cc_lsb) = match kind {
+                ParamKind::Rpn => (CC_RPN_MSB, CC_RPN_LSB),
+                ParamKind::Nrpn => (CC_NRPN_MSB, CC_NRPN_LSB),
+            };
+            let _ = packets.push(cc(cc_msb, msb));
+            let _ = packets.push(cc(cc_lsb, lsb));
+            *selected = Some((kind, number));
+        }
+
+        match value {
+            ParamValue::Absolute(value) => {
+                let (lsb, msb) = value.into();
+                let _ = packets.push(cc(CC_DATA_ENTRY_MSB, msb));
+                if self.send_lsb {
+                    let _ = packets.push(cc(CC_DATA_ENTRY_LSB, lsb));
+                }
+            }
+            ParamValue::Increment(amount) => {
+                let _ = packets.push(cc(CC_DATA_INCREMENT, amount));
+            }
+            ParamValue::Decrement(amount) => {
+                let _ = packets.push(cc(CC_DATA_DECREMENT, amount));
+            }
+        }
+        PacketList::from_iter(packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+    use core::convert::TryFrom;
+
+    fn cc(ch: u8, cc: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange(channel(ch), U7(cc), U7(value))
+    }
+
+    #[test]
+    fn decode_nrpn() {
+        let mut decoder = ParamDecoder::default();
+        assert_eq!(None, decoder.advance(&cc(1, CC_NRPN_MSB, 1)));
+        assert_eq!(None, decoder.advance(&cc(1, CC_NRPN_LSB, 2)));
+        assert_eq!(
+            Some(ParamEvent::Nrpn(channel(1), U14(0x82), ParamValue::Absolute(U14(0x400)))),
+            decoder.advance(&cc(1, CC_DATA_ENTRY_MSB, 8))
+        );
+        assert_eq!(
+            Some(ParamEvent::Nrpn(channel(1), U14(0x82), ParamValue::Absolute(U14(0x403)))),
+            decoder.advance(&cc(1, CC_DATA_ENTRY_LSB, 3))
+        );
+        assert_eq!(
+            Some(ParamEvent::Nrpn(channel(1), U14(0x82), ParamValue::Increment(U7(1)))),
+            decoder.advance(&cc(1, CC_DATA_INCREMENT, 1))
+        );
+        // other channels are independent
+        assert_eq!(None, decoder.advance(&cc(2, CC_DATA_ENTRY_MSB, 8)));
+        assert_eq!(None, decoder.advance(&cc(1, 74, 8)));
+    }
+
+    #[test]
+    fn decode_rpn_wait_lsb() {
+        let mut decoder = ParamDecoder::default().with_wait_for_lsb(true);
+        decoder.advance(&cc(3, CC_RPN_MSB, 0));
+        decoder.advance(&cc(3, CC_RPN_LSB, 0));
+        assert_eq!(None, decoder.advance(&cc(3, CC_DATA_ENTRY_MSB, 2)));
+        assert_eq!(
+            Some(ParamEvent::Rpn(channel(3), U14(0), ParamValue::Absolute(U14(0x100)))),
+            decoder.advance(&cc(3, CC_DATA_ENTRY_LSB, 0))
+        );
+        // null RPN deselects
+        decoder.advance(&cc(3, CC_RPN_MSB, 0x7F));
+        decoder.advance(&cc(3, CC_RPN_LSB, 0x7F));
+        assert_eq!(None, decoder.advance(&cc(3, CC_DATA_ENTRY_LSB, 0)));
+    }
+
+    #[test]
+    fn encode_roundtrip() {
+        let mut encoder = ParamEncoder::default().with_lsb(true).with_running_param(true);
+        let mut decoder = ParamDecoder::default().with_wait_for_lsb(true);
+        let event = ParamEvent::Nrpn(channel(4), U14(0x1234), ParamValue::Absolute(U14(0x2345)));
+
+        let packets = encoder.encode(&event);
+        assert_eq!(4, packets.len());
+        let decoded: Vec<ParamEvent, 4> = packets.iter()
+            .filter_map(|p| decoder.advance(&MidiMessage::try_from(*p).unwrap()))
+            .collect();
+        assert_eq!(&[event], decoded.as_slice());
+
+        // parameter already selected
+        assert_eq!(2, encoder.encode(&event).len());
+        encoder.reset();
+        assert_eq!(4, encoder.encode(&event).len());
+    }
+}
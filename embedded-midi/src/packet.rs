@@ -3,7 +3,7 @@
 
 use crate::message::MidiMessage;
 use core::convert::{TryFrom};
-use crate::{MidiError, MidiChannel, channel};
+use crate::{MidiError, MidiChannel};
 use crate::status::{Status, status_byte, SYSEX_START, SYSEX_END};
 use CodeIndexNumber::*;
 
@@ -11,7 +11,7 @@ use num_enum::UnsafeFromPrimitive;
 
 pub type CableNumber = u8;
 
-#[derive(Default, Clone, Copy, Debug)]
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Packet {
     bytes: [u8; 4]
@@ -49,7 +49,7 @@ impl Packet {
         if byte < NoteOff as u8 {
             None
         } else {
-            Some(channel(byte))
+            Some(MidiChannel(byte & 0x0F))
         }
     }
 
@@ -1,4 +1,4 @@
-use crate::status::{is_non_status, is_channel_status, SYSEX_END};
+use crate::status::{is_non_status, is_channel_status, is_realtime, SYSEX_END};
 use crate::{CodeIndexNumber, Packet, Status, MidiError};
 use core::convert::TryFrom;
 
@@ -42,51 +42,237 @@ impl PacketBuffer {
 #[derive(Debug, Default)]
 pub struct PacketParser {
     status: Option<Status>,
+    /// Raw status byte, including channel
+    status_byte: u8,
+    /// Sysex of unknown length is in progress, chunked into 3-byte packets
+    in_sysex: bool,
     buffer: PacketBuffer,
+    /// Single-byte message that interrupted a sysex, returned after the error
+    pending: Option<Packet>,
 }
 
 impl PacketParser {
     /// Push new payload byte
+    /// Realtime bytes are returned immediately as their own packet, without disturbing the message in progress.
     /// returns:
     /// - Ok(None) if packet is incomplete
     /// - Ok(Some(packet)) if packet is complete - should not be pushed to anymore, waiting on either sysex or sysex_end
+    /// - Err(SysexInterrupted) if a new status byte aborted an unterminated sysex. The new status is still applied.
+    ///   If the new status is a single-byte message, it is returned by the next `advance()` or `take_pending()`.
     pub fn advance(&mut self, byte: u8) -> Result<Option<Packet>, MidiError> {
+        let previous = self.pending.take();
+        match (previous, self.parse(byte)) {
+            (None, result) => result,
+            (Some(previous), Ok(Some(packet))) => {
+                // keep packets in order
+                self.pending = Some(packet);
+                Ok(Some(previous))
+            }
+            (Some(previous), Ok(None)) => Ok(Some(previous)),
+            (Some(previous), Err(err)) => {
+                self.pending.get_or_insert(previous);
+                Err(err)
+            }
+        }
+    }
+
+    /// Message waiting to be returned after an Err(SysexInterrupted)
+    /// Receivers should check it before waiting for more bytes
+    pub fn take_pending(&mut self) -> Option<Packet> {
+        self.pending.take()
+    }
+
+    fn parse(&mut self, byte: u8) -> Result<Option<Packet>, MidiError> {
+        if byte == SYSEX_END {
+            if !self.in_sysex {
+                // spurious end marker, nothing to terminate
+                return Ok(None);
+            }
+            self.in_sysex = false;
+            self.status = None;
+            self.buffer.push(byte);
+            return Ok(Some(self.buffer.build(CodeIndexNumber::end_sysex(self.buffer.len)?)));
+        }
+
         if is_non_status(byte) {
+            if self.in_sysex {
+                self.buffer.push(byte);
+                if self.buffer.is_full() {
+                    // sysex continues
+                    return Ok(Some(self.buffer.build(CodeIndexNumber::Sysex)));
+                }
+                return Ok(None);
+            }
             if let Some(status) = self.status {
                 if !self.buffer.is_started() && is_channel_status(status as u8) {
                     // running status, repeat last
                     self.buffer.clear(self.buffer.expected_len);
-                    self.buffer.push(status as u8);
+                    self.buffer.push(self.status_byte);
                 }
                 self.buffer.push(byte);
 
-                if byte == SYSEX_END {
-                    self.status = None;
-                    return Ok(Some(self.buffer.build(CodeIndexNumber::end_sysex(self.buffer.len)?)));
-                }
                 if self.buffer.is_full() {
+                    if !is_channel_status(status as u8) {
+                        // system common messages do not have running status
+                        self.status = None;
+                    }
                     return Ok(Some(self.buffer.build(CodeIndexNumber::from(status))));
                 }
             }
             return Ok(None);
         }
 
+        if is_realtime(byte) {
+            // realtime messages are interleaved with other messages and sysex,
+            // leave running status and partially buffered bytes untouched
+            return Ok(Status::try_from(byte).ok()
+                .map(|status| Packet::from_raw([CodeIndexNumber::from(status) as u8, byte, 0, 0])));
+        }
+
+        let interrupted = self.in_sysex;
+        self.in_sysex = false;
+
         if let Ok(status) = Status::try_from(byte) {
             match status.expected_len() {
+                _ if status == Status::SysexStart => {
+                    self.status = None;
+                    self.in_sysex = true;
+                    self.buffer.clear(3);
+                    self.buffer.push(byte);
+                }
                 1 => {
                     // single-byte message do not need running status
                     self.status = None;
 
                     // skip buffer for single-byte messages
-                    return Ok(Some(Packet::from_raw([CodeIndexNumber::from(status) as u8, byte, 0, 0])));
+                    let packet = Packet::from_raw([CodeIndexNumber::from(status) as u8, byte, 0, 0]);
+                    if !interrupted {
+                        return Ok(Some(packet));
+                    }
+                    self.pending = Some(packet);
                 }
                 expected_len => {
                     self.status = Some(status);
                     self.buffer.clear(expected_len);
+                    self.status_byte = byte;
                     self.buffer.push(byte);
                 }
             }
         }
+        if interrupted {
+            return Err(MidiError::SysexInterrupted);
+        }
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{channel, MidiMessage, Note, U7};
+
+    fn parse_all(parser: &mut PacketParser, bytes: &[u8]) -> heapless::Vec<Packet, 16> {
+        let mut packets = heapless::Vec::new();
+        for byte in bytes {
+            if let Some(packet) = parser.advance(*byte).unwrap() {
+                packets.push(packet).unwrap();
+            }
+        }
+        packets
+    }
+
+    #[test]
+    fn running_status() {
+        let mut parser = PacketParser::default();
+        let packets = parse_all(&mut parser, &[0x91, 60, 100, 62, 100]);
+        assert_eq!(2, packets.len());
+        assert_eq!(MidiMessage::NoteOn(channel(2), Note::C4, U7(100)), MidiMessage::try_from(packets[0]).unwrap());
+        assert_eq!(MidiMessage::NoteOn(channel(2), Note::D4, U7(100)), MidiMessage::try_from(packets[1]).unwrap());
+    }
+
+    #[test]
+    fn long_sysex() {
+        // DW-6000 dump
+        let mut dump = [0x11; 32];
+        dump[..5].copy_from_slice(&[0xF0, 0x42, 0x30, 0x04, 0x40]);
+        dump[31] = SYSEX_END;
+        let mut parser = PacketParser::default();
+        let packets = parse_all(&mut parser, &dump);
+        assert_eq!(11, packets.len());
+        assert_eq!(&[0x04, 0xF0, 0x42, 0x30], packets[0].bytes());
+        for packet in &packets[1..10] {
+            assert_eq!(CodeIndexNumber::Sysex, packet.code_index_number());
+        }
+        assert_eq!(&[0x06, 0x11, 0xF7, 0x00], packets[10].bytes());
+    }
+
+    #[test]
+    fn sysex_end_any_position() {
+        let cases: [(&[u8], &[u8; 4]); 6] = [
+            (&[0xF0, 0xF7], &[0x06, 0xF0, 0xF7, 0]),
+            (&[0xF0, 0x01, 0xF7], &[0x07, 0xF0, 0x01, 0xF7]),
+            (&[0xF0, 0x01, 0x02, 0xF7], &[0x05, 0xF7, 0, 0]),
+            (&[0xF0, 0x01, 0x02, 0x03, 0xF7], &[0x06, 0x03, 0xF7, 0]),
+            (&[0xF0, 0x01, 0x02, 0x03, 0x04, 0xF7], &[0x07, 0x03, 0x04, 0xF7]),
+            (&[0xF0, 0x01, 0x02, 0x03, 0x04, 0x05, 0xF7], &[0x05, 0xF7, 0, 0]),
+        ];
+        for (bytes, last) in cases {
+            let mut parser = PacketParser::default();
+            let packets = parse_all(&mut parser, bytes);
+            assert_eq!(last, packets.last().unwrap().bytes());
+            // sysex data bytes are not mistaken for running status
+            assert!(parse_all(&mut parser, &[0x01, 0x02]).is_empty());
+        }
+    }
+
+    #[test]
+    fn sysex_interrupted() {
+        let mut parser = PacketParser::default();
+        parse_all(&mut parser, &[0xF0, 0x42, 0x30, 0x04]);
+        assert!(matches!(parser.advance(0x90), Err(MidiError::SysexInterrupted)));
+        // new status still applies
+        assert_eq!(None, parser.advance(60).unwrap());
+        let packet = parser.advance(100).unwrap().unwrap();
+        assert_eq!(MidiMessage::NoteOn(channel(1), Note::C4, U7(100)), MidiMessage::try_from(packet).unwrap());
+    }
+
+    #[test]
+    fn sysex_interrupted_by_single_byte() {
+        let mut parser = PacketParser::default();
+        parse_all(&mut parser, &[0xF0, 0x42, 0x30]);
+        assert!(matches!(parser.advance(0xF6), Err(MidiError::SysexInterrupted)));
+        assert_eq!(Some(Packet::from(MidiMessage::TuneRequest)), parser.take_pending());
+        assert_eq!(None, parser.take_pending());
+
+        // or returned by next byte, ahead of the packet that byte completes
+        parse_all(&mut parser, &[0xF0, 0x42, 0x30]);
+        assert!(matches!(parser.advance(0xF6), Err(MidiError::SysexInterrupted)));
+        assert_eq!(Some(Packet::from(MidiMessage::TuneRequest)), parser.advance(0xF8).unwrap());
+        assert_eq!(Some(Packet::from(MidiMessage::TimingClock)), parser.advance(0x90).unwrap());
+        assert_eq!(None, parser.advance(60).unwrap());
+    }
+
+    #[test]
+    fn realtime_inside_message() {
+        let mut parser = PacketParser::default();
+        let packets = parse_all(&mut parser, &[0x92, 0xF8, 60, 0xF8, 100, 0xFA, 62, 0xFD, 100]);
+        assert_eq!(5, packets.len());
+        assert_eq!(MidiMessage::TimingClock, MidiMessage::try_from(packets[0]).unwrap());
+        assert_eq!(MidiMessage::TimingClock, MidiMessage::try_from(packets[1]).unwrap());
+        assert_eq!(MidiMessage::NoteOn(channel(3), Note::C4, U7(100)), MidiMessage::try_from(packets[2]).unwrap());
+        assert_eq!(MidiMessage::Start, MidiMessage::try_from(packets[3]).unwrap());
+        // running status survived
+        assert_eq!(MidiMessage::NoteOn(channel(3), Note::D4, U7(100)), MidiMessage::try_from(packets[4]).unwrap());
+    }
+
+    #[test]
+    fn realtime_inside_sysex() {
+        let mut parser = PacketParser::default();
+        let packets = parse_all(&mut parser, &[0xF0, 0x42, 0xF8, 0x30, 0x04, 0xFE, 0x10, 0xF7]);
+        assert_eq!(4, packets.len());
+        assert_eq!(MidiMessage::TimingClock, MidiMessage::try_from(packets[0]).unwrap());
+        assert_eq!(MidiMessage::SysexBegin(0x42, 0x30), MidiMessage::try_from(packets[1]).unwrap());
+        assert_eq!(MidiMessage::ActiveSensing, MidiMessage::try_from(packets[2]).unwrap());
+        assert_eq!(MidiMessage::SysexEnd2(0x04, 0x10), MidiMessage::try_from(packets[3]).unwrap());
+    }
+}
@@ -1,10 +1,10 @@
-use core::fmt::{Debug};
+use core::fmt::{self, Debug, Display, Formatter};
 
 use hash32::{Hasher};
 use heapless::{FnvIndexMap, Vec};
 use heapless::spsc::Queue;
 use spin::mutex::SpinMutex;
-use crate::{MidiError, Packet};
+use crate::{CableNumber, MidiError, Packet, PortStats};
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -13,7 +13,7 @@ pub enum PortId {
     Serial(u8),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PortDirection {
     // Packets coming in from other devices
@@ -22,6 +22,24 @@ pub enum PortDirection {
     Out,
 }
 
+impl Display for PortId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PortId::Usb(id) => write!(f, "USB {}", id),
+            PortId::Serial(id) => write!(f, "Serial {}", id),
+        }
+    }
+}
+
+impl Display for PortDirection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PortDirection::In => "in",
+            PortDirection::Out => "out",
+        })
+    }
+}
+
 impl hash32::Hash for PortId {
     fn hash<H>(&self, state: &mut H) where H: Hasher {
         match self {
@@ -36,13 +54,82 @@ impl hash32::Hash for PortId {
     }
 }
 
-#[derive(Copy, Clone)]
+/// What a port supports
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PortCapabilities {
+    /// Port can both receive and transmit, e.g. one USB cable or DIN in/out pair
+    pub bidirectional: bool,
+    pub sysex: bool,
+    /// Serial speed, None for USB or unknown
+    pub baud_rate: Option<u32>,
+}
+
+#[derive(Debug, Copy, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct PortInfo {
     pub port_id: PortId,
     pub direction: PortDirection,
-    // TODO figure out strings
-    // name: &'str
+    /// Human-readable name, e.g. "DW-6000 out", empty if unnamed
+    pub name: &'static str,
+    pub manufacturer: Option<&'static str>,
+    pub model: Option<&'static str>,
+    pub cable_number: CableNumber,
+    pub capabilities: PortCapabilities,
+}
+
+impl PortInfo {
+    pub fn new(port_id: PortId, direction: PortDirection) -> Self {
+        PortInfo {
+            port_id,
+            direction,
+            name: "",
+            manufacturer: None,
+            model: None,
+            cable_number: 0,
+            capabilities: PortCapabilities::default(),
+        }
+    }
+
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn with_manufacturer(mut self, manufacturer: &'static str) -> Self {
+        self.manufacturer = Some(manufacturer);
+        self
+    }
+
+    pub fn with_model(mut self, model: &'static str) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    pub fn with_cable_num(mut self, cable_number: CableNumber) -> Self {
+        self.cable_number = cable_number;
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: PortCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn is_named(&self) -> bool {
+        !self.name.is_empty()
+    }
+}
+
+/// Name if port has one, else id and direction, e.g. "Serial 0 out"
+impl Display for PortInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_named() {
+            f.write_str(self.name)
+        } else {
+            write!(f, "{} {}", self.port_id, self.direction)
+        }
+    }
 }
 
 pub type PortHandle = usize;
@@ -50,6 +137,7 @@ pub type PortHandle = usize;
 pub struct MidiPort {
     info: PortInfo,
     buffer: Queue<Packet, MAX_BUFFERED_PACKETS>,
+    stats: PortStats,
 }
 
 const MAX_BUFFERED_PACKETS: usize = 16;
@@ -74,6 +162,28 @@ pub trait MidiPorts {
     fn space(&self, handle: &PortHandle) -> Result<usize, MidiError>;
 
     fn info(&self, handle: &PortHandle) -> Result<PortInfo, MidiError>;
+
+    /// Snapshot of port counters
+    /// In and out are relative to the port buffer: writes count as in and reads as out, whatever the port direction
+    fn stats(&self, handle: &PortHandle) -> Result<PortStats, MidiError>;
+
+    /// Zero port counters, returning their last values
+    fn reset_stats(&self, handle: &PortHandle) -> Result<PortStats, MidiError>;
+
+    /// Count an error that happened outside the registry, e.g. a parse error of the device driver
+    fn record_error(&self, handle: &PortHandle, error: &MidiError) -> Result<(), MidiError>;
+
+    /// Find handle of port with id and direction
+    fn find_port(&self, port_id: PortId, direction: PortDirection) -> Option<PortHandle> {
+        self.list_ports().into_iter()
+            .find(|handle| self.info(handle).is_ok_and(|info| info.port_id == port_id && info.direction == direction))
+    }
+
+    /// Find handle of first port with name
+    fn find_by_name(&self, name: &str) -> Option<PortHandle> {
+        self.list_ports().into_iter()
+            .find(|handle| self.info(handle).is_ok_and(|info| info.is_named() && info.name == name))
+    }
 }
 
 // pub type MidiOutFn = &'static mut (dyn FnMut(Packet) -> bool + Send + Sync);
@@ -81,9 +191,28 @@ pub trait MidiPorts {
 
 pub struct MidiRegistry<const N: usize> {
     inner: SpinMutex<MidiRegistryInner<N>>,
+    clock: Option<fn() -> u64>,
+}
+
+impl<const N: usize> Default for MidiRegistry<N> {
+    fn default() -> Self {
+        MidiRegistry {
+            inner: SpinMutex::new(MidiRegistryInner {
+                next_port_handle: 0,
+                ports: FnvIndexMap::new(),
+            }),
+            clock: None,
+        }
+    }
 }
 
 impl<const N: usize> MidiRegistry<N> {
+    /// Clock used to timestamp port activity, e.g. millis since boot
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
     fn with_port<R, F: Fn(&mut MidiPort) -> Result<R, MidiError>>(&self, handle: &PortHandle, fun: F) -> Result<R, MidiError> {
         if let Some(port) = self.inner.lock().ports.get_mut(handle) {
            fun(port)
@@ -108,14 +237,36 @@ impl<const N: usize> MidiPorts for MidiRegistry<N> {
         self.inner.lock().release_port(handle)
     }
 
-    /// Try to read a packet from the port
+    /// Try to read a packet from the port, counted as a packet out
     fn read(&self, handle: &PortHandle) -> Result<Option<Packet>, MidiError> {
-        self.with_port(handle, |port| Ok(port.buffer.dequeue()))
+        let now = self.clock.map(|clock| clock());
+        self.with_port(handle, |port| {
+            let packet = port.buffer.dequeue();
+            if let Some(packet) = &packet {
+                port.stats.packet_out(packet);
+                if let Some(now) = now {
+                    port.stats.touch(now);
+                }
+            }
+            Ok(packet)
+        })
     }
 
-    /// Write a packet to a port
+    /// Write a packet to a port, counted as a packet in
     fn write(&self, handle: &PortHandle, packet: Packet) -> Result<(), MidiError> {
-        self.with_port(handle, |port| port.buffer.enqueue(packet).or(Err(MidiError::BufferFull)))
+        let now = self.clock.map(|clock| clock());
+        self.with_port(handle, |port| {
+            if port.buffer.enqueue(packet).is_err() {
+                port.stats.error(&MidiError::BufferFull);
+                return Err(MidiError::BufferFull);
+            }
+            port.stats.packet_in(&packet);
+            port.stats.depth(port.buffer.len());
+            if let Some(now) = now {
+                port.stats.touch(now);
+            }
+            Ok(())
+        })
     }
 
     fn space(&self, handle: &PortHandle) -> Result<usize, MidiError> {
@@ -125,6 +276,21 @@ impl<const N: usize> MidiPorts for MidiRegistry<N> {
     fn info(&self, handle: &PortHandle) -> Result<PortInfo, MidiError> {
         self.with_port(handle, |port| Ok(port.info))
     }
+
+    fn stats(&self, handle: &PortHandle) -> Result<PortStats, MidiError> {
+        self.with_port(handle, |port| Ok(port.stats))
+    }
+
+    fn reset_stats(&self, handle: &PortHandle) -> Result<PortStats, MidiError> {
+        self.with_port(handle, |port| Ok(port.stats.take()))
+    }
+
+    fn record_error(&self, handle: &PortHandle, error: &MidiError) -> Result<(), MidiError> {
+        self.with_port(handle, |port| {
+            port.stats.error(error);
+            Ok(())
+        })
+    }
 }
 
 pub struct MidiRegistryInner<const N: usize> {
@@ -153,6 +319,7 @@ impl<const N: usize> MidiRegistryInner<N> {
         let new_port = MidiPort {
             info,
             buffer: Default::default(),
+            stats: PortStats::default(),
         };
         let _ = self.ports.insert(new_handle, new_port);
         Ok(new_handle)
@@ -165,3 +332,66 @@ impl<const N: usize> MidiRegistryInner<N> {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::String;
+    use core::fmt::Write;
+
+    #[test]
+    fn find_by_name() {
+        let registry: MidiRegistry<4> = MidiRegistry::default();
+        let usb = registry.acquire_port(PortInfo::new(PortId::Usb(0), PortDirection::In)).unwrap();
+        let dw = registry.acquire_port(PortInfo::new(PortId::Serial(0), PortDirection::Out)
+            .with_name("DW-6000 out")
+            .with_manufacturer("Korg")
+            .with_model("DW-6000")
+            .with_capabilities(PortCapabilities { sysex: true, baud_rate: Some(31250), ..Default::default() })
+        ).unwrap();
+
+        assert_eq!(Some(dw), registry.find_by_name("DW-6000 out"));
+        assert_eq!(None, registry.find_by_name("DW-6000 in"));
+        assert_eq!(None, registry.find_by_name(""));
+        assert_eq!(Some("Korg"), registry.info(&dw).unwrap().manufacturer);
+        assert_eq!(Some(usb), registry.find_port(PortId::Usb(0), PortDirection::In));
+    }
+
+    #[test]
+    fn stats() {
+        fn clock() -> u64 { 42 }
+        let registry: MidiRegistry<4> = MidiRegistry::default().with_clock(clock);
+        let port = registry.acquire_port(PortInfo::new(PortId::Serial(0), PortDirection::Out)).unwrap();
+        let packet = Packet::from_raw([0x09, 0x90, 60, 100]);
+        for _ in 0..MAX_BUFFERED_PACKETS {
+            let _ = registry.write(&port, packet);
+        }
+        registry.read(&port).unwrap();
+        registry.record_error(&port, &MidiError::InvalidStatus(0xF4)).unwrap();
+        // sysex counted once when written and once when read
+        registry.write(&port, Packet::from_raw([0x04, 0xF0, 0x42, 0x30])).unwrap();
+        while registry.read(&port).unwrap().is_some() {}
+
+        let stats = registry.reset_stats(&port).unwrap();
+        assert_eq!(MAX_BUFFERED_PACKETS as u32, stats.packets_in);
+        assert_eq!(3 * MAX_BUFFERED_PACKETS as u32, stats.bytes_in);
+        assert_eq!(MAX_BUFFERED_PACKETS as u32, stats.packets_out);
+        assert_eq!(1, stats.sysex_in);
+        assert_eq!(1, stats.sysex_out);
+        assert_eq!(1, stats.dropped);
+        assert_eq!(1, stats.parse_errors);
+        assert_eq!(MAX_BUFFERED_PACKETS as u16 - 1, stats.peak_depth);
+        assert_eq!(Some(42), stats.last_activity);
+        assert_eq!(PortStats::default(), registry.stats(&port).unwrap());
+    }
+
+    #[test]
+    fn display() {
+        let mut s: String<32> = String::new();
+        write!(s, "{}", PortInfo::new(PortId::Serial(1), PortDirection::Out)).unwrap();
+        assert_eq!("Serial 1 out", s.as_str());
+        s.clear();
+        write!(s, "{}", PortInfo::new(PortId::Serial(1), PortDirection::Out).with_name("Synth")).unwrap();
+        assert_eq!("Synth", s.as_str());
+    }
+}
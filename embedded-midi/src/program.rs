@@ -0,0 +1,140 @@
+//! Bank Select & Program Change
+//! Bank Select MSB (CC 0) and LSB (CC 32) apply to the next Program Change received on the same channel,
+//! giving access to 16384 banks of 128 programs.
+
+use core::convert::TryFrom;
+use core::iter::FromIterator;
+
+use crate::{Control, MidiChannel, MidiMessage, Packet, PacketList, Program, U14, U7};
+
+pub const CC_BANK_MSB: Control = U7(0);
+pub const CC_BANK_LSB: Control = U7(32);
+
+/// Programs per bank
+pub const BANK_SIZE: u32 = 128;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProgramSelect {
+    pub bank: U14,
+    pub program: Program,
+}
+
+impl ProgramSelect {
+    pub fn new(bank: U14, program: Program) -> Self {
+        ProgramSelect { bank, program }
+    }
+
+    /// Program from flat number, counting across banks (e.g. 130 is second program of bank 1)
+    /// Returns None if number is beyond the last bank
+    pub fn from_index(index: u32) -> Option<Self> {
+        let bank = u16::try_from(index / BANK_SIZE).ok()?;
+        Some(ProgramSelect {
+            bank: U14::try_from(bank).ok()?,
+            program: U7((index % BANK_SIZE) as u8),
+        })
+    }
+
+    /// Flat program number, counting across banks
+    pub fn index(&self) -> u32 {
+        u16::from(self.bank) as u32 * BANK_SIZE + self.program.0 as u32
+    }
+
+    /// Bank Select MSB, Bank Select LSB and Program Change, in the order they must be sent
+    pub fn to_messages(&self, channel: MidiChannel) -> [MidiMessage; 3] {
+        let (lsb, msb): (U7, U7) = self.bank.into();
+        [
+            MidiMessage::ControlChange(channel, CC_BANK_MSB, msb),
+            MidiMessage::ControlChange(channel, CC_BANK_LSB, lsb),
+            MidiMessage::ProgramChange(channel, self.program),
+        ]
+    }
+
+    pub fn to_packets(&self, channel: MidiChannel) -> PacketList {
+        PacketList::from_iter(self.to_messages(channel).into_iter().map(Packet::from))
+    }
+}
+
+/// Assembles Bank Select and Program Change messages into a single event
+#[derive(Debug)]
+pub struct ProgramSelectDecoder {
+    banks: [U14; 16],
+}
+
+impl Default for ProgramSelectDecoder {
+    fn default() -> Self {
+        ProgramSelectDecoder { banks: [U14::MIN; 16] }
+    }
+}
+
+impl ProgramSelectDecoder {
+    /// Feed a message to the decoder
+    /// Returns Some(channel, program) on Program Change, with the last bank selected on that channel
+    pub fn advance(&mut self, message: &MidiMessage) -> Option<(MidiChannel, ProgramSelect)> {
+        match *message {
+            MidiMessage::ControlChange(ch, control, value) if control == CC_BANK_MSB || control == CC_BANK_LSB => {
+                let bank = &mut self.banks[ch.0 as usize & 0x0F];
+                let (lsb, msb): (U7, U7) = (*bank).into();
+                *bank = if control == CC_BANK_MSB {
+                    U14::from((lsb, value))
+                } else {
+                    U14::from((value, msb))
+                };
+                None
+            }
+            MidiMessage::ProgramChange(ch, program) => Some((ch, ProgramSelect::new(self.bank(ch), program))),
+            _ => None,
+        }
+    }
+
+    /// Bank currently selected on channel
+    pub fn bank(&self, channel: MidiChannel) -> U14 {
+        self.banks[channel.0 as usize & 0x0F]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    #[test]
+    fn encode() {
+        // bank MSB 2, LSB 9
+        let select = ProgramSelect::from_index(0x109 * BANK_SIZE + 5).unwrap();
+        assert_eq!(ProgramSelect::new(U14(0x109), U7(5)), select);
+        assert_eq!(0x109 * BANK_SIZE + 5, select.index());
+        assert_eq!(None, ProgramSelect::from_index(0x4000 * BANK_SIZE));
+
+        let packets = select.to_packets(channel(2));
+        assert_eq!(3, packets.len());
+        assert_eq!(&[0x0B, 0xB1, 0, 2], packets[0].bytes());
+        assert_eq!(&[0x0B, 0xB1, 32, 9], packets[1].bytes());
+        assert_eq!(&[0x0C, 0xC1, 5, 0], packets[2].bytes());
+    }
+
+    #[test]
+    fn decode() {
+        let mut decoder = ProgramSelectDecoder::default();
+        let ch = channel(3);
+        assert_eq!(
+            Some((ch, ProgramSelect::new(U14::MIN, U7(9)))),
+            decoder.advance(&MidiMessage::ProgramChange(ch, U7(9)))
+        );
+        for message in ProgramSelect::new(U14(300), U7(1)).to_messages(ch) {
+            if let Some(event) = decoder.advance(&message) {
+                assert_eq!((ch, ProgramSelect::new(U14(300), U7(1))), event);
+            }
+        }
+        // bank is kept for next program changes
+        assert_eq!(U14(300), decoder.bank(ch));
+        assert_eq!(
+            Some((ch, ProgramSelect::new(U14(300), U7(2)))),
+            decoder.advance(&MidiMessage::ProgramChange(ch, U7(2)))
+        );
+        // LSB only
+        decoder.advance(&MidiMessage::ControlChange(ch, CC_BANK_LSB, U7(0)));
+        assert_eq!(U14(256), decoder.bank(ch));
+        assert_eq!(U14::MIN, decoder.bank(channel(1)));
+    }
+}
@@ -0,0 +1,183 @@
+//! Packet queue between interrupt handlers and async tasks
+//! Interrupt handlers use the non-blocking `push()` and `pop()`, tasks await `recv()` and `send()`.
+
+use core::future::{poll_fn, Future};
+use core::task::{Context, Poll, Waker};
+
+use heapless::mpmc::MpMcQueue;
+use spin::mutex::SpinMutex;
+
+use crate::{AsyncReceive, AsyncTransmit, MidiError, Packet, PacketList};
+
+/// Lock-free packet queue waking the tasks waiting on it
+/// N must be a power of two
+/// A single task may wait on each side of the queue, the last task to wait is the one woken
+pub struct PacketQueue<const N: usize> {
+    packets: MpMcQueue<Packet, N>,
+    receiver: WakerSlot,
+    sender: WakerSlot,
+}
+
+impl<const N: usize> Default for PacketQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> PacketQueue<N> {
+    pub const fn new() -> Self {
+        PacketQueue {
+            packets: MpMcQueue::new(),
+            receiver: WakerSlot::new(),
+            sender: WakerSlot::new(),
+        }
+    }
+
+    /// Enqueue packet without waiting, wakes the receiving task
+    pub fn push(&self, packet: Packet) -> Result<(), MidiError> {
+        self.packets.enqueue(packet).map_err(|_| MidiError::BufferFull)?;
+        self.receiver.wake();
+        Ok(())
+    }
+
+    /// Dequeue packet without waiting, wakes the sending task
+    pub fn pop(&self) -> Option<Packet> {
+        let packet = self.packets.dequeue()?;
+        self.sender.wake();
+        Some(packet)
+    }
+
+    fn poll_pop(&self, cx: &Context<'_>) -> Poll<Packet> {
+        if let Some(packet) = self.pop() {
+            return Poll::Ready(packet);
+        }
+        self.receiver.register(cx);
+        // check again in case a packet was pushed while registering
+        match self.pop() {
+            Some(packet) => Poll::Ready(packet),
+            None => Poll::Pending,
+        }
+    }
+
+    fn poll_push(&self, packet: Packet, cx: &Context<'_>) -> Poll<()> {
+        if self.push(packet).is_ok() {
+            return Poll::Ready(());
+        }
+        self.sender.register(cx);
+        // check again in case a packet was popped while registering
+        match self.push(packet) {
+            Ok(()) => Poll::Ready(()),
+            Err(_) => Poll::Pending,
+        }
+    }
+}
+
+impl<const N: usize> AsyncReceive for &PacketQueue<N> {
+    fn recv(&mut self) -> impl Future<Output=Result<Packet, MidiError>> {
+        let queue = *self;
+        poll_fn(move |cx| queue.poll_pop(cx).map(Ok))
+    }
+}
+
+impl<const N: usize> AsyncTransmit for &PacketQueue<N> {
+    /// Waits for room in queue before each packet
+    fn send(&mut self, packets: PacketList) -> impl Future<Output=Result<(), MidiError>> {
+        let queue = *self;
+        async move {
+            for packet in packets.iter() {
+                poll_fn(|cx| queue.poll_push(*packet, cx)).await;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Waker of a pending task
+/// The runtime's `WakerSet` is not used here: it is a thread mode only `UnsafeCell`,
+/// while `push()` and `pop()` wake tasks from interrupt handlers, and this crate must not depend on the runtime.
+struct WakerSlot {
+    waker: SpinMutex<Option<Waker>>,
+}
+
+impl WakerSlot {
+    const fn new() -> Self {
+        WakerSlot { waker: SpinMutex::new(None) }
+    }
+
+    fn register(&self, cx: &Context<'_>) {
+        let mut waker = self.waker.lock();
+        if !waker.as_ref().is_some_and(|w| w.will_wake(cx.waker())) {
+            *waker = Some(cx.waker().clone());
+        }
+    }
+
+    /// Never blocks, so it can be called from interrupt handlers
+    /// If the slot is locked, the task is registering and will check the queue again
+    fn wake(&self) {
+        let waker = self.waker.try_lock().and_then(|mut waker| waker.take());
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::pin::pin;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::{RawWaker, RawWakerVTable};
+    use crate::{channel, MidiMessage, Note, U7};
+
+    static WAKES: AtomicUsize = AtomicUsize::new(0);
+
+    fn counting_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+        fn wake(_: *const ()) {
+            WAKES.fetch_add(1, Ordering::Relaxed);
+        }
+        fn drop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+        unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+    }
+
+    fn packet(note: Note) -> Packet {
+        Packet::from(MidiMessage::NoteOn(channel(1), note, U7(100)))
+    }
+
+    #[test]
+    fn recv_waits_for_push() {
+        let queue: PacketQueue<4> = PacketQueue::new();
+        let waker = counting_waker();
+        let mut cx = Context::from_waker(&waker);
+        let wakes = WAKES.load(Ordering::Relaxed);
+
+        let mut rx = &queue;
+        let mut recv = pin!(rx.recv());
+        assert_eq!(Poll::Pending, recv.as_mut().poll(&mut cx));
+
+        queue.push(packet(Note::C4)).unwrap();
+        assert!(WAKES.load(Ordering::Relaxed) > wakes);
+        assert_eq!(Poll::Ready(Ok(packet(Note::C4))), recv.as_mut().poll(&mut cx));
+    }
+
+    #[test]
+    fn send_waits_for_room() {
+        let queue: PacketQueue<2> = PacketQueue::new();
+        let waker = counting_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut tx = &queue;
+        let packets = PacketList::from_iter([packet(Note::C4), packet(Note::D4), packet(Note::E4)]);
+        let mut send = pin!(tx.send(packets));
+        assert_eq!(Poll::Pending, send.as_mut().poll(&mut cx));
+        assert_eq!(Some(packet(Note::C4)), queue.pop());
+        assert_eq!(Poll::Ready(Ok(())), send.as_mut().poll(&mut cx));
+        assert_eq!(Some(packet(Note::D4)), queue.pop());
+        assert_eq!(Some(packet(Note::E4)), queue.pop());
+        assert_eq!(None, queue.pop());
+        assert_eq!(Err(MidiError::BufferFull), (0..3).try_for_each(|_| queue.push(packet(Note::A0))));
+    }
+}
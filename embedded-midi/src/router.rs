@@ -0,0 +1,369 @@
+//! Packet routing between ports
+//! Routes forward packets from a source port to destination ports, through a chain of filters and transforms.
+//! Packets are read from the `In` ports of a `MidiPorts` registry and written to its `Out` ports.
+
+use core::convert::TryFrom;
+
+use heapless::Vec;
+
+use crate::{MidiChannel, MidiError, MidiMessage, MidiPorts, Packet, PacketList, PortDirection, PortId};
+
+/// Max destinations of a single route
+pub const MAX_DESTINATIONS: usize = 4;
+
+/// Max filters & transforms of a single route
+pub const MAX_STAGES: usize = 8;
+
+pub type RouteId = usize;
+
+/// Packets going through a route
+#[derive(Debug)]
+pub struct RouteContext {
+    pub source: PortId,
+    pub packets: PacketList,
+}
+
+/// Return false to drop packets
+pub type FilterFn = fn(&RouteContext) -> bool;
+
+/// Modify, add or remove packets
+pub type TransformFn = fn(&mut RouteContext);
+
+/// Step of a route, applied in order
+#[derive(Copy, Clone)]
+pub enum Stage {
+    /// Keep channel messages of this channel, system messages are kept
+    Channel(MidiChannel),
+    /// Drop timing clock, transport and other realtime messages
+    DropRealtime,
+    DropSysex,
+    /// Move channel messages to channel
+    Remap(MidiChannel),
+    /// Transpose notes by semitones, notes out of range are dropped
+    Transpose(i8),
+    Filter(FilterFn),
+    Transform(TransformFn),
+}
+
+impl Stage {
+    /// Returns false if packets should be dropped
+    fn apply(&self, context: &mut RouteContext) -> bool {
+        match *self {
+            Stage::Filter(filter) => filter(context),
+            Stage::Transform(transform) => {
+                transform(context);
+                true
+            }
+            stage => {
+                context.packets.retain_mut(|packet| stage.apply_packet(packet));
+                true
+            }
+        }
+    }
+
+    fn apply_packet(&self, packet: &mut Packet) -> bool {
+        let message = match MidiMessage::try_from(*packet) {
+            Ok(message) => message,
+            // sysex and unknown packets
+            Err(_) => return !matches!(self, Stage::DropSysex),
+        };
+        match *self {
+            Stage::Channel(channel) => channel_of(&message).is_none_or(|ch| ch == channel),
+            Stage::DropRealtime => !is_realtime(&message),
+            Stage::DropSysex => !is_sysex(&message),
+            Stage::Remap(channel) => {
+                if let Some(remapped) = remap(message, channel) {
+                    *packet = Packet::from(remapped).with_cable_num(packet.cable_number());
+                }
+                true
+            }
+            Stage::Transpose(semitones) => match transpose(message, semitones) {
+                Some(Some(transposed)) => {
+                    *packet = Packet::from(transposed).with_cable_num(packet.cable_number());
+                    true
+                }
+                // out of range
+                Some(None) => false,
+                // not a note
+                None => true,
+            },
+            Stage::Filter(_) | Stage::Transform(_) => true,
+        }
+    }
+}
+
+fn channel_of(message: &MidiMessage) -> Option<MidiChannel> {
+    match *message {
+        MidiMessage::NoteOff(ch, ..)
+        | MidiMessage::NoteOn(ch, ..)
+        | MidiMessage::NotePressure(ch, ..)
+        | MidiMessage::ChannelPressure(ch, _)
+        | MidiMessage::ProgramChange(ch, _)
+        | MidiMessage::ControlChange(ch, ..)
+        | MidiMessage::PitchBend(ch, _) => Some(ch),
+        _ => None,
+    }
+}
+
+fn is_realtime(message: &MidiMessage) -> bool {
+    matches!(message,
+        MidiMessage::TimingClock | MidiMessage::MeasureEnd(_) | MidiMessage::Start | MidiMessage::Continue
+        | MidiMessage::Stop | MidiMessage::ActiveSensing | MidiMessage::SystemReset)
+}
+
+fn is_sysex(message: &MidiMessage) -> bool {
+    matches!(message,
+        MidiMessage::SysexBegin(..) | MidiMessage::SysexCont(..) | MidiMessage::SysexEnd | MidiMessage::SysexEnd1(_)
+        | MidiMessage::SysexEnd2(..) | MidiMessage::SysexEmpty | MidiMessage::SysexSingleByte(_))
+}
+
+fn remap(message: MidiMessage, ch: MidiChannel) -> Option<MidiMessage> {
+    Some(match message {
+        MidiMessage::NoteOff(_, note, velocity) => MidiMessage::NoteOff(ch, note, velocity),
+        MidiMessage::NoteOn(_, note, velocity) => MidiMessage::NoteOn(ch, note, velocity),
+        MidiMessage::NotePressure(_, note, pressure) => MidiMessage::NotePressure(ch, note, pressure),
+        MidiMessage::ChannelPressure(_, pressure) => MidiMessage::ChannelPressure(ch, pressure),
+        MidiMessage::ProgramChange(_, program) => MidiMessage::ProgramChange(ch, program),
+        MidiMessage::ControlChange(_, control, value) => MidiMessage::ControlChange(ch, control, value),
+        MidiMessage::PitchBend(_, bend) => MidiMessage::PitchBend(ch, bend),
+        _ => return None,
+    })
+}
+
+/// None if message has no note, Some(None) if transposed note is out of range
+fn transpose(message: MidiMessage, semitones: i8) -> Option<Option<MidiMessage>> {
+    Some(match message {
+        MidiMessage::NoteOff(ch, note, velocity) => note.checked_transpose(semitones).map(|n| MidiMessage::NoteOff(ch, n, velocity)),
+        MidiMessage::NoteOn(ch, note, velocity) => note.checked_transpose(semitones).map(|n| MidiMessage::NoteOn(ch, n, velocity)),
+        MidiMessage::NotePressure(ch, note, pressure) => note.checked_transpose(semitones).map(|n| MidiMessage::NotePressure(ch, n, pressure)),
+        _ => return None,
+    })
+}
+
+/// Packets from a source port to destination ports
+#[derive(Clone)]
+pub struct Route {
+    source: PortId,
+    destinations: Vec<PortId, MAX_DESTINATIONS>,
+    stages: Vec<Stage, MAX_STAGES>,
+}
+
+impl Route {
+    /// Route from port, without destinations yet
+    pub fn from(source: PortId) -> Self {
+        Route {
+            source,
+            destinations: Vec::new(),
+            stages: Vec::new(),
+        }
+    }
+
+    /// Route packets from a port to another
+    pub fn link(source: PortId, destination: PortId) -> Self {
+        Route::from(source).to(destination)
+    }
+
+    /// Route packets back to the port they came from
+    pub fn echo(port: PortId) -> Self {
+        Route::link(port, port)
+    }
+
+    /// Add destination, ignored if route has too many destinations
+    pub fn to(mut self, destination: PortId) -> Self {
+        let _ = self.destinations.push(destination);
+        self
+    }
+
+    /// Add stage to the chain, ignored if route has too many stages
+    pub fn with(mut self, stage: Stage) -> Self {
+        let _ = self.stages.push(stage);
+        self
+    }
+
+    pub fn filter(self, filter: FilterFn) -> Self {
+        self.with(Stage::Filter(filter))
+    }
+
+    pub fn transform(self, transform: TransformFn) -> Self {
+        self.with(Stage::Transform(transform))
+    }
+
+    pub fn source(&self) -> PortId {
+        self.source
+    }
+
+    pub fn destinations(&self) -> &[PortId] {
+        &self.destinations
+    }
+
+    /// Apply stages, returns None if packets were dropped
+    fn apply(&self, packets: &PacketList) -> Option<PacketList> {
+        let mut context = RouteContext { source: self.source, packets: packets.clone() };
+        for stage in self.stages.iter() {
+            if !stage.apply(&mut context) || context.packets.is_empty() {
+                return None;
+            }
+        }
+        Some(context.packets)
+    }
+}
+
+/// Route table, routes can be added and removed at any time
+pub struct Router<const N: usize> {
+    routes: Vec<(RouteId, Route), N>,
+    next_id: RouteId,
+}
+
+impl<const N: usize> Default for Router<N> {
+    fn default() -> Self {
+        Router { routes: Vec::new(), next_id: 0 }
+    }
+}
+
+impl<const N: usize> Router<N> {
+    pub fn add(&mut self, route: Route) -> Result<RouteId, MidiError> {
+        let id = self.next_id;
+        self.routes.push((id, route)).map_err(|_| MidiError::TooManyRoutes)?;
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    /// Returns removed route, if it existed
+    pub fn remove(&mut self, id: RouteId) -> Option<Route> {
+        let idx = self.routes.iter().position(|(route_id, _)| *route_id == id)?;
+        Some(self.routes.remove(idx).1)
+    }
+
+    pub fn clear(&mut self) {
+        self.routes.clear()
+    }
+
+    pub fn routes(&self) -> impl Iterator<Item=(RouteId, &Route)> {
+        self.routes.iter().map(|(id, route)| (*id, route))
+    }
+
+    /// Packets from source for each destination, after going through route stages
+    pub fn route<'a>(&'a self, source: PortId, packets: &'a PacketList) -> impl Iterator<Item=(PortId, PacketList)> + 'a {
+        self.routes.iter()
+            .filter(move |(_, route)| route.source == source)
+            .filter_map(move |(_, route)| Some((route, route.apply(packets)?)))
+            .flat_map(|(route, packets)| route.destinations.iter().map(move |dest| (*dest, packets.clone())))
+    }
+
+    /// Write packets from source to the `Out` ports of destinations
+    /// All destinations are attempted, the last error is returned
+    pub fn dispatch(&self, source: PortId, packets: &PacketList, ports: &impl MidiPorts) -> Result<(), MidiError> {
+        let mut result = Ok(());
+        for (destination, packets) in self.route(source, packets) {
+            let handle = match ports.find_port(destination, PortDirection::Out) {
+                Some(handle) => handle,
+                None => {
+                    result = Err(MidiError::InvalidPort);
+                    continue;
+                }
+            };
+            for packet in packets.iter() {
+                if let Err(err) = ports.write(&handle, *packet) {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    /// Dispatch all packets waiting in `In` ports
+    pub fn process(&self, ports: &impl MidiPorts) -> Result<(), MidiError> {
+        let mut result = Ok(());
+        for handle in ports.list_ports() {
+            let info = ports.info(&handle)?;
+            if info.direction != PortDirection::In {
+                continue;
+            }
+            while let Some(packet) = ports.read(&handle)? {
+                if let Err(err) = self.dispatch(info.port_id, &PacketList::single(packet), ports) {
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{channel, MidiRegistry, Note, PortInfo, U7};
+
+    const KEYS: PortId = PortId::Serial(1);
+    const SYNTH: PortId = PortId::Serial(2);
+    const HOST: PortId = PortId::Usb(0);
+
+    fn note_on(ch: u8, note: Note) -> Packet {
+        Packet::from(MidiMessage::NoteOn(channel(ch), note, U7(100)))
+    }
+
+    fn no_program_change(context: &RouteContext) -> bool {
+        !context.packets.iter().any(|p| matches!(MidiMessage::try_from(*p), Ok(MidiMessage::ProgramChange(..))))
+    }
+
+    #[test]
+    fn stages() {
+        let mut router: Router<4> = Router::default();
+        router.add(Route::link(KEYS, SYNTH)
+            .with(Stage::Channel(channel(1)))
+            .with(Stage::Remap(channel(3)))
+            .with(Stage::Transpose(12))
+            .filter(no_program_change)
+        ).unwrap();
+
+        let packets = PacketList::from_iter([note_on(1, Note::C4), note_on(2, Note::D4), Packet::from(MidiMessage::TimingClock)]);
+        let mut routed = router.route(KEYS, &packets);
+        let (dest, out) = routed.next().unwrap();
+        assert_eq!(SYNTH, dest);
+        assert_eq!(&[note_on(3, Note::C5), Packet::from(MidiMessage::TimingClock)], out.as_slice());
+        assert!(routed.next().is_none());
+
+        let pc = PacketList::single(Packet::from(MidiMessage::ProgramChange(channel(1), U7(1))));
+        assert_eq!(0, router.route(KEYS, &pc).count());
+        assert_eq!(0, router.route(SYNTH, &packets).count());
+    }
+
+    #[test]
+    fn add_remove() {
+        let mut router: Router<2> = Router::default();
+        let echo = router.add(Route::echo(HOST)).unwrap();
+        router.add(Route::from(HOST).to(SYNTH).to(KEYS).with(Stage::DropRealtime)).unwrap();
+        assert_eq!(Err(MidiError::TooManyRoutes), router.add(Route::echo(KEYS)));
+
+        let clock = PacketList::single(Packet::from(MidiMessage::TimingClock));
+        assert_eq!(1, router.route(HOST, &clock).count());
+        let notes = PacketList::single(note_on(1, Note::A0));
+        assert_eq!(3, router.route(HOST, &notes).count());
+
+        assert_eq!(HOST, router.remove(echo).unwrap().source());
+        assert!(router.remove(echo).is_none());
+        assert_eq!(2, router.route(HOST, &notes).count());
+    }
+
+    #[test]
+    fn process_registry() {
+        let registry: MidiRegistry<4> = MidiRegistry::default();
+        let keys_in = registry.acquire_port(PortInfo::new(KEYS, PortDirection::In)).unwrap();
+        let synth_out = registry.acquire_port(PortInfo::new(SYNTH, PortDirection::Out)).unwrap();
+        let mut router: Router<4> = Router::default();
+        router.add(Route::link(KEYS, SYNTH)).unwrap();
+
+        registry.write(&keys_in, note_on(1, Note::C4)).unwrap();
+        registry.write(&keys_in, note_on(1, Note::E4)).unwrap();
+        router.process(&registry).unwrap();
+        assert_eq!(None, registry.read(&keys_in).unwrap());
+        assert_eq!(Some(note_on(1, Note::C4)), registry.read(&synth_out).unwrap());
+        assert_eq!(Some(note_on(1, Note::E4)), registry.read(&synth_out).unwrap());
+
+        router.add(Route::link(KEYS, HOST)).unwrap();
+        registry.write(&keys_in, note_on(1, Note::C4)).unwrap();
+        assert_eq!(Err(MidiError::InvalidPort), router.process(&registry));
+        assert_eq!(Some(note_on(1, Note::C4)), registry.read(&synth_out).unwrap());
+    }
+}
@@ -0,0 +1,213 @@
+//! MIDI over any UART implementing `embedded_hal::serial` traits
+
+use embedded_hal::serial::{Read, Write};
+
+use crate::codec::ByteCodec;
+use crate::{CableNumber, MidiError, Packet, PacketList, PortStats, Receive, Transmit};
+
+/// Output buffer size, in bytes
+const TX_FIFO_LEN: usize = 64;
+
+/// Serial MIDI port, parsing received bytes to packets and serializing packets to transmit
+/// Bytes are written as long as the UART accepts them, call `flush()` again when UART is ready for more
+pub struct SerialMidiPort<RX, TX> {
+    rx: RX,
+    tx: TX,
+    codec: ByteCodec<TX_FIFO_LEN>,
+    // byte the UART was not ready for
+    pending: Option<u8>,
+}
+
+impl<RX, TX> SerialMidiPort<RX, TX> {
+    pub fn new(rx: RX, tx: TX) -> Self {
+        SerialMidiPort {
+            rx,
+            tx,
+            codec: ByteCodec::default(),
+            pending: None,
+        }
+    }
+
+    /// Omit repeated channel status bytes when transmitting (default: true)
+    pub fn with_running_status(mut self, running_status: bool) -> Self {
+        self.codec = self.codec.with_running_status(running_status);
+        self
+    }
+
+    /// Cable number of received packets (default: 0)
+    pub fn with_cable_num(mut self, cable_number: CableNumber) -> Self {
+        self.codec = self.codec.with_cable_num(cable_number);
+        self
+    }
+
+    /// Clock used to timestamp port activity, e.g. millis since boot
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.codec = self.codec.with_clock(clock);
+        self
+    }
+
+    pub fn rx_mut(&mut self) -> &mut RX {
+        &mut self.rx
+    }
+
+    pub fn tx_mut(&mut self) -> &mut TX {
+        &mut self.tx
+    }
+
+    /// Release UART halves
+    pub fn free(self) -> (RX, TX) {
+        (self.rx, self.tx)
+    }
+
+    /// Snapshot of port counters, packets out are counted when buffered for transmission
+    pub fn stats(&self) -> PortStats {
+        self.codec.stats()
+    }
+
+    /// Zero port counters, returning their last values
+    pub fn reset_stats(&mut self) -> PortStats {
+        self.codec.reset_stats()
+    }
+
+    /// True if all bytes were written to UART
+    pub fn is_flushed(&self) -> bool {
+        self.pending.is_none() && self.codec.is_empty()
+    }
+}
+
+impl<RX, TX: Write<u8>> SerialMidiPort<RX, TX> {
+    /// Write buffered bytes until UART would block
+    pub fn flush(&mut self) -> Result<(), MidiError> {
+        while let Some(byte) = self.pending.take().or_else(|| self.codec.next_byte()) {
+            match self.tx.write(byte) {
+                Ok(()) => {}
+                Err(nb::Error::WouldBlock) => {
+                    self.pending = Some(byte);
+                    return Ok(());
+                }
+                Err(nb::Error::Other(_)) => return Err(self.codec.port_error()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<RX: Read<u8>, TX> Receive for SerialMidiPort<RX, TX> {
+    /// Read bytes until a packet is complete or no more bytes are available
+    fn receive(&mut self) -> Result<Option<Packet>, MidiError> {
+        if let Some(packet) = self.codec.take_pending() {
+            return Ok(Some(packet));
+        }
+        loop {
+            let byte = match self.rx.read() {
+                Ok(byte) => byte,
+                Err(nb::Error::WouldBlock) => return Ok(None),
+                Err(nb::Error::Other(_)) => return Err(self.codec.port_error()),
+            };
+            if let Some(packet) = self.codec.advance(byte)? {
+                return Ok(Some(packet));
+            }
+        }
+    }
+}
+
+impl<RX, TX: Write<u8>> Transmit for SerialMidiPort<RX, TX> {
+    /// Packets that do not fit in output buffer are not sent and Err(BufferFull) is returned
+    /// They are not counted as dropped, callers usually retry them once the UART caught up
+    fn transmit(&mut self, packets: PacketList) -> Result<(), MidiError> {
+        let result = self.codec.push(&packets);
+        self.flush()?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use heapless::{Deque, Vec};
+    use crate::{channel, MidiMessage, Note, U7};
+
+    /// Mock UART, receives bytes from a script and accepts a limited number of bytes before blocking
+    #[derive(Default)]
+    struct MockUart {
+        incoming: Deque<u8, 64>,
+        written: Vec<u8, 256>,
+        ready: usize,
+    }
+
+    impl Read<u8> for MockUart {
+        type Error = Infallible;
+
+        fn read(&mut self) -> nb::Result<u8, Infallible> {
+            self.incoming.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    impl Write<u8> for MockUart {
+        type Error = Infallible;
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Infallible> {
+            if self.ready == 0 {
+                return Err(nb::Error::WouldBlock);
+            }
+            self.ready -= 1;
+            self.written.push(byte).unwrap();
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    fn note_on(note: Note) -> Packet {
+        Packet::from(MidiMessage::NoteOn(channel(1), note, U7(100)))
+    }
+
+    #[test]
+    fn receive_with_running_status() {
+        let mut rx = MockUart::default();
+        for byte in [0x90, 60, 100, 62, 100, 0xF8, 64] {
+            rx.incoming.push_back(byte).unwrap();
+        }
+        fn clock() -> u64 { 42 }
+        let mut port = SerialMidiPort::new(rx, MockUart::default()).with_cable_num(1).with_clock(clock);
+        assert_eq!(Some(note_on(Note::C4).with_cable_num(1)), port.receive().unwrap());
+        assert_eq!(Some(note_on(Note::D4).with_cable_num(1)), port.receive().unwrap());
+        assert_eq!(Some(Packet::from(MidiMessage::TimingClock).with_cable_num(1)), port.receive().unwrap());
+        // incomplete message
+        assert_eq!(None, port.receive().unwrap());
+        assert_eq!(3, port.stats().packets_in);
+        assert_eq!(7, port.stats().bytes_in);
+        assert_eq!(Some(42), port.stats().last_activity);
+    }
+
+    #[test]
+    fn transmit_when_uart_ready() {
+        let tx = MockUart { ready: 4, ..MockUart::default() };
+        let mut port = SerialMidiPort::new(MockUart::default(), tx);
+        port.transmit(PacketList::from_iter([note_on(Note::C4), note_on(Note::D4)])).unwrap();
+        assert_eq!(&[0x90, 60, 100, 62], port.tx_mut().written.as_slice());
+        assert!(!port.is_flushed());
+        assert_eq!(None, port.stats().last_activity);
+
+        port.tx_mut().ready = 10;
+        port.flush().unwrap();
+        assert!(port.is_flushed());
+        let (_rx, tx) = port.free();
+        assert_eq!(&[0x90, 60, 100, 62, 100], tx.written.as_slice());
+    }
+
+    #[test]
+    fn full_buffer_is_not_dropped() {
+        let mut port = SerialMidiPort::new(MockUart::default(), MockUart::default());
+        let mut sent = 0;
+        while port.transmit(PacketList::single(note_on(Note::C4))).is_ok() {
+            sent += 1;
+        }
+        assert_eq!(Err(MidiError::BufferFull), port.transmit(PacketList::single(note_on(Note::C4))));
+        assert_eq!(sent, port.stats().packets_out);
+        assert_eq!(0, port.stats().dropped);
+    }
+}
@@ -0,0 +1,194 @@
+use heapless::spsc::Queue;
+
+use crate::status::{is_channel_status, is_realtime};
+use crate::{MidiError, Packet};
+
+/// Max number of pending realtime bytes
+const REALTIME_QUEUE_LEN: usize = 8;
+
+/// Turns USB Event Packets back into a MIDI byte stream, for Serial (DIN) output
+/// Counterpart of `PacketParser`, `N` is the size of the output FIFO in bytes
+/// - Running status drops the status byte of consecutive channel messages with the same status
+/// - Status refresh periodically resends the status byte, so receivers that missed it can resync
+/// - Realtime bytes jump the queue, being sent between bytes of other messages if needed
+#[derive(Debug)]
+pub struct PacketSerializer<const N: usize> {
+    running_status: bool,
+    refresh_every: Option<u16>,
+    last_status: Option<u8>,
+    // messages sent since last status byte
+    since_status: u16,
+    realtime: Queue<u8, REALTIME_QUEUE_LEN>,
+    fifo: Queue<u8, N>,
+}
+
+impl<const N: usize> Default for PacketSerializer<N> {
+    fn default() -> Self {
+        PacketSerializer {
+            running_status: true,
+            refresh_every: None,
+            last_status: None,
+            since_status: 0,
+            realtime: Queue::new(),
+            fifo: Queue::new(),
+        }
+    }
+}
+
+impl<const N: usize> PacketSerializer<N> {
+    /// Omit repeated channel status bytes (default: true)
+    pub fn with_running_status(mut self, running_status: bool) -> Self {
+        self.running_status = running_status;
+        self
+    }
+
+    /// Resend status byte after `messages` consecutive running status messages (default: never)
+    pub fn with_status_refresh(mut self, messages: u16) -> Self {
+        self.refresh_every = Some(messages);
+        self
+    }
+
+    /// Force next channel message to be sent with its status byte
+    /// Should be called after a pause in transmission, or if the receiver may have lost sync
+    pub fn reset_status(&mut self) {
+        self.last_status = None;
+    }
+
+    /// Enqueue packet bytes for output
+    /// Either all the bytes of the packet are enqueued, or none are and Err(BufferFull) is returned
+    pub fn push(&mut self, packet: Packet) -> Result<(), MidiError> {
+        let payload = packet.payload();
+        if payload.is_empty() {
+            return Ok(());
+        }
+
+        if is_realtime(payload[0]) {
+            // realtime does not affect running status
+            return self.realtime.enqueue(payload[0]).map_err(|_| MidiError::BufferFull);
+        }
+
+        let status = payload[0];
+        let channel_status = is_channel_status(status);
+        let mut payload = payload;
+        if channel_status && self.running_status && self.last_status == Some(status) {
+            let refresh_due = self.refresh_every.is_some_and(|every| self.since_status >= every);
+            if !refresh_due {
+                // same status as last time, chop out status byte
+                payload = &payload[1..];
+            }
+        }
+
+        if self.space() < payload.len() {
+            return Err(MidiError::BufferFull);
+        }
+        for byte in payload {
+            // space was checked
+            let _ = self.fifo.enqueue(*byte);
+        }
+
+        if !channel_status {
+            // non-repeatable status or no status (sysex)
+            self.last_status = None;
+        } else if payload[0] == status {
+            self.last_status = Some(status);
+            self.since_status = 0;
+        } else if self.refresh_every.is_some() {
+            self.since_status = self.since_status.saturating_add(1);
+        }
+        Ok(())
+    }
+
+    /// Next byte to send, realtime bytes first
+    pub fn next_byte(&mut self) -> Option<u8> {
+        self.realtime.dequeue().or_else(|| self.fifo.dequeue())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.realtime.is_empty() && self.fifo.is_empty()
+    }
+
+    /// Space left in output FIFO, in bytes
+    pub fn space(&self) -> usize {
+        self.fifo.capacity() - self.fifo.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{channel, MidiMessage, Note, U7};
+
+    fn drain<const N: usize>(ser: &mut PacketSerializer<N>) -> heapless::Vec<u8, 32> {
+        let mut bytes = heapless::Vec::new();
+        while let Some(byte) = ser.next_byte() {
+            bytes.push(byte).unwrap();
+        }
+        bytes
+    }
+
+    fn note_on(note: Note) -> Packet {
+        MidiMessage::NoteOn(channel(1), note, U7(64)).into()
+    }
+
+    #[test]
+    fn running_status() {
+        let mut ser: PacketSerializer<32> = PacketSerializer::default();
+        ser.push(note_on(Note::C4)).unwrap();
+        ser.push(note_on(Note::D4)).unwrap();
+        ser.push(MidiMessage::NoteOn(channel(2), Note::C4, U7(64)).into()).unwrap();
+        assert_eq!(&[0x90, 60, 64, 62, 64, 0x91, 60, 64], drain(&mut ser).as_slice());
+
+        let mut ser: PacketSerializer<32> = PacketSerializer::default().with_running_status(false);
+        ser.push(note_on(Note::C4)).unwrap();
+        ser.push(note_on(Note::D4)).unwrap();
+        assert_eq!(&[0x90, 60, 64, 0x90, 62, 64], drain(&mut ser).as_slice());
+    }
+
+    #[test]
+    fn status_refresh() {
+        let mut ser: PacketSerializer<32> = PacketSerializer::default().with_status_refresh(2);
+        for _ in 0..4 {
+            ser.push(note_on(Note::C4)).unwrap();
+        }
+        assert_eq!(&[0x90, 60, 64, 60, 64, 60, 64, 0x90, 60, 64], drain(&mut ser).as_slice());
+    }
+
+    #[test]
+    fn long_running_status_stream() {
+        for mut ser in [PacketSerializer::<4>::default(), PacketSerializer::<4>::default().with_status_refresh(u16::MAX)] {
+            let pressure = Packet::from(MidiMessage::ChannelPressure(channel(1), U7(64)));
+            for _ in 0..70_000 {
+                ser.push(pressure).unwrap();
+                while ser.next_byte().is_some() {}
+            }
+        }
+    }
+
+    #[test]
+    fn sysex_cancels_running_status() {
+        let mut ser: PacketSerializer<32> = PacketSerializer::default();
+        ser.push(note_on(Note::C4)).unwrap();
+        ser.push(MidiMessage::SysexSingleByte(0x42).into()).unwrap();
+        ser.push(note_on(Note::C4)).unwrap();
+        assert_eq!(&[0x90, 60, 64, 0xF0, 0x42, 0xF7, 0x90, 60, 64], drain(&mut ser).as_slice());
+    }
+
+    #[test]
+    fn realtime_priority() {
+        let mut ser: PacketSerializer<32> = PacketSerializer::default();
+        ser.push(note_on(Note::C4)).unwrap();
+        assert_eq!(Some(0x90), ser.next_byte());
+        ser.push(MidiMessage::TimingClock.into()).unwrap();
+        ser.push(note_on(Note::D4)).unwrap();
+        assert_eq!(&[0xF8, 60, 64, 62, 64], drain(&mut ser).as_slice());
+    }
+
+    #[test]
+    fn full_packet_or_nothing() {
+        let mut ser: PacketSerializer<4> = PacketSerializer::default();
+        ser.push(note_on(Note::C4)).unwrap();
+        assert!(ser.push(MidiMessage::NoteOn(channel(2), Note::C4, U7(64)).into()).is_err());
+        assert_eq!(&[0x90, 60, 64], drain(&mut ser).as_slice());
+        assert!(ser.is_empty());
+    }
+}
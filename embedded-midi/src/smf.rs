@@ -0,0 +1,820 @@
+//! Standard MIDI File (SMF) reader and writer
+//! Supports format 0 (single track) and format 1 (multiple simultaneous tracks)
+//! Reading is zero-copy: text, sysex and unknown meta payloads borrow from the file buffer.
+//! Writing is done into a caller-provided byte buffer.
+
+use core::convert::TryFrom;
+
+use heapless::Vec;
+
+use crate::status::{is_channel_status, SYSEX_END, SYSEX_START};
+use crate::{CodeIndexNumber, MidiError, MidiMessage, Packet, Status};
+
+const HEADER_CHUNK: &[u8; 4] = b"MThd";
+const TRACK_CHUNK: &[u8; 4] = b"MTrk";
+const HEADER_LEN: u32 = 6;
+
+const META: u8 = 0xFF;
+const ESCAPE: u8 = SYSEX_END;
+
+const META_SEQUENCE_NUMBER: u8 = 0x00;
+const META_TEXT: u8 = 0x01;
+const META_COPYRIGHT: u8 = 0x02;
+const META_TRACK_NAME: u8 = 0x03;
+const META_INSTRUMENT_NAME: u8 = 0x04;
+const META_LYRIC: u8 = 0x05;
+const META_MARKER: u8 = 0x06;
+const META_CUE_POINT: u8 = 0x07;
+const META_CHANNEL_PREFIX: u8 = 0x20;
+const META_PORT: u8 = 0x21;
+const META_END_OF_TRACK: u8 = 0x2F;
+const META_TEMPO: u8 = 0x51;
+const META_SMPTE_OFFSET: u8 = 0x54;
+const META_TIME_SIGNATURE: u8 = 0x58;
+const META_KEY_SIGNATURE: u8 = 0x59;
+const META_SEQUENCER_SPECIFIC: u8 = 0x7F;
+
+/// Largest value representable as a variable-length quantity (4 bytes)
+pub const VLQ_MAX: u32 = 0x0FFF_FFFF;
+
+/// Default tempo if none specified, 120 BPM
+pub const DEFAULT_TEMPO: u32 = 500_000;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u16)]
+pub enum Format {
+    /// Single multi-channel track
+    SingleTrack = 0,
+    /// One or more simultaneous tracks
+    MultiTrack = 1,
+}
+
+impl TryFrom<u16> for Format {
+    type Error = MidiError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Format::SingleTrack),
+            1 => Ok(Format::MultiTrack),
+            _ => Err(MidiError::UnsupportedFormat),
+        }
+    }
+}
+
+/// Meaning of delta times
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Division {
+    /// Musical time, ticks per quarter note
+    TicksPerQuarter(u16),
+    /// Absolute time, SMPTE frames per second (24, 25, 29 or 30) and ticks per frame
+    Smpte(u8, u8),
+}
+
+impl From<u16> for Division {
+    fn from(value: u16) -> Self {
+        if value & 0x8000 == 0 {
+            Division::TicksPerQuarter(value)
+        } else {
+            let [fps, ticks] = value.to_be_bytes();
+            Division::Smpte((fps as i8).unsigned_abs(), ticks)
+        }
+    }
+}
+
+impl From<Division> for u16 {
+    fn from(value: Division) -> Self {
+        match value {
+            Division::TicksPerQuarter(tpq) => tpq & 0x7FFF,
+            Division::Smpte(fps, ticks) => u16::from_be_bytes([(-(fps as i8)) as u8, ticks]),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MetaEvent<'a> {
+    SequenceNumber(u16),
+    Text(&'a [u8]),
+    Copyright(&'a [u8]),
+    TrackName(&'a [u8]),
+    InstrumentName(&'a [u8]),
+    Lyric(&'a [u8]),
+    Marker(&'a [u8]),
+    CuePoint(&'a [u8]),
+    /// MIDI channel (0-15) of subsequent sysex and meta events
+    ChannelPrefix(u8),
+    Port(u8),
+    EndOfTrack,
+    /// Microseconds per quarter note
+    Tempo(u32),
+    /// Hours, minutes, seconds, frames, fractional frames (1/100)
+    SmpteOffset(u8, u8, u8, u8, u8),
+    /// Numerator, denominator as power of 2, MIDI clocks per metronome click, 32nds per quarter note
+    TimeSignature(u8, u8, u8, u8),
+    /// Number of sharps (negative for flats), minor key
+    KeySignature(i8, bool),
+    SequencerSpecific(&'a [u8]),
+    /// Meta type and raw data
+    Unknown(u8, &'a [u8]),
+}
+
+impl<'a> MetaEvent<'a> {
+    fn parse(meta_type: u8, data: &'a [u8]) -> Result<Self, MidiError> {
+        let expect = |len: usize| if data.len() < len { Err(MidiError::InvalidMetaEvent(meta_type)) } else { Ok(()) };
+        Ok(match meta_type {
+            META_SEQUENCE_NUMBER => {
+                expect(2)?;
+                MetaEvent::SequenceNumber(u16::from_be_bytes([data[0], data[1]]))
+            }
+            META_TEXT => MetaEvent::Text(data),
+            META_COPYRIGHT => MetaEvent::Copyright(data),
+            META_TRACK_NAME => MetaEvent::TrackName(data),
+            META_INSTRUMENT_NAME => MetaEvent::InstrumentName(data),
+            META_LYRIC => MetaEvent::Lyric(data),
+            META_MARKER => MetaEvent::Marker(data),
+            META_CUE_POINT => MetaEvent::CuePoint(data),
+            META_CHANNEL_PREFIX => {
+                expect(1)?;
+                MetaEvent::ChannelPrefix(data[0])
+            }
+            META_PORT => {
+                expect(1)?;
+                MetaEvent::Port(data[0])
+            }
+            META_END_OF_TRACK => MetaEvent::EndOfTrack,
+            META_TEMPO => {
+                expect(3)?;
+                MetaEvent::Tempo(u32::from_be_bytes([0, data[0], data[1], data[2]]))
+            }
+            META_SMPTE_OFFSET => {
+                expect(5)?;
+                MetaEvent::SmpteOffset(data[0], data[1], data[2], data[3], data[4])
+            }
+            META_TIME_SIGNATURE => {
+                expect(4)?;
+                MetaEvent::TimeSignature(data[0], data[1], data[2], data[3])
+            }
+            META_KEY_SIGNATURE => {
+                expect(2)?;
+                MetaEvent::KeySignature(data[0] as i8, data[1] != 0)
+            }
+            META_SEQUENCER_SPECIFIC => MetaEvent::SequencerSpecific(data),
+            _ => MetaEvent::Unknown(meta_type, data),
+        })
+    }
+
+    /// Returns the meta type byte and event data
+    /// Fixed-size data is encoded into `scratch`
+    fn encode<'b>(&'b self, scratch: &'b mut [u8; 5]) -> (u8, &'b [u8]) {
+        match *self {
+            MetaEvent::SequenceNumber(num) => {
+                scratch[..2].copy_from_slice(&num.to_be_bytes());
+                (META_SEQUENCE_NUMBER, &scratch[..2])
+            }
+            MetaEvent::Text(text) => (META_TEXT, text),
+            MetaEvent::Copyright(text) => (META_COPYRIGHT, text),
+            MetaEvent::TrackName(text) => (META_TRACK_NAME, text),
+            MetaEvent::InstrumentName(text) => (META_INSTRUMENT_NAME, text),
+            MetaEvent::Lyric(text) => (META_LYRIC, text),
+            MetaEvent::Marker(text) => (META_MARKER, text),
+            MetaEvent::CuePoint(text) => (META_CUE_POINT, text),
+            MetaEvent::ChannelPrefix(ch) => {
+                scratch[0] = ch;
+                (META_CHANNEL_PREFIX, &scratch[..1])
+            }
+            MetaEvent::Port(port) => {
+                scratch[0] = port;
+                (META_PORT, &scratch[..1])
+            }
+            MetaEvent::EndOfTrack => (META_END_OF_TRACK, &[]),
+            MetaEvent::Tempo(tempo) => {
+                scratch[..3].copy_from_slice(&tempo.to_be_bytes()[1..]);
+                (META_TEMPO, &scratch[..3])
+            }
+            MetaEvent::SmpteOffset(hr, mn, se, fr, ff) => {
+                *scratch = [hr, mn, se, fr, ff];
+                (META_SMPTE_OFFSET, &scratch[..5])
+            }
+            MetaEvent::TimeSignature(nn, dd, cc, bb) => {
+                scratch[..4].copy_from_slice(&[nn, dd, cc, bb]);
+                (META_TIME_SIGNATURE, &scratch[..4])
+            }
+            MetaEvent::KeySignature(sf, minor) => {
+                scratch[..2].copy_from_slice(&[sf as u8, minor as u8]);
+                (META_KEY_SIGNATURE, &scratch[..2])
+            }
+            MetaEvent::SequencerSpecific(data) => (META_SEQUENCER_SPECIFIC, data),
+            MetaEvent::Unknown(meta_type, data) => (meta_type, data),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EventKind<'a> {
+    /// Channel message
+    Midi(MidiMessage),
+    /// Sysex message (F0 event), data _excludes_ SYSEX_START but includes SYSEX_END if present
+    Sysex(&'a [u8]),
+    /// Escaped raw bytes (F7 event), sysex continuation or system messages
+    Escape(&'a [u8]),
+    Meta(MetaEvent<'a>),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TrackEvent<'a> {
+    /// Ticks since previous event in track
+    pub delta: u32,
+    pub kind: EventKind<'a>,
+}
+
+/// Decode a variable-length quantity
+/// Returns the value and the number of bytes read
+pub fn read_vlq(bytes: &[u8]) -> Result<(u32, usize), MidiError> {
+    let mut value: u32 = 0;
+    for (i, byte) in bytes.iter().take(4).enumerate() {
+        value = (value << 7) | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    if bytes.len() < 4 {
+        Err(MidiError::TruncatedData)
+    } else {
+        Err(MidiError::InvalidInteger)
+    }
+}
+
+/// Encode a variable-length quantity
+/// Returns the encoded bytes and their count
+pub fn vlq(value: u32) -> Result<([u8; 4], usize), MidiError> {
+    if value > VLQ_MAX {
+        return Err(MidiError::InvalidInteger);
+    }
+    let mut bytes = [0; 4];
+    let mut len = 1;
+    while len < 4 && value >> (7 * len) != 0 {
+        len += 1;
+    }
+    for (i, byte) in bytes[..len].iter_mut().enumerate() {
+        let shift = 7 * (len - 1 - i);
+        *byte = ((value >> shift) & 0x7F) as u8;
+        if i < len - 1 {
+            *byte |= 0x80;
+        }
+    }
+    Ok((bytes, len))
+}
+
+/// Byte cursor over file data
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn peek(&self) -> Result<u8, MidiError> {
+        self.bytes.get(self.pos).copied().ok_or(MidiError::TruncatedData)
+    }
+
+    fn u8(&mut self) -> Result<u8, MidiError> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn slice(&mut self, len: usize) -> Result<&'a [u8], MidiError> {
+        let end = self.pos.checked_add(len).ok_or(MidiError::TruncatedData)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(MidiError::TruncatedData)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, MidiError> {
+        let s = self.slice(2)?;
+        Ok(u16::from_be_bytes([s[0], s[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, MidiError> {
+        let s = self.slice(4)?;
+        Ok(u32::from_be_bytes([s[0], s[1], s[2], s[3]]))
+    }
+
+    fn vlq(&mut self) -> Result<u32, MidiError> {
+        let (value, len) = read_vlq(&self.bytes[self.pos.min(self.bytes.len())..])?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    /// Returns chunk type and data
+    fn chunk(&mut self) -> Result<(&'a [u8], &'a [u8]), MidiError> {
+        let chunk_type = self.slice(4)?;
+        let len = self.u32()? as usize;
+        Ok((chunk_type, self.slice(len)?))
+    }
+}
+
+/// A parsed Standard MIDI File, borrowing the file data
+#[derive(Copy, Clone, Debug)]
+pub struct Smf<'a> {
+    pub format: Format,
+    pub track_count: u16,
+    pub division: Division,
+    tracks: &'a [u8],
+}
+
+impl<'a> Smf<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, MidiError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let (chunk_type, header) = reader.chunk()?;
+        if chunk_type != HEADER_CHUNK || header.len() < HEADER_LEN as usize {
+            return Err(MidiError::InvalidChunk);
+        }
+        let mut header = Reader { bytes: header, pos: 0 };
+        let format = Format::try_from(header.u16()?)?;
+        let track_count = header.u16()?;
+        let division = Division::from(header.u16()?);
+        if format == Format::SingleTrack && track_count != 1 {
+            return Err(MidiError::InvalidChunk);
+        }
+        Ok(Smf { format, track_count, division, tracks: &bytes[reader.pos..] })
+    }
+
+    /// Iterate over track chunks, skipping unknown chunk types
+    pub fn tracks(&self) -> Tracks<'a> {
+        Tracks { reader: Reader { bytes: self.tracks, pos: 0 } }
+    }
+}
+
+pub struct Tracks<'a> {
+    reader: Reader<'a>,
+}
+
+impl<'a> Iterator for Tracks<'a> {
+    type Item = Result<Track<'a>, MidiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.reader.is_empty() {
+            match self.reader.chunk() {
+                Ok((chunk_type, data)) if chunk_type == TRACK_CHUNK => return Some(Ok(Track { data })),
+                // alien chunks must be ignored
+                Ok(_) => continue,
+                Err(err) => {
+                    self.reader.pos = self.reader.bytes.len();
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Track<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Track<'a> {
+    pub fn events(&self) -> TrackEvents<'a> {
+        TrackEvents { reader: Reader { bytes: self.data, pos: 0 }, running_status: None }
+    }
+}
+
+/// Iterates over events of a track
+/// Iteration ends after the first error or after the End Of Track meta event
+pub struct TrackEvents<'a> {
+    reader: Reader<'a>,
+    running_status: Option<u8>,
+}
+
+impl<'a> TrackEvents<'a> {
+    fn read_event(&mut self) -> Result<TrackEvent<'a>, MidiError> {
+        let delta = self.reader.vlq()?;
+        let mut status = self.reader.peek()?;
+        let kind = match status {
+            META => {
+                self.reader.u8()?;
+                self.running_status = None;
+                let meta_type = self.reader.u8()?;
+                let len = self.reader.vlq()? as usize;
+                let meta = MetaEvent::parse(meta_type, self.reader.slice(len)?)?;
+                if meta == MetaEvent::EndOfTrack {
+                    self.reader.pos = self.reader.bytes.len();
+                }
+                EventKind::Meta(meta)
+            }
+            SYSEX_START | ESCAPE => {
+                self.reader.u8()?;
+                self.running_status = None;
+                let len = self.reader.vlq()? as usize;
+                let data = self.reader.slice(len)?;
+                if status == SYSEX_START {
+                    EventKind::Sysex(data)
+                } else {
+                    EventKind::Escape(data)
+                }
+            }
+            _ => {
+                if is_channel_status(status) {
+                    self.reader.u8()?;
+                    self.running_status = Some(status);
+                } else if let (Some(running), false) = (self.running_status, status & 0x80 != 0) {
+                    status = running;
+                } else {
+                    return Err(MidiError::InvalidStatus(status));
+                }
+                EventKind::Midi(self.read_message(status)?)
+            }
+        };
+        Ok(TrackEvent { delta, kind })
+    }
+
+    fn read_message(&mut self, status: u8) -> Result<MidiMessage, MidiError> {
+        let len = Status::try_from(status)?.expected_len() as usize;
+        let mut bytes = [0; 4];
+        bytes[0] = CodeIndexNumber::from(Status::try_from(status)?) as u8;
+        bytes[1] = status;
+        bytes[2..1 + len].copy_from_slice(self.reader.slice(len - 1)?);
+        MidiMessage::try_from(Packet::from_raw(bytes))
+    }
+}
+
+impl<'a> Iterator for TrackEvents<'a> {
+    type Item = Result<TrackEvent<'a>, MidiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.reader.is_empty() {
+            return None;
+        }
+        let event = self.read_event();
+        if event.is_err() {
+            self.reader.pos = self.reader.bytes.len();
+        }
+        Some(event)
+    }
+}
+
+/// Tempo changes by absolute tick, used to convert ticks to wall time
+/// Holds up to N tempo changes
+#[derive(Debug, Clone)]
+pub struct TempoMap<const N: usize> {
+    division: Division,
+    // (tick, microseconds per quarter)
+    changes: Vec<(u32, u32), N>,
+}
+
+impl<const N: usize> TempoMap<N> {
+    pub fn new(division: Division) -> Self {
+        TempoMap { division, changes: Vec::new() }
+    }
+
+    /// Build tempo map from the first track of the file (conductor track)
+    /// Format 0 files hold tempo events in their single track
+    pub fn from_smf(smf: &Smf) -> Result<Self, MidiError> {
+        let mut map = TempoMap::new(smf.division);
+        if let Some(track) = smf.tracks().next() {
+            let mut tick: u32 = 0;
+            for event in track?.events() {
+                let event = event?;
+                tick = tick.saturating_add(event.delta);
+                if let EventKind::Meta(MetaEvent::Tempo(tempo)) = event.kind {
+                    map.push(tick, tempo)?;
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    /// Add a tempo change, returns Err(OutOfOrder) if tick is before the last change
+    pub fn push(&mut self, tick: u32, tempo: u32) -> Result<(), MidiError> {
+        if let Some(last) = self.changes.last_mut() {
+            if tick < last.0 {
+                return Err(MidiError::OutOfOrder);
+            }
+            if last.0 == tick {
+                // later event at same tick wins
+                last.1 = tempo;
+                return Ok(());
+            }
+        }
+        self.changes.push((tick, tempo)).map_err(|_| MidiError::BufferFull)
+    }
+
+    /// Tempo in effect at tick, in microseconds per quarter note
+    pub fn tempo_at(&self, tick: u32) -> u32 {
+        self.changes.iter()
+            .take_while(|(at, _)| *at <= tick)
+            .last()
+            .map(|(_, tempo)| *tempo)
+            .unwrap_or(DEFAULT_TEMPO)
+    }
+
+    /// Convert absolute tick to microseconds since start
+    pub fn micros_at(&self, tick: u32) -> u64 {
+        match self.division {
+            Division::Smpte(fps, ticks_per_frame) => {
+                let ticks_per_sec = fps as u64 * ticks_per_frame as u64;
+                if ticks_per_sec == 0 {
+                    return 0;
+                }
+                // 29 stands for 29.97 drop-frame
+                let ticks_per_sec = if fps == 29 { ticks_per_sec * 2997 / 2900 } else { ticks_per_sec };
+                tick as u64 * 1_000_000 / ticks_per_sec
+            }
+            Division::TicksPerQuarter(tpq) => {
+                let tpq = tpq.max(1) as u64;
+                let mut micros = 0;
+                let mut last_tick = 0;
+                let mut tempo = DEFAULT_TEMPO;
+                for &(at, new_tempo) in self.changes.iter().take_while(|(at, _)| *at <= tick) {
+                    micros += (at - last_tick) as u64 * tempo as u64 / tpq;
+                    last_tick = at;
+                    tempo = new_tempo;
+                }
+                micros + (tick - last_tick) as u64 * tempo as u64 / tpq
+            }
+        }
+    }
+}
+
+/// Writes a Standard MIDI File into a byte buffer
+/// Tracks are written one at a time: `begin_track()`, events, then `end_track()`
+pub struct SmfWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+    // start of current track chunk data, if any
+    format: Format,
+    track_start: Option<usize>,
+    tracks_written: u16,
+    running_status: bool,
+    last_status: Option<u8>,
+}
+
+impl<'a> SmfWriter<'a> {
+    /// Track count in header is set by `finish()`
+    pub fn new(buffer: &'a mut [u8], format: Format, division: Division) -> Result<Self, MidiError> {
+        let mut writer = SmfWriter {
+            buffer,
+            len: 0,
+            format,
+            track_start: None,
+            tracks_written: 0,
+            running_status: true,
+            last_status: None,
+        };
+        writer.write(HEADER_CHUNK)?;
+        writer.write(&HEADER_LEN.to_be_bytes())?;
+        writer.write(&(format as u16).to_be_bytes())?;
+        writer.write(&[0; 2])?;
+        writer.write(&u16::from(division).to_be_bytes())?;
+        Ok(writer)
+    }
+
+    /// Omit repeated channel status bytes (default: true)
+    pub fn with_running_status(mut self, running_status: bool) -> Self {
+        self.running_status = running_status;
+        self
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), MidiError> {
+        let end = self.len + bytes.len();
+        if end > self.buffer.len() {
+            return Err(MidiError::BufferFull);
+        }
+        self.buffer[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn write_vlq(&mut self, value: u32) -> Result<(), MidiError> {
+        let (bytes, len) = vlq(value)?;
+        self.write(&bytes[..len])
+    }
+
+    fn in_track(&self) -> Result<(), MidiError> {
+        self.track_start.map(|_| ()).ok_or(MidiError::InvalidChunk)
+    }
+
+    pub fn begin_track(&mut self) -> Result<(), MidiError> {
+        if self.track_start.is_some() || (self.format == Format::SingleTrack && self.tracks_written > 0) {
+            return Err(MidiError::InvalidChunk);
+        }
+        self.write(TRACK_CHUNK)?;
+        // length patched by end_track()
+        self.write(&[0; 4])?;
+        self.track_start = Some(self.len);
+        self.last_status = None;
+        Ok(())
+    }
+
+    /// Write End Of Track meta event and close track chunk
+    pub fn end_track(&mut self) -> Result<(), MidiError> {
+        self.end_track_after(0)
+    }
+
+    /// Write End Of Track meta event `delta` ticks after last event and close track chunk
+    pub fn end_track_after(&mut self, delta: u32) -> Result<(), MidiError> {
+        self.meta(delta, MetaEvent::EndOfTrack)?;
+        let start = self.track_start.take().ok_or(MidiError::InvalidChunk)?;
+        let chunk_len = (self.len - start) as u32;
+        self.buffer[start - 4..start].copy_from_slice(&chunk_len.to_be_bytes());
+        self.tracks_written += 1;
+        Ok(())
+    }
+
+    /// Write a channel message
+    pub fn midi(&mut self, delta: u32, message: MidiMessage) -> Result<(), MidiError> {
+        self.in_track()?;
+        let packet = Packet::from(message);
+        let payload = packet.payload();
+        if !is_channel_status(payload[0]) {
+            return Err(MidiError::Untranslatable);
+        }
+        self.write_vlq(delta)?;
+        if self.running_status && self.last_status == Some(payload[0]) {
+            self.write(&payload[1..])?;
+        } else {
+            self.write(payload)?;
+        }
+        self.last_status = Some(payload[0]);
+        Ok(())
+    }
+
+    /// Write a complete sysex message, body _excludes_ SYSEX_START and SYSEX_END markers
+    pub fn sysex(&mut self, delta: u32, body: &[u8]) -> Result<(), MidiError> {
+        self.in_track()?;
+        self.write_vlq(delta)?;
+        self.write(&[SYSEX_START])?;
+        self.write_vlq(body.len() as u32 + 1)?;
+        self.write(body)?;
+        self.write(&[SYSEX_END])?;
+        self.last_status = None;
+        Ok(())
+    }
+
+    /// Write escaped raw bytes (sysex continuation, system common & realtime)
+    pub fn escape(&mut self, delta: u32, bytes: &[u8]) -> Result<(), MidiError> {
+        self.in_track()?;
+        self.write_vlq(delta)?;
+        self.write(&[ESCAPE])?;
+        self.write_vlq(bytes.len() as u32)?;
+        self.write(bytes)?;
+        self.last_status = None;
+        Ok(())
+    }
+
+    pub fn meta(&mut self, delta: u32, meta: MetaEvent) -> Result<(), MidiError> {
+        self.in_track()?;
+        let mut scratch = [0; 5];
+        let (meta_type, data) = meta.encode(&mut scratch);
+        self.write_vlq(delta)?;
+        self.write(&[META, meta_type])?;
+        self.write_vlq(data.len() as u32)?;
+        self.write(data)?;
+        self.last_status = None;
+        Ok(())
+    }
+
+    /// Write any event as read from a track
+    pub fn event(&mut self, event: &TrackEvent) -> Result<(), MidiError> {
+        match event.kind {
+            EventKind::Midi(message) => self.midi(event.delta, message),
+            EventKind::Sysex(data) => {
+                // data read from file may or may not include the terminator
+                let body = data.strip_suffix(&[SYSEX_END]).unwrap_or(data);
+                self.sysex(event.delta, body)
+            }
+            EventKind::Escape(data) => self.escape(event.delta, data),
+            EventKind::Meta(MetaEvent::EndOfTrack) => self.end_track_after(event.delta),
+            EventKind::Meta(meta) => self.meta(event.delta, meta),
+        }
+    }
+
+    /// Returns length of written file
+    pub fn finish(self) -> Result<usize, MidiError> {
+        if self.track_start.is_some() || self.tracks_written == 0 {
+            return Err(MidiError::InvalidChunk);
+        }
+        self.buffer[10..12].copy_from_slice(&self.tracks_written.to_be_bytes());
+        Ok(self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{channel, Note, U7};
+
+    #[test]
+    fn vlq_roundtrip() {
+        for (value, encoded) in [
+            (0u32, &[0x00][..]),
+            (0x40, &[0x40]),
+            (0x7F, &[0x7F]),
+            (0x80, &[0x81, 0x00]),
+            (0x2000, &[0xC0, 0x00]),
+            (0x3FFF, &[0xFF, 0x7F]),
+            (0x4000, &[0x81, 0x80, 0x00]),
+            (VLQ_MAX, &[0xFF, 0xFF, 0xFF, 0x7F]),
+        ] {
+            let (bytes, len) = vlq(value).unwrap();
+            assert_eq!(encoded, &bytes[..len]);
+            assert_eq!((value, len), read_vlq(encoded).unwrap());
+        }
+        assert!(vlq(VLQ_MAX + 1).is_err());
+        assert!(read_vlq(&[0x81]).is_err());
+    }
+
+    #[test]
+    fn write_read_roundtrip() {
+        let mut buf = [0; 256];
+        let mut writer = SmfWriter::new(&mut buf, Format::MultiTrack, Division::TicksPerQuarter(96)).unwrap();
+        writer.begin_track().unwrap();
+        writer.meta(0, MetaEvent::Tempo(250_000)).unwrap();
+        writer.meta(0, MetaEvent::TimeSignature(4, 2, 24, 8)).unwrap();
+        writer.end_track().unwrap();
+        writer.begin_track().unwrap();
+        writer.meta(0, MetaEvent::TrackName(b"DW-6000")).unwrap();
+        writer.midi(0, MidiMessage::NoteOn(channel(1), Note::C4, U7(100))).unwrap();
+        writer.midi(96, MidiMessage::NoteOn(channel(1), Note::C4, U7(0))).unwrap();
+        writer.sysex(0, &[0x42, 0x30, 0x04, 0x10]).unwrap();
+        writer.midi(0x100, MidiMessage::PitchBend(channel(2), crate::U14(0x2000))).unwrap();
+        writer.end_track_after(10).unwrap();
+        let len = writer.finish().unwrap();
+
+        let smf = Smf::parse(&buf[..len]).unwrap();
+        assert_eq!(Format::MultiTrack, smf.format);
+        assert_eq!(2, smf.track_count);
+        assert_eq!(Division::TicksPerQuarter(96), smf.division);
+
+        let mut tracks = smf.tracks();
+        let conductor = tracks.next().unwrap().unwrap();
+        assert_eq!(3, conductor.events().count());
+
+        let mut events = tracks.next().unwrap().unwrap().events();
+        assert_eq!(EventKind::Meta(MetaEvent::TrackName(b"DW-6000")), events.next().unwrap().unwrap().kind);
+        assert_eq!(
+            TrackEvent { delta: 0, kind: EventKind::Midi(MidiMessage::NoteOn(channel(1), Note::C4, U7(100))) },
+            events.next().unwrap().unwrap()
+        );
+        // running status
+        assert_eq!(
+            TrackEvent { delta: 96, kind: EventKind::Midi(MidiMessage::NoteOn(channel(1), Note::C4, U7(0))) },
+            events.next().unwrap().unwrap()
+        );
+        assert_eq!(EventKind::Sysex(&[0x42, 0x30, 0x04, 0x10, SYSEX_END]), events.next().unwrap().unwrap().kind);
+        assert_eq!(
+            TrackEvent { delta: 0x100, kind: EventKind::Midi(MidiMessage::PitchBend(channel(2), crate::U14(0x2000))) },
+            events.next().unwrap().unwrap()
+        );
+        assert_eq!(TrackEvent { delta: 10, kind: EventKind::Meta(MetaEvent::EndOfTrack) }, events.next().unwrap().unwrap());
+        assert!(events.next().is_none());
+        assert!(tracks.next().is_none());
+
+        // running status saves one byte
+        let mut buf2 = [0; 256];
+        let mut writer = SmfWriter::new(&mut buf2, Format::MultiTrack, smf.division).unwrap().with_running_status(false);
+        for track in smf.tracks() {
+            writer.begin_track().unwrap();
+            for event in track.unwrap().events() {
+                writer.event(&event.unwrap()).unwrap();
+            }
+        }
+        assert_eq!(len + 1, writer.finish().unwrap());
+    }
+
+    #[test]
+    fn tempo_map() {
+        let mut map: TempoMap<4> = TempoMap::new(Division::TicksPerQuarter(480));
+        assert_eq!(500_000, map.micros_at(480));
+        map.push(960, 250_000).unwrap();
+        assert_eq!(500_000, map.tempo_at(959));
+        assert_eq!(250_000, map.tempo_at(960));
+        assert_eq!(1_000_000, map.micros_at(960));
+        assert_eq!(1_250_000, map.micros_at(1440));
+        assert_eq!(Err(MidiError::OutOfOrder), map.push(480, 400_000));
+        assert_eq!(1_250_000, map.micros_at(1440));
+
+        let smpte: TempoMap<1> = TempoMap::new(Division::Smpte(25, 40));
+        assert_eq!(1_000_000, smpte.micros_at(1000));
+        assert_eq!(Division::Smpte(25, 40), Division::from(u16::from(Division::Smpte(25, 40))));
+    }
+
+    #[test]
+    fn reject_garbage() {
+        assert!(Smf::parse(b"MThd").is_err());
+        assert!(Smf::parse(b"RIFF\0\0\0\x06\0\0\0\x01\0\x60").is_err());
+        let smf = Smf::parse(b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk\0\0\0\x03\0\x40\x40").unwrap();
+        let mut events = smf.tracks().next().unwrap().unwrap().events();
+        // data byte without running status
+        assert!(events.next().unwrap().is_err());
+        assert!(events.next().is_none());
+    }
+}
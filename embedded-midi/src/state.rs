@@ -0,0 +1,342 @@
+//! Channel state tracking
+//! Follows held notes and controllers of all 16 channels, to silence or restore a device.
+
+use core::convert::TryFrom;
+
+use crate::{Bend, Control, MidiChannel, MidiMessage, Note, Pressure, Program, U14, U7};
+use crate::mode::{ChannelMode, CC_ALL_SOUND_OFF};
+use crate::program::{CC_BANK_LSB, CC_BANK_MSB};
+use crate::nrpn::{CC_DATA_ENTRY_LSB, CC_DATA_ENTRY_MSB, CC_DATA_INCREMENT, CC_RPN_MSB};
+
+/// Sustain (damper) pedal, on if value is 64 or more
+pub const CC_SUSTAIN: Control = U7(64);
+
+/// Pitch bend center value
+pub const BEND_CENTER: Bend = U14(0x2000);
+
+/// Marks a control that was never received
+const UNSET: u8 = 0xFF;
+
+fn all_notes() -> impl Iterator<Item=Note> {
+    (0..=127u8).filter_map(|n| Note::try_from(n).ok())
+}
+
+/// Set of notes, one bit per note number
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+struct NoteSet([u32; 4]);
+
+impl NoteSet {
+    fn set(&mut self, note: Note, on: bool) {
+        let note = u8::from(note) as usize & 0x7F;
+        if on {
+            self.0[note / 32] |= 1 << (note % 32);
+        } else {
+            self.0[note / 32] &= !(1 << (note % 32));
+        }
+    }
+
+    fn contains(&self, note: Note) -> bool {
+        let note = u8::from(note) as usize & 0x7F;
+        self.0[note / 32] & (1 << (note % 32)) != 0
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    fn iter(&self) -> impl Iterator<Item=Note> + '_ {
+        all_notes().filter(move |n| self.contains(*n))
+    }
+}
+
+/// State of a single channel
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ChannelData {
+    // keys down
+    held: NoteSet,
+    // keys released while sustain pedal down
+    sustained: NoteSet,
+    controls: [u8; 128],
+    program: Option<Program>,
+    bend: Option<Bend>,
+    pressure: Option<Pressure>,
+}
+
+impl Default for ChannelData {
+    fn default() -> Self {
+        ChannelData {
+            held: NoteSet::default(),
+            sustained: NoteSet::default(),
+            controls: [UNSET; 128],
+            program: None,
+            bend: None,
+            pressure: None,
+        }
+    }
+}
+
+impl ChannelData {
+    fn advance(&mut self, message: &MidiMessage) {
+        match *message {
+            MidiMessage::NoteOn(_, note, U7(0)) | MidiMessage::NoteOff(_, note, _) => {
+                if self.held.contains(note) && self.sustain() {
+                    self.sustained.set(note, true);
+                }
+                self.held.set(note, false);
+            }
+            MidiMessage::NoteOn(_, note, _) => {
+                self.held.set(note, true);
+                self.sustained.set(note, false);
+            }
+            MidiMessage::ProgramChange(_, program) => self.program = Some(program),
+            MidiMessage::PitchBend(_, bend) => self.bend = Some(bend),
+            MidiMessage::ChannelPressure(_, pressure) => self.pressure = Some(pressure),
+            MidiMessage::ControlChange(_, control, value) => self.control_change(control, value),
+            _ => {}
+        }
+    }
+
+    fn control_change(&mut self, control: Control, value: U7) {
+        match ChannelMode::from_control(control, value) {
+            Some(ChannelMode::AllSoundOff) => {
+                self.held = NoteSet::default();
+                self.sustained = NoteSet::default();
+            }
+            Some(ChannelMode::ResetAllControllers) => {
+                // bank, volume, pan & co are kept as per spec
+                for cc in [CC_SUSTAIN.0, 65, 66, 67, 1, 11] {
+                    self.controls[cc as usize] = UNSET;
+                }
+                self.sustained = NoteSet::default();
+                self.bend = None;
+                self.pressure = None;
+            }
+            Some(mode) if mode.is_notes_off() => {
+                // keys are released, notes keep sounding while sustain pedal is down
+                if self.sustain() {
+                    for (sustained, held) in self.sustained.0.iter_mut().zip(self.held.0) {
+                        *sustained |= held;
+                    }
+                }
+                self.held = NoteSet::default();
+            }
+            Some(_) => {}
+            None => {
+                self.controls[control.0 as usize & 0x7F] = value.0;
+                if control == CC_SUSTAIN && !self.sustain() {
+                    self.sustained = NoteSet::default();
+                }
+            }
+        }
+    }
+
+    /// True if key is down for note
+    pub fn is_held(&self, note: Note) -> bool {
+        self.held.contains(note)
+    }
+
+    /// Keys down, lowest first
+    pub fn held_notes(&self) -> impl Iterator<Item=Note> + '_ {
+        self.held.iter()
+    }
+
+    /// True if note is held or sustained by pedal
+    pub fn is_sounding(&self, note: Note) -> bool {
+        self.held.contains(note) || self.sustained.contains(note)
+    }
+
+    /// Notes held or sustained by pedal, lowest first
+    pub fn sounding_notes(&self) -> impl Iterator<Item=Note> + '_ {
+        all_notes().filter(move |n| self.is_sounding(*n))
+    }
+
+    pub fn has_sounding_notes(&self) -> bool {
+        !self.held.is_empty() || !self.sustained.is_empty()
+    }
+
+    /// Last value of controller, None if never received
+    pub fn control(&self, control: Control) -> Option<U7> {
+        match self.controls[control.0 as usize & 0x7F] {
+            UNSET => None,
+            value => Some(U7(value)),
+        }
+    }
+
+    pub fn sustain(&self) -> bool {
+        self.control(CC_SUSTAIN).is_some_and(|v| v.0 >= 64)
+    }
+
+    pub fn program(&self) -> Option<Program> {
+        self.program
+    }
+
+    pub fn bend(&self) -> Option<Bend> {
+        self.bend
+    }
+
+    pub fn pressure(&self) -> Option<Pressure> {
+        self.pressure
+    }
+}
+
+/// Tracks notes and controllers of all channels from messages sent to a device
+#[derive(Debug, Default)]
+pub struct ChannelState {
+    channels: [ChannelData; 16],
+}
+
+impl ChannelState {
+    /// Feed a message to the tracker, System Reset clears all channels
+    pub fn advance(&mut self, message: &MidiMessage) {
+        match message {
+            MidiMessage::NoteOff(ch, ..)
+            | MidiMessage::NoteOn(ch, ..)
+            | MidiMessage::ProgramChange(ch, _)
+            | MidiMessage::PitchBend(ch, _)
+            | MidiMessage::ChannelPressure(ch, _)
+            | MidiMessage::ControlChange(ch, ..) => self.channels[ch.0 as usize & 0x0F].advance(message),
+            MidiMessage::SystemReset => self.reset(),
+            _ => {}
+        }
+    }
+
+    /// Forget everything, e.g. after device was power cycled
+    pub fn reset(&mut self) {
+        *self = ChannelState::default();
+    }
+
+    pub fn channel(&self, channel: MidiChannel) -> &ChannelData {
+        &self.channels[channel.0 as usize & 0x0F]
+    }
+
+    /// Messages silencing notes sounding on channel: sustain pedal release then a note off per sounding note
+    /// Feed the messages back to `advance()` once sent, or call `reset()`
+    pub fn note_offs(&self, channel: MidiChannel) -> impl Iterator<Item=MidiMessage> + '_ {
+        let data = self.channel(channel);
+        let pedal = data.sustain().then_some(MidiMessage::ControlChange(channel, CC_SUSTAIN, U7::MIN));
+        pedal.into_iter().chain(data.sounding_notes().map(move |note| MidiMessage::NoteOff(channel, note, U7::MIN)))
+    }
+
+    /// Note offs for all channels, only channels with sounding notes produce messages
+    pub fn all_note_offs(&self) -> impl Iterator<Item=MidiMessage> + '_ {
+        (0..16).map(MidiChannel).flat_map(move |ch| self.note_offs(ch))
+    }
+
+    /// Messages restoring controllers of channel, in order: bank select, program, controllers, bend and pressure
+    /// Sustain, RPN / NRPN selection, data entry & increment / decrement and notes are not restored
+    pub fn restore(&self, channel: MidiChannel) -> impl Iterator<Item=MidiMessage> + '_ {
+        let data = self.channel(channel);
+        let control = move |cc: Control| data.control(cc).map(|value| MidiMessage::ControlChange(channel, cc, value));
+        let bank = [CC_BANK_MSB, CC_BANK_LSB].into_iter().filter_map(control);
+        let program = data.program.map(|program| MidiMessage::ProgramChange(channel, program));
+        let controls = (0..CC_ALL_SOUND_OFF.0).map(U7)
+            .filter(|cc| !is_transient(*cc))
+            .filter_map(control);
+        let bend = data.bend.filter(|b| *b != BEND_CENTER).map(|bend| MidiMessage::PitchBend(channel, bend));
+        let pressure = data.pressure.map(|pressure| MidiMessage::ChannelPressure(channel, pressure));
+        bank.chain(program).chain(controls).chain(bend).chain(pressure)
+    }
+}
+
+/// Controls not restored: bank select (sent before program), sustain, data entry, data increment / decrement, RPN & NRPN
+fn is_transient(cc: Control) -> bool {
+    cc == CC_BANK_MSB
+        || cc == CC_BANK_LSB
+        || cc == CC_SUSTAIN
+        || cc.0 == CC_DATA_ENTRY_MSB
+        || cc.0 == CC_DATA_ENTRY_LSB
+        || (CC_DATA_INCREMENT..=CC_RPN_MSB).contains(&cc.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    fn cc(ch: MidiChannel, control: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange(ch, U7(control), U7(value))
+    }
+
+    #[test]
+    fn silence_held_notes() {
+        let mut state = ChannelState::default();
+        let ch = channel(2);
+        state.advance(&MidiMessage::NoteOn(ch, Note::C4, U7(100)));
+        state.advance(&MidiMessage::NoteOn(ch, Note::E4, U7(100)));
+        state.advance(&MidiMessage::NoteOn(ch, Note::G4, U7(100)));
+        state.advance(&MidiMessage::NoteOn(ch, Note::G4, U7(0)));
+        assert!(state.channel(ch).is_held(Note::C4));
+        assert!(!state.channel(ch).is_sounding(Note::G4));
+        assert!(!state.channel(channel(1)).has_sounding_notes());
+
+        let mut offs = state.note_offs(ch);
+        assert_eq!(Some(MidiMessage::NoteOff(ch, Note::C4, U7(0))), offs.next());
+        assert_eq!(Some(MidiMessage::NoteOff(ch, Note::E4, U7(0))), offs.next());
+        assert_eq!(None, offs.next());
+        drop(offs);
+        assert_eq!(2, state.all_note_offs().count());
+
+        state.advance(&cc(ch, 123, 0));
+        assert_eq!(0, state.all_note_offs().count());
+    }
+
+    #[test]
+    fn sustain_pedal() {
+        let mut state = ChannelState::default();
+        let ch = channel(1);
+        state.advance(&cc(ch, 64, 127));
+        state.advance(&MidiMessage::NoteOn(ch, Note::A3, U7(90)));
+        state.advance(&MidiMessage::NoteOff(ch, Note::A3, U7(0)));
+        assert!(!state.channel(ch).is_held(Note::A3));
+        assert!(state.channel(ch).is_sounding(Note::A3));
+
+        let mut offs = state.note_offs(ch);
+        assert_eq!(Some(cc(ch, 64, 0)), offs.next());
+        assert_eq!(Some(MidiMessage::NoteOff(ch, Note::A3, U7(0))), offs.next());
+        assert_eq!(None, offs.next());
+        drop(offs);
+
+        state.advance(&cc(ch, 64, 0));
+        assert!(!state.channel(ch).has_sounding_notes());
+
+        // all notes off with pedal down releases keys only
+        state.advance(&cc(ch, 64, 127));
+        state.advance(&MidiMessage::NoteOn(ch, Note::C4, U7(90)));
+        state.advance(&cc(ch, 123, 0));
+        assert!(!state.channel(ch).is_held(Note::C4));
+        assert!(state.channel(ch).is_sounding(Note::C4));
+        state.advance(&cc(ch, 64, 0));
+        assert!(!state.channel(ch).has_sounding_notes());
+    }
+
+    #[test]
+    fn restore_controllers() {
+        let mut state = ChannelState::default();
+        let ch = channel(10);
+        state.advance(&cc(ch, 7, 100));
+        state.advance(&cc(ch, 32, 2));
+        state.advance(&cc(ch, 101, 0));
+        state.advance(&cc(ch, 96, 1));
+        state.advance(&cc(ch, 64, 127));
+        state.advance(&MidiMessage::ProgramChange(ch, U7(12)));
+        state.advance(&MidiMessage::PitchBend(ch, BEND_CENTER));
+        state.advance(&MidiMessage::ChannelPressure(ch, U7(40)));
+        assert_eq!(Some(U7(100)), state.channel(ch).control(U7(7)));
+
+        let mut restore = state.restore(ch);
+        assert_eq!(Some(cc(ch, 32, 2)), restore.next());
+        assert_eq!(Some(MidiMessage::ProgramChange(ch, U7(12))), restore.next());
+        assert_eq!(Some(cc(ch, 7, 100)), restore.next());
+        assert_eq!(Some(MidiMessage::ChannelPressure(ch, U7(40))), restore.next());
+        assert_eq!(None, restore.next());
+        drop(restore);
+
+        state.advance(&cc(ch, 121, 0));
+        assert_eq!(None, state.channel(ch).pressure());
+        assert!(!state.channel(ch).sustain());
+        assert_eq!(Some(U7(100)), state.channel(ch).control(U7(7)));
+
+        state.advance(&MidiMessage::SystemReset);
+        assert_eq!(0, state.restore(ch).count());
+    }
+}
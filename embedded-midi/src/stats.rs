@@ -0,0 +1,115 @@
+//! Traffic & error counters of a port
+
+use crate::{MidiError, Packet};
+
+const SYSEX_START: u8 = 0xF0;
+
+/// Counters of a port, updated as packets go through it
+/// Counters wrap around on overflow
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PortStats {
+    pub packets_in: u32,
+    pub packets_out: u32,
+    /// MIDI bytes, excluding USB packet headers
+    pub bytes_in: u32,
+    pub bytes_out: u32,
+    /// Sysex messages started
+    pub sysex_in: u32,
+    pub sysex_out: u32,
+    /// Malformed data received
+    pub parse_errors: u32,
+    /// Packets dropped because a buffer was full
+    pub dropped: u32,
+    /// Other port errors
+    pub port_errors: u32,
+    /// Most packets ever waiting in port buffer
+    pub peak_depth: u16,
+    /// Time of last packet in or out, as given by port clock, None if port has no clock or saw no packet yet
+    pub last_activity: Option<u64>,
+}
+
+impl PortStats {
+    pub fn packet_in(&mut self, packet: &Packet) {
+        self.packets_in = self.packets_in.wrapping_add(1);
+        self.bytes_in = self.bytes_in.wrapping_add(packet.payload().len() as u32);
+        if is_sysex_start(packet) {
+            self.sysex_in = self.sysex_in.wrapping_add(1);
+        }
+    }
+
+    pub fn packet_out(&mut self, packet: &Packet) {
+        self.packets_out = self.packets_out.wrapping_add(1);
+        self.bytes_out = self.bytes_out.wrapping_add(packet.payload().len() as u32);
+        if is_sysex_start(packet) {
+            self.sysex_out = self.sysex_out.wrapping_add(1);
+        }
+    }
+
+    /// Count error in matching counter
+    pub fn error(&mut self, error: &MidiError) {
+        let counter = match error {
+            MidiError::BufferFull => &mut self.dropped,
+            MidiError::PortError | MidiError::InvalidPort | MidiError::TooManyPorts => &mut self.port_errors,
+            _ => &mut self.parse_errors,
+        };
+        *counter = counter.wrapping_add(1);
+    }
+
+    /// Record number of packets waiting in port buffer
+    pub fn depth(&mut self, depth: usize) {
+        self.peak_depth = self.peak_depth.max(depth.min(u16::MAX as usize) as u16);
+    }
+
+    pub fn touch(&mut self, now: u64) {
+        self.last_activity = Some(now);
+    }
+
+    /// Sum of all error counters
+    pub fn errors(&self) -> u32 {
+        self.parse_errors.wrapping_add(self.dropped).wrapping_add(self.port_errors)
+    }
+
+    /// Return current counters and zero them
+    pub fn take(&mut self) -> PortStats {
+        core::mem::take(self)
+    }
+}
+
+fn is_sysex_start(packet: &Packet) -> bool {
+    packet.payload().first() == Some(&SYSEX_START)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{channel, MidiMessage, Note, U7};
+
+    #[test]
+    fn count_packets_and_errors() {
+        let mut stats = PortStats::default();
+        stats.packet_in(&Packet::from(MidiMessage::NoteOn(channel(1), Note::C4, U7(100))));
+        stats.packet_in(&Packet::from(MidiMessage::TimingClock));
+        stats.packet_out(&Packet::from_raw([0x04, 0xF0, 0x42, 0x30]));
+        stats.packet_out(&Packet::from_raw([0x06, 0x01, 0xF7, 0x00]));
+        stats.error(&MidiError::BufferFull);
+        stats.error(&MidiError::InvalidStatus(0xF4));
+        stats.depth(3);
+        stats.depth(1);
+
+        assert_eq!(2, stats.packets_in);
+        assert_eq!(4, stats.bytes_in);
+        assert_eq!(2, stats.packets_out);
+        assert_eq!(5, stats.bytes_out);
+        assert_eq!(0, stats.sysex_in);
+        assert_eq!(1, stats.sysex_out);
+        assert_eq!(1, stats.dropped);
+        assert_eq!(1, stats.parse_errors);
+        assert_eq!(2, stats.errors());
+        assert_eq!(3, stats.peak_depth);
+
+        let snapshot = stats.take();
+        assert_eq!(2, snapshot.packets_in);
+        assert_eq!(PortStats::default(), stats);
+    }
+}
@@ -38,6 +38,17 @@ pub fn is_channel_status(byte: u8) -> bool {
     (NOTE_OFF..SYSEX_START).contains(&byte)
 }
 
+/// System Realtime bytes may appear anywhere, even between the bytes of another message
+/// MEASURE_END is excluded as it carries a data byte
+pub fn is_realtime(byte: u8) -> bool {
+    byte >= TIMING_CLOCK && byte != MEASURE_END
+}
+
+/// Reserved status bytes that have no defined meaning
+fn is_undefined(byte: u8) -> bool {
+    matches!(byte, 0xF4 | 0xF5 | 0xFD)
+}
+
 #[derive(Copy, Clone, Debug, UnsafeFromPrimitive, Eq, PartialEq)]
 #[repr(u8)]
 pub enum Status {
@@ -113,7 +124,7 @@ impl TryFrom<u8> for Status {
     type Error = MidiError;
 
     fn try_from(mut byte: u8) -> Result<Self, Self::Error> {
-        if is_non_status(byte) {
+        if is_non_status(byte) || is_undefined(byte) {
             return Err(MidiError::InvalidStatus(byte));
         }
         if is_channel_status(byte) {
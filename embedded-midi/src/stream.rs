@@ -0,0 +1,201 @@
+//! Raw MIDI bytes over any `std::io` stream, e.g. a pty, a serial device file or a TCP socket
+//! A pty pair can be created with `socat -d -d pty,raw,echo=0 pty,raw,echo=0`,
+//! then one end opened with `StreamMidiPort::open()` and the other driven by a script.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::Path;
+
+use heapless::Vec;
+
+use crate::codec::ByteCodec;
+use crate::{CableNumber, MidiError, Packet, PacketList, PortStats, Receive, Transmit};
+
+/// Bytes read or written per call to stream
+const CHUNK_LEN: usize = 64;
+
+/// Output buffer size, in bytes
+const TX_FIFO_LEN: usize = 256;
+
+/// MIDI port over a byte stream, parsing received bytes to packets and serializing packets to transmit
+/// Non-blocking streams are supported, `WouldBlock` is treated as no data or no room for data
+pub struct StreamMidiPort<S> {
+    stream: S,
+    codec: ByteCodec<TX_FIFO_LEN>,
+    // bytes read but not yet parsed
+    input: Vec<u8, CHUNK_LEN>,
+    // bytes serialized but not yet written
+    output: Vec<u8, CHUNK_LEN>,
+}
+
+impl StreamMidiPort<File> {
+    /// Open a device file for reading & writing, e.g. "/dev/pts/3"
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(StreamMidiPort::new(file))
+    }
+}
+
+impl<S> StreamMidiPort<S> {
+    pub fn new(stream: S) -> Self {
+        StreamMidiPort {
+            stream,
+            codec: ByteCodec::default(),
+            input: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Omit repeated channel status bytes when transmitting (default: true)
+    pub fn with_running_status(mut self, running_status: bool) -> Self {
+        self.codec = self.codec.with_running_status(running_status);
+        self
+    }
+
+    /// Cable number of received packets (default: 0)
+    pub fn with_cable_num(mut self, cable_number: CableNumber) -> Self {
+        self.codec = self.codec.with_cable_num(cable_number);
+        self
+    }
+
+    /// Clock used to timestamp port activity, e.g. millis since start
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.codec = self.codec.with_clock(clock);
+        self
+    }
+
+    pub fn stream_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Release stream, dropping any buffered bytes
+    pub fn free(self) -> S {
+        self.stream
+    }
+
+    pub fn stats(&self) -> PortStats {
+        self.codec.stats()
+    }
+
+    pub fn reset_stats(&mut self) -> PortStats {
+        self.codec.reset_stats()
+    }
+
+    /// True if all bytes were written to stream
+    pub fn is_flushed(&self) -> bool {
+        self.output.is_empty() && self.codec.is_empty()
+    }
+}
+
+impl<S: Write> StreamMidiPort<S> {
+    /// Write buffered bytes until stream would block
+    pub fn flush(&mut self) -> Result<(), MidiError> {
+        loop {
+            while !self.output.is_full() {
+                match self.codec.next_byte() {
+                    Some(byte) => { let _ = self.output.push(byte); }
+                    None => break,
+                }
+            }
+            if self.output.is_empty() {
+                break;
+            }
+            match self.stream.write(&self.output) {
+                Ok(0) => return Err(self.codec.port_error()),
+                Ok(written) => {
+                    let remaining = self.output.len() - written;
+                    self.output.rotate_left(written);
+                    self.output.truncate(remaining);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return Err(self.codec.port_error()),
+            }
+        }
+        match self.stream.flush() {
+            Err(err) if err.kind() != ErrorKind::WouldBlock => Err(self.codec.port_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<S: Read> Receive for StreamMidiPort<S> {
+    /// Read bytes until a packet is complete or no more bytes are available
+    /// End of stream is treated as no more bytes
+    fn receive(&mut self) -> Result<Option<Packet>, MidiError> {
+        if let Some(packet) = self.codec.take_pending() {
+            return Ok(Some(packet));
+        }
+        loop {
+            if self.input.is_empty() {
+                let mut chunk = [0; CHUNK_LEN];
+                let len = match self.stream.read(&mut chunk) {
+                    Ok(len) => len,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(None),
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) => return Err(self.codec.port_error()),
+                };
+                if len == 0 {
+                    return Ok(None);
+                }
+                // read in reverse so bytes can be popped in order
+                self.input.extend(chunk[..len].iter().rev().copied());
+            }
+            while let Some(byte) = self.input.pop() {
+                if let Some(packet) = self.codec.advance(byte)? {
+                    return Ok(Some(packet));
+                }
+            }
+        }
+    }
+}
+
+impl<S: Write> Transmit for StreamMidiPort<S> {
+    /// Packets that do not fit in output buffer are not sent and Err(BufferFull) is returned
+    /// They are not counted as dropped, callers usually retry them once the stream caught up
+    fn transmit(&mut self, packets: PacketList) -> Result<(), MidiError> {
+        let result = self.codec.push(&packets);
+        self.flush()?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::{channel, MidiMessage, Note, U7};
+
+    fn note_on(note: Note) -> Packet {
+        Packet::from(MidiMessage::NoteOn(channel(1), note, U7(100)))
+    }
+
+    #[test]
+    fn receive_sysex_and_running_status() {
+        let mut bytes = std::vec![0xF0, 0x42, 0x30, 0x04, 0x40, 0xF7, 0x90, 60, 100, 62, 100];
+        // more than a chunk
+        bytes.extend(core::iter::repeat_n([64, 100], CHUNK_LEN).flatten());
+        fn clock() -> u64 { 42 }
+        let mut port = StreamMidiPort::new(Cursor::new(bytes)).with_clock(clock);
+
+        assert_eq!(Some(Packet::from_raw([0x04, 0xF0, 0x42, 0x30])), port.receive().unwrap());
+        assert_eq!(Some(Packet::from_raw([0x07, 0x04, 0x40, 0xF7])), port.receive().unwrap());
+        assert_eq!(Some(note_on(Note::C4)), port.receive().unwrap());
+        assert_eq!(Some(note_on(Note::D4)), port.receive().unwrap());
+        for _ in 0..CHUNK_LEN {
+            assert_eq!(Some(note_on(Note::E4)), port.receive().unwrap());
+        }
+        assert_eq!(None, port.receive().unwrap());
+        assert_eq!(1, port.stats().sysex_in);
+        assert_eq!(Some(42), port.stats().last_activity);
+    }
+
+    #[test]
+    fn transmit_with_running_status() {
+        let mut port = StreamMidiPort::new(std::vec::Vec::new());
+        port.transmit(PacketList::from_iter([note_on(Note::C4), note_on(Note::D4)])).unwrap();
+        assert!(port.is_flushed());
+        assert_eq!(None, port.stats().last_activity);
+        assert_eq!(&[0x90, 60, 100, 62, 100], port.free().as_slice());
+    }
+}
@@ -0,0 +1,285 @@
+//! Human-readable text form of MIDI messages, e.g. `noteon ch1 C4 100`, `cc ch2 74 63`, `sysex 42 30 ..`
+//! Channels are 1-16, notes use the C4 convention, values are decimal and sysex bytes are hex.
+//! Sysex messages span multiple lines, one per packet:
+//! `sysex 42 30 ..` begins, `.. 04 10 7f ..` continues and `.. 10 end` ends a message.
+//! Short messages fit on a single line, e.g. `sysex 7e end`.
+
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+
+use heapless::Vec;
+
+use crate::{channel, MidiChannel, MidiError, MidiMessage, Note, U14, U7};
+use crate::MidiMessage::*;
+#[cfg(feature = "defmt")]
+use crate::{MiddleC, note::SHARP_NAMES};
+
+const SYSEX: &str = "sysex";
+const MORE: &str = "..";
+const END: &str = "end";
+
+/// Part of a message after its keyword and channel
+#[derive(Copy, Clone)]
+enum Arg {
+    Note(Note),
+    Value(u16),
+    Byte(u8),
+    /// More sysex lines follow
+    More,
+    /// Sysex message ends
+    End,
+}
+
+struct Tokens {
+    keyword: &'static str,
+    channel: Option<MidiChannel>,
+    args: Vec<Arg, 5>,
+}
+
+impl Tokens {
+    fn new(keyword: &'static str, channel: Option<MidiChannel>, args: &[Arg]) -> Self {
+        Tokens { keyword, channel, args: Vec::from_slice(args).unwrap() }
+    }
+}
+
+fn tokens(message: &MidiMessage) -> Tokens {
+    use Arg::*;
+    match *message {
+        NoteOff(ch, note, velocity) => Tokens::new("noteoff", Some(ch), &[Note(note), Value(velocity.0 as u16)]),
+        NoteOn(ch, note, velocity) => Tokens::new("noteon", Some(ch), &[Note(note), Value(velocity.0 as u16)]),
+        NotePressure(ch, note, pressure) => Tokens::new("notepressure", Some(ch), &[Note(note), Value(pressure.0 as u16)]),
+        ChannelPressure(ch, pressure) => Tokens::new("pressure", Some(ch), &[Value(pressure.0 as u16)]),
+        ProgramChange(ch, program) => Tokens::new("program", Some(ch), &[Value(program.0 as u16)]),
+        ControlChange(ch, control, value) => Tokens::new("cc", Some(ch), &[Value(control.0 as u16), Value(value.0 as u16)]),
+        PitchBend(ch, bend) => Tokens::new("bend", Some(ch), &[Value(bend.0)]),
+        TimeCodeQuarterFrame(frame) => Tokens::new("mtc", None, &[Value(frame.0 as u16)]),
+        SongPositionPointer(lsb, msb) => Tokens::new("songpos", None, &[Value(U14::from((lsb, msb)).0)]),
+        SongSelect(song) => Tokens::new("song", None, &[Value(song.0 as u16)]),
+        TuneRequest => Tokens::new("tune", None, &[]),
+        TimingClock => Tokens::new("clock", None, &[]),
+        MeasureEnd(measure) => Tokens::new("measure", None, &[Value(measure.0 as u16)]),
+        Start => Tokens::new("start", None, &[]),
+        Continue => Tokens::new("continue", None, &[]),
+        Stop => Tokens::new("stop", None, &[]),
+        ActiveSensing => Tokens::new("sensing", None, &[]),
+        SystemReset => Tokens::new("reset", None, &[]),
+        SysexBegin(b1, b2) => Tokens::new(SYSEX, None, &[Byte(b1), Byte(b2), More]),
+        SysexCont(b1, b2, b3) => Tokens::new(MORE, None, &[Byte(b1), Byte(b2), Byte(b3), More]),
+        SysexEnd => Tokens::new(MORE, None, &[End]),
+        SysexEnd1(b1) => Tokens::new(MORE, None, &[Byte(b1), End]),
+        SysexEnd2(b1, b2) => Tokens::new(MORE, None, &[Byte(b1), Byte(b2), End]),
+        SysexEmpty => Tokens::new(SYSEX, None, &[End]),
+        SysexSingleByte(b1) => Tokens::new(SYSEX, None, &[Byte(b1), End]),
+    }
+}
+
+impl fmt::Display for MidiMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tokens = tokens(self);
+        f.write_str(tokens.keyword)?;
+        if let Some(ch) = tokens.channel {
+            write!(f, " ch{}", ch.0 + 1)?;
+        }
+        for arg in tokens.args {
+            match arg {
+                Arg::Note(note) => write!(f, " {}", note)?,
+                Arg::Value(value) => write!(f, " {}", value)?,
+                Arg::Byte(byte) => write!(f, " {:02x}", byte)?,
+                Arg::More => write!(f, " {}", MORE)?,
+                Arg::End => write!(f, " {}", END)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for MidiMessage {
+    fn format(&self, f: defmt::Formatter) {
+        let tokens = tokens(self);
+        defmt::write!(f, "{=str}", tokens.keyword);
+        if let Some(ch) = tokens.channel {
+            defmt::write!(f, " ch{=u8}", ch.0 + 1);
+        }
+        for arg in tokens.args {
+            match arg {
+                Arg::Note(note) => defmt::write!(f, " {=str}{=i8}", SHARP_NAMES[note.pitch_class() as usize], note.octave(MiddleC::C4)),
+                Arg::Value(value) => defmt::write!(f, " {=u16}", value),
+                Arg::Byte(byte) => defmt::write!(f, " {=u8:02x}", byte),
+                Arg::More => defmt::write!(f, " {=str}", MORE),
+                Arg::End => defmt::write!(f, " {=str}", END),
+            }
+        }
+    }
+}
+
+/// Parses the text form of a single message
+/// Notes can be given by name or number
+impl FromStr for MidiMessage {
+    type Err = MidiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let keyword = words.next().ok_or(MidiError::InvalidText)?;
+        let mut parser = Parser { words };
+        let message = match keyword {
+            "noteoff" => NoteOff(parser.channel()?, parser.note()?, parser.u7()?),
+            "noteon" => NoteOn(parser.channel()?, parser.note()?, parser.u7()?),
+            "notepressure" => NotePressure(parser.channel()?, parser.note()?, parser.u7()?),
+            "pressure" => ChannelPressure(parser.channel()?, parser.u7()?),
+            "program" => ProgramChange(parser.channel()?, parser.u7()?),
+            "cc" => ControlChange(parser.channel()?, parser.u7()?, parser.u7()?),
+            "bend" => PitchBend(parser.channel()?, parser.u14()?),
+            "mtc" => TimeCodeQuarterFrame(parser.u7()?),
+            "songpos" => {
+                let (lsb, msb) = parser.u14()?.into();
+                SongPositionPointer(lsb, msb)
+            }
+            "song" => SongSelect(parser.u7()?),
+            "tune" => TuneRequest,
+            "clock" => TimingClock,
+            "measure" => MeasureEnd(parser.u7()?),
+            "start" => Start,
+            "continue" => Continue,
+            "stop" => Stop,
+            "sensing" => ActiveSensing,
+            "reset" => SystemReset,
+            SYSEX => match parser.sysex()?.as_slice() {
+                [Arg::End] => SysexEmpty,
+                [Arg::Byte(b1), Arg::End] => SysexSingleByte(*b1),
+                [Arg::Byte(b1), Arg::Byte(b2), Arg::More] => SysexBegin(*b1, *b2),
+                _ => return Err(MidiError::InvalidSysex),
+            },
+            MORE => match parser.sysex()?.as_slice() {
+                [Arg::End] => SysexEnd,
+                [Arg::Byte(b1), Arg::End] => SysexEnd1(*b1),
+                [Arg::Byte(b1), Arg::Byte(b2), Arg::End] => SysexEnd2(*b1, *b2),
+                [Arg::Byte(b1), Arg::Byte(b2), Arg::Byte(b3), Arg::More] => SysexCont(*b1, *b2, *b3),
+                _ => return Err(MidiError::InvalidSysex),
+            },
+            _ => return Err(MidiError::InvalidText),
+        };
+        if parser.words.next().is_some() {
+            return Err(MidiError::InvalidText);
+        }
+        Ok(message)
+    }
+}
+
+struct Parser<'a, I: Iterator<Item=&'a str>> {
+    words: I,
+}
+
+impl<'a, I: Iterator<Item=&'a str>> Parser<'a, I> {
+    fn word(&mut self) -> Result<&'a str, MidiError> {
+        self.words.next().ok_or(MidiError::InvalidText)
+    }
+
+    fn channel(&mut self) -> Result<MidiChannel, MidiError> {
+        let word = self.word()?;
+        let number = word.strip_prefix("ch")
+            .and_then(|number| u8::from_str(number).ok())
+            .ok_or(MidiError::InvalidChannel)?;
+        if !(1..=16).contains(&number) {
+            return Err(MidiError::InvalidChannel);
+        }
+        Ok(channel(number))
+    }
+
+    fn note(&mut self) -> Result<Note, MidiError> {
+        let word = self.word()?;
+        match u8::from_str(word) {
+            Ok(number) => Note::try_from(number),
+            Err(_) => Note::from_str(word),
+        }
+    }
+
+    fn u7(&mut self) -> Result<U7, MidiError> {
+        let value = u8::from_str(self.word()?).map_err(|_| MidiError::InvalidInteger)?;
+        U7::try_from(value)
+    }
+
+    fn u14(&mut self) -> Result<U14, MidiError> {
+        let value = u16::from_str(self.word()?).map_err(|_| MidiError::InvalidInteger)?;
+        U14::try_from(value)
+    }
+
+    /// Sysex bytes up to and including `..` or `end`
+    fn sysex(&mut self) -> Result<Vec<Arg, 5>, MidiError> {
+        let mut args = Vec::new();
+        loop {
+            let word = self.word()?;
+            let arg = if word == MORE {
+                Arg::More
+            } else if word == END {
+                Arg::End
+            } else {
+                let byte = u8::from_str_radix(word.trim_start_matches("0x"), 16).map_err(|_| MidiError::InvalidSysex)?;
+                if byte > 0x7F {
+                    return Err(MidiError::InvalidSysex);
+                }
+                Arg::Byte(byte)
+            };
+            args.push(arg).map_err(|_| MidiError::InvalidSysex)?;
+            if matches!(arg, Arg::More | Arg::End) {
+                return Ok(args);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::ToString;
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for text in [
+            "noteon ch1 C4 100",
+            "noteoff ch16 C#-1 0",
+            "notepressure ch3 G9 12",
+            "cc ch2 74 63",
+            "program ch10 127",
+            "pressure ch1 64",
+            "bend ch1 8192",
+            "mtc 17",
+            "songpos 300",
+            "song 2",
+            "clock",
+            "sensing",
+            "reset",
+            "sysex 42 30 ..",
+            ".. 04 10 7f ..",
+            ".. end",
+            ".. 01 end",
+            ".. 01 02 end",
+            "sysex end",
+            "sysex 7e end",
+        ] {
+            let message = MidiMessage::from_str(text).unwrap();
+            assert_eq!(text, message.to_string());
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Ok(NoteOn(channel(1), Note::C4, U7(100))), "noteon ch1 60 100".parse());
+        assert_eq!(Ok(SysexBegin(0x42, 0x30)), "sysex 0x42 0x30 ..".parse());
+        assert_eq!(Ok(SystemReset), "reset".parse());
+
+        assert_eq!(Err(MidiError::InvalidText), "".parse::<MidiMessage>());
+        assert_eq!(Err(MidiError::InvalidText), "noteblah ch1 C4 100".parse::<MidiMessage>());
+        assert_eq!(Err(MidiError::InvalidText), "noteon ch1 C4".parse::<MidiMessage>());
+        assert_eq!(Err(MidiError::InvalidText), "noteon ch1 C4 100 100".parse::<MidiMessage>());
+        assert_eq!(Err(MidiError::InvalidChannel), "noteon ch17 C4 100".parse::<MidiMessage>());
+        assert_eq!(Err(MidiError::InvalidChannel), "noteon 1 C4 100".parse::<MidiMessage>());
+        assert_eq!(Err(MidiError::InvalidNote), "noteon ch1 H4 100".parse::<MidiMessage>());
+        assert_eq!(Err(MidiError::InvalidInteger), "noteon ch1 C4 128".parse::<MidiMessage>());
+        assert_eq!(Err(MidiError::InvalidSysex), "sysex 42 30 04 ..".parse::<MidiMessage>());
+        assert_eq!(Err(MidiError::InvalidSysex), "sysex f7 end".parse::<MidiMessage>());
+    }
+}
@@ -0,0 +1,252 @@
+//! Scales and chords
+//! Scales are sets of pitch classes relative to a root, stored as a 12-bit mask (bit 0 is the root).
+
+use core::convert::TryFrom;
+
+use crate::Note;
+
+/// All pitch classes, 12 bits set
+const CHROMATIC_MASK: u16 = 0x0FFF;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScaleKind {
+    Chromatic,
+    Major,
+    /// Natural minor (aeolian)
+    Minor,
+    Dorian,
+    Mixolydian,
+    HarmonicMinor,
+    /// Hexatonic blues
+    Blues,
+    /// Bit set for each semitone above root in scale, root is always included
+    User(u16),
+}
+
+impl ScaleKind {
+    pub fn mask(&self) -> u16 {
+        match self {
+            ScaleKind::Chromatic => CHROMATIC_MASK,
+            ScaleKind::Major => mask(&[0, 2, 4, 5, 7, 9, 11]),
+            ScaleKind::Minor => mask(&[0, 2, 3, 5, 7, 8, 10]),
+            ScaleKind::Dorian => mask(&[0, 2, 3, 5, 7, 9, 10]),
+            ScaleKind::Mixolydian => mask(&[0, 2, 4, 5, 7, 9, 10]),
+            ScaleKind::HarmonicMinor => mask(&[0, 2, 3, 5, 7, 8, 11]),
+            ScaleKind::Blues => mask(&[0, 3, 5, 6, 7, 10]),
+            ScaleKind::User(mask) => (mask & CHROMATIC_MASK) | 1,
+        }
+    }
+}
+
+const fn mask(semitones: &[u8]) -> u16 {
+    let mut mask = 0;
+    let mut i = 0;
+    while i < semitones.len() {
+        mask |= 1 << semitones[i];
+        i += 1;
+    }
+    mask
+}
+
+/// A scale rooted on a pitch class
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Scale {
+    /// Pitch class of root, 0 for C up to 11 for B
+    root: u8,
+    mask: u16,
+}
+
+impl Scale {
+    /// Only the pitch class of the root note matters, not its octave
+    pub fn new(root: Note, kind: ScaleKind) -> Self {
+        Scale { root: root.pitch_class(), mask: kind.mask() }
+    }
+
+    /// Pitch class of root, 0 for C up to 11 for B
+    pub fn root(&self) -> u8 {
+        self.root
+    }
+
+    /// Number of notes per octave
+    pub fn notes_per_octave(&self) -> u8 {
+        self.mask.count_ones() as u8
+    }
+
+    /// Semitones above root of the scale notes
+    pub fn semitones(&self) -> impl Iterator<Item=u8> + '_ {
+        (0..12).filter(move |s| self.mask & (1 << s) != 0)
+    }
+
+    fn semitone_of(&self, note: Note) -> u8 {
+        (note.pitch_class() + 12 - self.root) % 12
+    }
+
+    pub fn contains(&self, note: Note) -> bool {
+        self.mask & (1 << self.semitone_of(note)) != 0
+    }
+
+    /// Scale degree of note, 0 for the root, None if note is not in scale
+    pub fn degree(&self, note: Note) -> Option<u8> {
+        let semitone = self.semitone_of(note);
+        if !self.contains(note) {
+            return None;
+        }
+        Some((self.mask & ((1 << semitone) - 1)).count_ones() as u8)
+    }
+
+    /// Note of scale degree, counting from root note (degree 0)
+    /// Degrees beyond the scale length continue in the octaves above or below
+    /// Returns None if note would be out of range
+    pub fn note_at(&self, root: Note, degree: i16) -> Option<Note> {
+        let len = self.notes_per_octave() as i16;
+        let octave = degree.div_euclid(len);
+        let index = degree.rem_euclid(len) as usize;
+        let semitone = self.semitones().nth(index)? as i16;
+        let root = u8::from(root) as i16 - self.semitone_of(root) as i16;
+        let note = root + octave * 12 + semitone;
+        if !(0..=Note::MAX as i16).contains(&note) {
+            return None;
+        }
+        Note::try_from(note as u8).ok()
+    }
+
+    /// Nearest note in scale, lower note wins ties
+    pub fn quantize(&self, note: Note) -> Note {
+        for distance in 0..12i8 {
+            for candidate in [note.checked_transpose(-distance), note.checked_transpose(distance)].into_iter().flatten() {
+                if self.contains(candidate) {
+                    return candidate;
+                }
+            }
+        }
+        note
+    }
+
+    /// Chord stacked in thirds from a note of the scale, e.g. 3 notes for triad, 4 notes for seventh chord
+    /// Notes out of range are skipped
+    pub fn chord(&self, root: Note, degree: i16, size: usize) -> impl Iterator<Item=Note> + '_ {
+        (0..size as i16).filter_map(move |i| self.note_at(root, degree + i * 2))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChordShape {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Major7,
+    Minor7,
+    Dominant7,
+    HalfDiminished7,
+    Diminished7,
+    /// Root and fifth
+    Power,
+}
+
+impl ChordShape {
+    /// Semitones above root of chord notes
+    pub fn semitones(&self) -> &'static [u8] {
+        match self {
+            ChordShape::Major => &[0, 4, 7],
+            ChordShape::Minor => &[0, 3, 7],
+            ChordShape::Diminished => &[0, 3, 6],
+            ChordShape::Augmented => &[0, 4, 8],
+            ChordShape::Sus2 => &[0, 2, 7],
+            ChordShape::Sus4 => &[0, 5, 7],
+            ChordShape::Major7 => &[0, 4, 7, 11],
+            ChordShape::Minor7 => &[0, 3, 7, 10],
+            ChordShape::Dominant7 => &[0, 4, 7, 10],
+            ChordShape::HalfDiminished7 => &[0, 3, 6, 10],
+            ChordShape::Diminished7 => &[0, 3, 6, 9],
+            ChordShape::Power => &[0, 7],
+        }
+    }
+
+    /// Chord notes from root, notes out of range are skipped
+    pub fn notes(&self, root: Note) -> impl Iterator<Item=Note> {
+        self.semitones().iter().filter_map(move |s| root.checked_transpose(*s as i8))
+    }
+
+    /// Find shape of notes, in any octave and inversion, with root as lowest note
+    pub fn identify(root: Note, notes: &[Note]) -> Option<ChordShape> {
+        let root_class = root.pitch_class();
+        let classes = notes.iter().fold(1u16, |mask, note| mask | 1 << ((note.pitch_class() + 12 - root_class) % 12));
+        ALL_SHAPES.iter().copied().find(|shape| mask_of(shape.semitones()) == classes)
+    }
+}
+
+const ALL_SHAPES: [ChordShape; 12] = [
+    ChordShape::Major, ChordShape::Minor, ChordShape::Diminished, ChordShape::Augmented,
+    ChordShape::Sus2, ChordShape::Sus4, ChordShape::Major7, ChordShape::Minor7,
+    ChordShape::Dominant7, ChordShape::HalfDiminished7, ChordShape::Diminished7, ChordShape::Power,
+];
+
+fn mask_of(semitones: &[u8]) -> u16 {
+    semitones.iter().fold(0, |mask, s| mask | 1 << s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degrees() {
+        let d_dorian = Scale::new(Note::D2, ScaleKind::Dorian);
+        assert_eq!(7, d_dorian.notes_per_octave());
+        assert!(d_dorian.contains(Note::B4));
+        assert!(!d_dorian.contains(Note::Bb4));
+        assert_eq!(Some(0), d_dorian.degree(Note::D5));
+        assert_eq!(Some(5), d_dorian.degree(Note::B1));
+        assert_eq!(None, d_dorian.degree(Note::Fs1));
+
+        assert_eq!(Some(Note::D4), d_dorian.note_at(Note::D4, 0));
+        assert_eq!(Some(Note::C5), d_dorian.note_at(Note::D4, 6));
+        assert_eq!(Some(Note::E5), d_dorian.note_at(Note::D4, 8));
+        assert_eq!(Some(Note::C4), d_dorian.note_at(Note::D4, -1));
+        // root note not on scale root, use scale root below it
+        assert_eq!(Some(Note::D4), d_dorian.note_at(Note::E4, 0));
+        assert_eq!(None, d_dorian.note_at(Note::G9, 7));
+
+        let user = Scale::new(Note::C0, ScaleKind::User(0b1001_0000_0000));
+        assert_eq!(3, user.notes_per_octave());
+        assert_eq!(Some(Note::B0), user.note_at(Note::C0, 2));
+    }
+
+    #[test]
+    fn quantize() {
+        let c_major = Scale::new(Note::C4, ScaleKind::Major);
+        assert_eq!(Note::E4, c_major.quantize(Note::E4));
+        assert_eq!(Note::C4, c_major.quantize(Note::Cs4));
+        assert_eq!(Note::F4, c_major.quantize(Note::F4));
+        let blues = Scale::new(Note::A0, ScaleKind::Blues);
+        // A C D D# E G
+        assert_eq!(Note::E3, blues.quantize(Note::F3));
+        assert_eq!(Note::G3, blues.quantize(Note::Gs3));
+        assert_eq!(Note::Gs3, Scale::new(Note::C0, ScaleKind::Chromatic).quantize(Note::Gs3));
+    }
+
+    #[test]
+    fn chords() {
+        let c_major = Scale::new(Note::C4, ScaleKind::Major);
+        let mut ii7 = c_major.chord(Note::C4, 1, 4);
+        assert_eq!(Some(Note::D4), ii7.next());
+        assert_eq!(Some(Note::F4), ii7.next());
+        assert_eq!(Some(Note::A4), ii7.next());
+        assert_eq!(Some(Note::C5), ii7.next());
+        assert_eq!(None, ii7.next());
+
+        let mut notes = ChordShape::Minor.notes(Note::A3);
+        assert_eq!(Some(Note::A3), notes.next());
+        assert_eq!(Some(Note::C4), notes.next());
+        assert_eq!(Some(Note::E4), notes.next());
+        assert_eq!(Some(ChordShape::Minor7), ChordShape::identify(Note::D3, &[Note::F4, Note::A3, Note::C5]));
+        assert_eq!(Some(ChordShape::Major), ChordShape::identify(Note::G2, &[Note::B2, Note::D3]));
+        assert_eq!(None, ChordShape::identify(Note::C2, &[Note::Cs2]));
+    }
+}
@@ -0,0 +1,498 @@
+//! MIDI Tuning Standard (MTS)
+//! Retunes notes of a receiving device, either the whole keyboard at once (bulk dump),
+//! a few keys at a time (single note change) or as cents offsets repeated every octave (scale/octave).
+//! Sysex messages are complete byte sequences, including SYSEX_START and SYSEX_END markers.
+
+use core::convert::TryFrom;
+
+use crate::status::{SYSEX_END, SYSEX_START};
+use crate::universal::{UNIVERSAL_NON_REALTIME, UNIVERSAL_REALTIME};
+use crate::{MidiError, Note, U14, U7};
+
+/// MIDI Tuning Standard sub-ID #1
+const MTS: u8 = 0x08;
+const BULK_DUMP_REQUEST: u8 = 0x00;
+const BULK_DUMP: u8 = 0x01;
+const SINGLE_NOTE_CHANGE: u8 = 0x02;
+const SCALE_OCTAVE_1: u8 = 0x08;
+const SCALE_OCTAVE_2: u8 = 0x09;
+
+pub const TUNING_NAME_LEN: usize = 16;
+
+/// Length of a bulk tuning dump message
+pub const BULK_DUMP_LEN: usize = 6 + TUNING_NAME_LEN + 128 * 3 + 2;
+
+/// Max number of keys in a single note tuning change message
+pub const MAX_NOTE_CHANGES: usize = 127;
+
+/// Reference pitch of A4
+pub const A4_HZ: f32 = 440.0;
+
+const A4_CENTS: f32 = 6900.0;
+
+/// Pitch of a key, as a semitone and 14-bit fraction of semitone (in units of 100/16384 cents) above it
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NoteTuning {
+    pub semitone: U7,
+    pub fraction: U14,
+}
+
+impl NoteTuning {
+    /// Reserved value, receivers leave the key tuning unchanged
+    pub const NO_CHANGE: NoteTuning = NoteTuning { semitone: U7(0x7F), fraction: U14(0x3FFF) };
+
+    /// Standard 12-tone equal temperament tuning of note
+    pub fn equal(note: Note) -> Self {
+        NoteTuning { semitone: U7::from(note), fraction: U14::MIN }
+    }
+
+    /// Pitch in cents above C-1 (note 0), clamped to the MTS range
+    pub fn from_cents(cents: f32) -> Self {
+        if cents.is_nan() || cents <= 0.0 {
+            return NoteTuning { semitone: U7::MIN, fraction: U14::MIN };
+        }
+        let semitone = (cents / 100.0) as u32;
+        let mut fraction = round((cents / 100.0 - semitone as f32) * 16384.0) as u32;
+        let mut semitone = semitone;
+        if fraction >= 16384 {
+            semitone += 1;
+            fraction = 0;
+        }
+        if semitone > 127 || (semitone == 127 && fraction > 0x3FFE) {
+            // highest valid value, 0x3FFF is reserved
+            return NoteTuning { semitone: U7::MAX, fraction: U14(0x3FFE) };
+        }
+        NoteTuning { semitone: U7(semitone as u8), fraction: U14(fraction as u16) }
+    }
+
+    /// Pitch in cents above C-1 (note 0)
+    pub fn cents(&self) -> f32 {
+        self.semitone.0 as f32 * 100.0 + self.fraction.0 as f32 * 100.0 / 16384.0
+    }
+
+    /// Returns None if frequency is out of MTS range
+    pub fn from_frequency(hz: f32) -> Option<Self> {
+        let cents = hz_to_cents(hz)?;
+        if !(0.0..12800.0).contains(&cents) {
+            return None;
+        }
+        Some(NoteTuning::from_cents(cents))
+    }
+
+    pub fn frequency(&self) -> f32 {
+        cents_to_hz(self.cents())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        NoteTuning {
+            semitone: U7(bytes[0] & 0x7F),
+            fraction: U14::from((U7(bytes[2] & 0x7F), U7(bytes[1] & 0x7F))),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 3] {
+        let (lsb, msb): (U7, U7) = self.fraction.into();
+        [self.semitone.0, msb.0, lsb.0]
+    }
+}
+
+/// Frequency of note, detuned by cents
+pub fn note_frequency(note: Note, cents: f32) -> f32 {
+    cents_to_hz(note as u8 as f32 * 100.0 + cents)
+}
+
+/// Nearest note to frequency, and deviation from it in cents (-50 to +50)
+/// Returns None if frequency is outside of MIDI note range
+pub fn frequency_note(hz: f32) -> Option<(Note, f32)> {
+    let cents = hz_to_cents(hz)?;
+    let nearest = round(cents / 100.0);
+    if !(0..=127).contains(&nearest) {
+        return None;
+    }
+    Some((Note::try_from(nearest as u8).ok()?, cents - nearest as f32 * 100.0))
+}
+
+fn hz_to_cents(hz: f32) -> Option<f32> {
+    if !(hz.is_finite() && hz > 0.0) {
+        return None;
+    }
+    Some(A4_CENTS + 1200.0 * log2(hz / A4_HZ))
+}
+
+fn cents_to_hz(cents: f32) -> f32 {
+    A4_HZ * exp2((cents - A4_CENTS) / 1200.0)
+}
+
+fn round(value: f32) -> i32 {
+    if value < 0.0 {
+        (value - 0.5) as i32
+    } else {
+        (value + 0.5) as i32
+    }
+}
+
+/// Base 2 logarithm of finite positive value, `core` has no float math
+fn log2(value: f32) -> f32 {
+    let bits = value.to_bits();
+    let exponent = ((bits >> 23) & 0xFF) as i32 - 127;
+    // mantissa, in [1, 2)
+    let m = f32::from_bits((bits & 0x007F_FFFF) | 0x3F80_0000);
+    // ln(m) = 2 * atanh(z), z is at most 1/3 so the series converges fast
+    let z = (m - 1.0) / (m + 1.0);
+    let z2 = z * z;
+    let mut term = z;
+    let mut sum = 0.0;
+    for k in 0..7 {
+        sum += term / (2 * k + 1) as f32;
+        term *= z2;
+    }
+    exponent as f32 + 2.0 * sum * core::f32::consts::LOG2_E
+}
+
+/// Two to the power of value, `core` has no float math
+fn exp2(value: f32) -> f32 {
+    let mut int = value as i32;
+    if int as f32 > value {
+        int -= 1;
+    }
+    if int < -126 {
+        return 0.0;
+    }
+    if int > 127 {
+        return f32::INFINITY;
+    }
+    // e^y series, with y below ln(2)
+    let y = (value - int as f32) * core::f32::consts::LN_2;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for k in 1..10 {
+        term *= y / k as f32;
+        sum += term;
+    }
+    sum * f32::from_bits(((int + 127) as u32) << 23)
+}
+
+fn checked_header(sysex: &[u8], sub_id: u8, min_len: usize) -> Result<(), MidiError> {
+    if sysex.len() < min_len {
+        return Err(MidiError::TruncatedData);
+    }
+    match sysex {
+        [SYSEX_START, UNIVERSAL_NON_REALTIME | UNIVERSAL_REALTIME, _, MTS, id, ..] if *id == sub_id => {}
+        _ => return Err(MidiError::InvalidSysex),
+    }
+    if sysex[sysex.len() - 1] != SYSEX_END {
+        return Err(MidiError::InvalidSysex);
+    }
+    Ok(())
+}
+
+/// Request a device to send its tuning program as a bulk dump
+pub fn bulk_dump_request(device_id: u8, program: U7) -> [u8; 7] {
+    [SYSEX_START, UNIVERSAL_NON_REALTIME, device_id & 0x7F, MTS, BULK_DUMP_REQUEST, program.0, SYSEX_END]
+}
+
+/// Tuning of all 128 keys
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TuningTable {
+    pub program: U7,
+    /// ASCII name
+    pub name: [u8; TUNING_NAME_LEN],
+    pub notes: [NoteTuning; 128],
+}
+
+impl TuningTable {
+    /// Standard 12-tone equal temperament
+    pub fn equal(program: U7) -> Self {
+        let mut notes = [NoteTuning::equal(Note::C1m); 128];
+        for (key, tuning) in notes.iter_mut().enumerate() {
+            tuning.semitone = U7(key as u8);
+        }
+        TuningTable { program, name: [b' '; TUNING_NAME_LEN], notes }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = [b' '; TUNING_NAME_LEN];
+        for (dst, src) in self.name.iter_mut().zip(name.bytes()) {
+            *dst = src & 0x7F;
+        }
+        self
+    }
+
+    /// Equal temperament with octave-repeating cents offsets applied
+    pub fn from_scale(program: U7, scale: &ScaleTuning) -> Self {
+        let mut table = TuningTable::equal(program);
+        for (key, tuning) in table.notes.iter_mut().enumerate() {
+            *tuning = NoteTuning::from_cents(key as f32 * 100.0 + scale.cents[key % 12]);
+        }
+        table
+    }
+
+    pub fn tuning(&self, note: Note) -> NoteTuning {
+        self.notes[(note as u8 & 0x7F) as usize]
+    }
+
+    /// Write bulk tuning dump message to buffer, returns its length
+    pub fn to_bulk_dump(&self, device_id: u8, buffer: &mut [u8]) -> Result<usize, MidiError> {
+        if buffer.len() < BULK_DUMP_LEN {
+            return Err(MidiError::BufferFull);
+        }
+        buffer[..6].copy_from_slice(&[SYSEX_START, UNIVERSAL_NON_REALTIME, device_id & 0x7F, MTS, BULK_DUMP, self.program.0]);
+        buffer[6..6 + TUNING_NAME_LEN].copy_from_slice(&self.name);
+        for (key, tuning) in self.notes.iter().enumerate() {
+            let offset = 6 + TUNING_NAME_LEN + key * 3;
+            buffer[offset..offset + 3].copy_from_slice(&tuning.to_bytes());
+        }
+        buffer[BULK_DUMP_LEN - 2] = checksum(&buffer[1..BULK_DUMP_LEN - 2]);
+        buffer[BULK_DUMP_LEN - 1] = SYSEX_END;
+        Ok(BULK_DUMP_LEN)
+    }
+
+    /// Parse bulk tuning dump message
+    /// Checksum is verified unless `ignore_checksum` is set, many devices get it wrong
+    pub fn from_bulk_dump(sysex: &[u8], ignore_checksum: bool) -> Result<Self, MidiError> {
+        checked_header(sysex, BULK_DUMP, BULK_DUMP_LEN)?;
+        if sysex.len() != BULK_DUMP_LEN {
+            return Err(MidiError::InvalidSysex);
+        }
+        if !ignore_checksum && checksum(&sysex[1..BULK_DUMP_LEN - 2]) != sysex[BULK_DUMP_LEN - 2] {
+            return Err(MidiError::InvalidSysex);
+        }
+        let mut table = TuningTable::equal(U7(sysex[5] & 0x7F));
+        table.name.copy_from_slice(&sysex[6..6 + TUNING_NAME_LEN]);
+        for (key, tuning) in table.notes.iter_mut().enumerate() {
+            let offset = 6 + TUNING_NAME_LEN + key * 3;
+            *tuning = NoteTuning::from_bytes(&sysex[offset..offset + 3]);
+        }
+        Ok(table)
+    }
+}
+
+/// XOR of message bytes, from sub-ID up to last data byte
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, b| sum ^ b) & 0x7F
+}
+
+/// Retuning of a single key
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NoteChange {
+    pub key: Note,
+    pub tuning: NoteTuning,
+}
+
+/// Realtime Single Note Tuning Change message
+#[derive(Copy, Clone, Debug)]
+pub struct SingleNoteChange<'a> {
+    pub device_id: u8,
+    pub program: U7,
+    data: &'a [u8],
+}
+
+impl<'a> SingleNoteChange<'a> {
+    /// Write single note tuning change message to buffer, returns its length
+    pub fn write(device_id: u8, program: U7, changes: &[NoteChange], buffer: &mut [u8]) -> Result<usize, MidiError> {
+        if changes.len() > MAX_NOTE_CHANGES {
+            return Err(MidiError::SysexOutOfBounds);
+        }
+        let len = 8 + changes.len() * 4;
+        if buffer.len() < len {
+            return Err(MidiError::BufferFull);
+        }
+        buffer[..7].copy_from_slice(&[
+            SYSEX_START, UNIVERSAL_REALTIME, device_id & 0x7F, MTS, SINGLE_NOTE_CHANGE, program.0, changes.len() as u8,
+        ]);
+        for (change, chunk) in changes.iter().zip(buffer[7..len - 1].chunks_exact_mut(4)) {
+            chunk[0] = change.key as u8 & 0x7F;
+            chunk[1..].copy_from_slice(&change.tuning.to_bytes());
+        }
+        buffer[len - 1] = SYSEX_END;
+        Ok(len)
+    }
+
+    pub fn parse(sysex: &'a [u8]) -> Result<Self, MidiError> {
+        checked_header(sysex, SINGLE_NOTE_CHANGE, 8)?;
+        let count = sysex[6] as usize;
+        if sysex.len() != 8 + count * 4 {
+            return Err(MidiError::TruncatedData);
+        }
+        Ok(SingleNoteChange {
+            device_id: sysex[2],
+            program: U7(sysex[5] & 0x7F),
+            data: &sysex[7..sysex.len() - 1],
+        })
+    }
+
+    pub fn changes(&self) -> impl Iterator<Item=NoteChange> + 'a {
+        self.data.chunks_exact(4).filter_map(|chunk| Some(NoteChange {
+            key: Note::try_from(chunk[0] & 0x7F).ok()?,
+            tuning: NoteTuning::from_bytes(&chunk[1..]),
+        }))
+    }
+
+    /// Apply changes to table, skipping keys marked NO_CHANGE
+    pub fn apply(&self, table: &mut TuningTable) {
+        for change in self.changes() {
+            if change.tuning != NoteTuning::NO_CHANGE {
+                table.notes[change.key as usize] = change.tuning;
+            }
+        }
+    }
+}
+
+/// Cents offset of each pitch class (C to B), repeated every octave
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScaleTuning {
+    /// Bit set for each channel (0-15) to retune
+    pub channels: u16,
+    /// Offsets from equal temperament, -64 to +63 cents (1 byte format) or -100 to +100 cents (2 byte format)
+    pub cents: [f32; 12],
+}
+
+impl ScaleTuning {
+    /// Write scale/octave tuning message to buffer, returns its length
+    /// The 2 byte format has a resolution of 0.012 cents, the 1 byte format of 1 cent
+    pub fn write(&self, device_id: u8, realtime: bool, two_byte: bool, buffer: &mut [u8]) -> Result<usize, MidiError> {
+        let len = if two_byte { 9 + 24 } else { 9 + 12 };
+        if buffer.len() < len {
+            return Err(MidiError::BufferFull);
+        }
+        buffer[..8].copy_from_slice(&[
+            SYSEX_START,
+            if realtime { UNIVERSAL_REALTIME } else { UNIVERSAL_NON_REALTIME },
+            device_id & 0x7F,
+            MTS,
+            if two_byte { SCALE_OCTAVE_2 } else { SCALE_OCTAVE_1 },
+            (self.channels >> 14) as u8 & 0x03,
+            (self.channels >> 7) as u8 & 0x7F,
+            self.channels as u8 & 0x7F,
+        ]);
+        for (pc, cents) in self.cents.iter().enumerate() {
+            if two_byte {
+                let value = round((cents.clamp(-100.0, 100.0) + 100.0) / 200.0 * 16383.0) as u16;
+                buffer[8 + pc * 2] = (value >> 7) as u8 & 0x7F;
+                buffer[9 + pc * 2] = value as u8 & 0x7F;
+            } else {
+                buffer[8 + pc] = (round(cents.clamp(-64.0, 63.0)) + 64) as u8;
+            }
+        }
+        buffer[len - 1] = SYSEX_END;
+        Ok(len)
+    }
+
+    /// Parse scale/octave tuning message, in either format
+    pub fn parse(sysex: &[u8]) -> Result<Self, MidiError> {
+        let two_byte = sysex.get(4) == Some(&SCALE_OCTAVE_2);
+        let (sub_id, len) = if two_byte { (SCALE_OCTAVE_2, 9 + 24) } else { (SCALE_OCTAVE_1, 9 + 12) };
+        checked_header(sysex, sub_id, len)?;
+        if sysex.len() != len {
+            return Err(MidiError::InvalidSysex);
+        }
+        let channels = (sysex[5] as u16 & 0x03) << 14 | (sysex[6] as u16 & 0x7F) << 7 | sysex[7] as u16 & 0x7F;
+        let mut cents = [0.0; 12];
+        for (pc, cents) in cents.iter_mut().enumerate() {
+            *cents = if two_byte {
+                let value = (sysex[8 + pc * 2] as u16 & 0x7F) << 7 | sysex[9 + pc * 2] as u16 & 0x7F;
+                value as f32 * 200.0 / 16383.0 - 100.0
+            } else {
+                (sysex[8 + pc] & 0x7F) as f32 - 64.0
+            };
+        }
+        Ok(ScaleTuning { channels, cents })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(expected: f32, actual: f32, tolerance: f32) -> bool {
+        (expected - actual).abs() <= tolerance
+    }
+
+    #[test]
+    fn frequency_conversion() {
+        assert!(close(440.0, note_frequency(Note::A4, 0.0), 0.01));
+        assert!(close(261.6256, note_frequency(Note::C4, 0.0), 0.01));
+        assert!(close(8.1758, note_frequency(Note::C1m, 0.0), 0.001));
+        assert!(close(452.893, note_frequency(Note::A4, 50.0), 0.01));
+
+        let (note, cents) = frequency_note(445.0).unwrap();
+        assert_eq!(Note::A4, note);
+        assert!(close(19.56, cents, 0.05));
+        let (note, cents) = frequency_note(261.0).unwrap();
+        assert_eq!(Note::C4, note);
+        assert!(close(-4.15, cents, 0.05));
+        assert_eq!(None, frequency_note(0.0));
+        assert_eq!(None, frequency_note(20000.0));
+    }
+
+    #[test]
+    fn note_tuning() {
+        let tuning = NoteTuning::from_frequency(8.661_957).unwrap();
+        assert_eq!(NoteTuning { semitone: U7(1), fraction: U14(0) }, tuning);
+        let tuning = NoteTuning::from_frequency(440.0).unwrap();
+        assert_eq!(NoteTuning::equal(Note::A4), tuning);
+        let tuning = NoteTuning::from_cents(6950.0);
+        assert_eq!(NoteTuning { semitone: U7(69), fraction: U14(0x2000) }, tuning);
+        assert_eq!([69, 0x40, 0], tuning.to_bytes());
+        assert!(close(452.893, tuning.frequency(), 0.01));
+        assert_eq!(None, NoteTuning::from_frequency(14000.0));
+    }
+
+    #[test]
+    fn bulk_dump() {
+        let scale = ScaleTuning { channels: 0xFFFF, cents: [0.0, -10.0, 4.0, -6.0, -14.0, -2.0, -12.0, 2.0, -8.0, -16.0, -4.0, -12.0] };
+        let table = TuningTable::from_scale(U7(3), &scale).with_name("Just");
+        let mut buffer = [0; BULK_DUMP_LEN];
+        assert_eq!(BULK_DUMP_LEN, table.to_bulk_dump(0x10, &mut buffer).unwrap());
+        assert_eq!(&[0xF0, 0x7E, 0x10, 0x08, 0x01, 3, b'J'], &buffer[..7]);
+        assert_eq!(table, TuningTable::from_bulk_dump(&buffer, false).unwrap());
+
+        buffer[BULK_DUMP_LEN - 2] ^= 1;
+        assert!(TuningTable::from_bulk_dump(&buffer, false).is_err());
+        assert!(TuningTable::from_bulk_dump(&buffer, true).is_ok());
+        assert!(TuningTable::from_bulk_dump(&buffer[..100], true).is_err());
+        assert_eq!([0xF0, 0x7E, 0x10, 0x08, 0x00, 3, 0xF7], bulk_dump_request(0x10, U7(3)));
+    }
+
+    #[test]
+    fn single_note_change() {
+        let changes = [
+            NoteChange { key: Note::C4, tuning: NoteTuning::from_cents(6012.5) },
+            NoteChange { key: Note::D4, tuning: NoteTuning::NO_CHANGE },
+        ];
+        let mut buffer = [0; 32];
+        let len = SingleNoteChange::write(0x7F, U7(0), &changes, &mut buffer).unwrap();
+        assert_eq!(16, len);
+        let message = SingleNoteChange::parse(&buffer[..len]).unwrap();
+        let mut parsed = message.changes();
+        assert_eq!(Some(changes[0]), parsed.next());
+        assert_eq!(Some(changes[1]), parsed.next());
+        assert_eq!(None, parsed.next());
+
+        let mut table = TuningTable::equal(U7(0));
+        message.apply(&mut table);
+        assert_eq!(changes[0].tuning, table.tuning(Note::C4));
+        assert_eq!(NoteTuning::equal(Note::D4), table.tuning(Note::D4));
+        assert!(SingleNoteChange::parse(&buffer[..len - 4]).is_err());
+    }
+
+    #[test]
+    fn scale_octave() {
+        let scale = ScaleTuning { channels: 0x8001, cents: [0.0, 10.0, -20.0, 30.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -64.0, 63.0] };
+        let mut buffer = [0; 40];
+        let len = scale.write(0x7F, false, false, &mut buffer).unwrap();
+        assert_eq!(&[0xF0, 0x7E, 0x7F, 0x08, 0x08, 0x02, 0x00, 0x01, 64, 74, 44], &buffer[..11]);
+        assert_eq!(scale, ScaleTuning::parse(&buffer[..len]).unwrap());
+
+        let len = scale.write(0x7F, true, true, &mut buffer).unwrap();
+        assert_eq!(33, len);
+        let parsed = ScaleTuning::parse(&buffer[..len]).unwrap();
+        for (expected, actual) in scale.cents.iter().zip(parsed.cents.iter()) {
+            assert!(close(*expected, *actual, 0.02));
+        }
+    }
+}
@@ -0,0 +1,829 @@
+//! MIDI 2.0 Universal MIDI Packet (UMP) definitions
+//! A UMP is made of one to four 32-bit words, the Message Type (MT) in the top nibble defining the size.
+//! Translation to and from MIDI 1.0 follows the default rules of the UMP spec (M2-104-UM), Appendix D.
+
+use core::convert::TryFrom;
+use core::iter::FromIterator;
+
+use heapless::Vec;
+
+use crate::nrpn::{CC_DATA_ENTRY_LSB, CC_DATA_ENTRY_MSB, CC_NRPN_LSB, CC_NRPN_MSB, CC_RPN_LSB, CC_RPN_MSB};
+use crate::status::{is_channel_status, SYSEX_END, SYSEX_START};
+use crate::{
+    Bend, CodeIndexNumber, Control, Cull, MidiChannel, MidiError, MidiMessage, Note, Packet, PacketList, Program, Status,
+    U14, U4, U7,
+};
+
+/// UMP Message Type, top nibble of the first word
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum MessageType {
+    /// NOOP and Jitter Reduction timestamps
+    Utility = 0x0,
+    /// System Common and System Realtime
+    System = 0x1,
+    /// MIDI 1.0 Channel Voice
+    Midi1ChannelVoice = 0x2,
+    /// 7-bit System Exclusive
+    Data64 = 0x3,
+    /// MIDI 2.0 Channel Voice
+    Midi2ChannelVoice = 0x4,
+    /// 8-bit System Exclusive and Mixed Data Set
+    Data128 = 0x5,
+    Reserved6 = 0x6,
+    Reserved7 = 0x7,
+    Reserved8 = 0x8,
+    Reserved9 = 0x9,
+    ReservedA = 0xA,
+    ReservedB = 0xB,
+    ReservedC = 0xC,
+    /// Flex Data (tempo, lyrics, etc.)
+    FlexData = 0xD,
+    ReservedE = 0xE,
+    /// UMP Stream (endpoint discovery, etc.)
+    Stream = 0xF,
+}
+
+impl From<u8> for MessageType {
+    fn from(nibble: u8) -> Self {
+        use MessageType::*;
+        match nibble & 0x0F {
+            0x0 => Utility,
+            0x1 => System,
+            0x2 => Midi1ChannelVoice,
+            0x3 => Data64,
+            0x4 => Midi2ChannelVoice,
+            0x5 => Data128,
+            0x6 => Reserved6,
+            0x7 => Reserved7,
+            0x8 => Reserved8,
+            0x9 => Reserved9,
+            0xA => ReservedA,
+            0xB => ReservedB,
+            0xC => ReservedC,
+            0xD => FlexData,
+            0xE => ReservedE,
+            _ => Stream,
+        }
+    }
+}
+
+impl MessageType {
+    /// Number of 32-bit words in a packet of this type
+    pub fn word_len(&self) -> usize {
+        use MessageType::*;
+        match self {
+            Utility | System | Midi1ChannelVoice | Reserved6 | Reserved7 => 1,
+            Data64 | Midi2ChannelVoice | Reserved8 | Reserved9 | ReservedA => 2,
+            ReservedB | ReservedC => 3,
+            Data128 | FlexData | ReservedE | Stream => 4,
+        }
+    }
+}
+
+/// Status of a 7-bit sysex UMP (MT 0x3)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum SysexStatus {
+    /// Whole sysex message fits in a single UMP
+    Complete = 0x0,
+    Start = 0x1,
+    Continue = 0x2,
+    End = 0x3,
+}
+
+impl TryFrom<u8> for SysexStatus {
+    type Error = MidiError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x0 => Ok(SysexStatus::Complete),
+            0x1 => Ok(SysexStatus::Start),
+            0x2 => Ok(SysexStatus::Continue),
+            0x3 => Ok(SysexStatus::End),
+            _ => Err(MidiError::InvalidStatus(value)),
+        }
+    }
+}
+
+/// Max number of data bytes carried by a single 7-bit sysex UMP
+pub const SYSEX7_MAX_BYTES: usize = 6;
+
+/// A Universal MIDI Packet, 32 to 128 bits
+/// Unused trailing words are always zero
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ump {
+    words: [u32; 4],
+}
+
+impl Ump {
+    pub fn from_raw(words: [u32; 4]) -> Self {
+        let mut ump = Ump { words };
+        let len = ump.word_len();
+        ump.words[len..].iter_mut().for_each(|w| *w = 0);
+        ump
+    }
+
+    /// Utility NOOP, as sent to pad streams
+    pub fn noop() -> Self {
+        Ump::default()
+    }
+
+    pub fn message_type(&self) -> MessageType {
+        MessageType::from((self.words[0] >> 28) as u8)
+    }
+
+    /// UMP group is the equivalent of the USB-MIDI cable number
+    pub fn group(&self) -> U4 {
+        U4::cull((self.words[0] >> 24) as u8)
+    }
+
+    pub fn with_group(mut self, group: U4) -> Self {
+        self.words[0] = self.words[0] & 0xF0FF_FFFF | (u8::from(group) as u32) << 24;
+        self
+    }
+
+    /// Size of the packet, in 32-bit words
+    pub fn word_len(&self) -> usize {
+        self.message_type().word_len()
+    }
+
+    /// Only the significant words of the packet
+    pub fn words(&self) -> &[u32] {
+        &self.words[..self.word_len()]
+    }
+
+    /// Status nibble of channel voice and sysex messages
+    fn status_nibble(&self) -> u8 {
+        ((self.words[0] >> 20) & 0x0F) as u8
+    }
+
+    /// Bytes of first word following the MT/group byte
+    fn word0_bytes(&self) -> [u8; 3] {
+        let [_, b1, b2, b3] = self.words[0].to_be_bytes();
+        [b1, b2, b3]
+    }
+
+    fn with_header(mt: MessageType, header: [u8; 3]) -> Ump {
+        Ump { words: [u32::from_be_bytes([(mt as u8) << 4, header[0], header[1], header[2]]), 0, 0, 0] }
+    }
+
+    /// Build a 7-bit sysex UMP from up to 6 data bytes (excluding SYSEX_START and SYSEX_END markers)
+    pub fn sysex7(status: SysexStatus, data: &[u8]) -> Result<Ump, MidiError> {
+        if data.len() > SYSEX7_MAX_BYTES {
+            return Err(MidiError::SysexOutOfBounds);
+        }
+        let mut bytes = [0; 8];
+        bytes[0] = (MessageType::Data64 as u8) << 4;
+        bytes[1] = (status as u8) << 4 | data.len() as u8;
+        bytes[2..2 + data.len()].copy_from_slice(data);
+        Ok(Ump {
+            words: [
+                u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+                0,
+                0,
+            ],
+        })
+    }
+
+    /// Sysex status of a 7-bit sysex UMP
+    pub fn sysex7_status(&self) -> Option<SysexStatus> {
+        if self.message_type() != MessageType::Data64 {
+            return None;
+        }
+        SysexStatus::try_from(self.status_nibble()).ok()
+    }
+
+    /// Data bytes carried by a 7-bit sysex UMP, _excluding_ SYSEX_START and SYSEX_END markers
+    /// Returns an empty body if packet holds no sysex data
+    pub fn sysex7_body(&self) -> Vec<u8, SYSEX7_MAX_BYTES> {
+        let mut body = Vec::new();
+        if self.message_type() == MessageType::Data64 {
+            let len = ((self.words[0] >> 16) & 0x0F) as usize;
+            let [_, _, b0, b1] = self.words[0].to_be_bytes();
+            let [b2, b3, b4, b5] = self.words[1].to_be_bytes();
+            let all = [b0, b1, b2, b3, b4, b5];
+            let _ = body.extend_from_slice(&all[..len.min(SYSEX7_MAX_BYTES)]);
+        }
+        body
+    }
+}
+
+/// MIDI 2.0 Protocol channel voice messages (MT 0x4)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Midi2Message {
+    /// Channel, note, velocity, attribute type, attribute
+    NoteOff(MidiChannel, Note, u16, u8, u16),
+    /// Channel, note, velocity, attribute type, attribute
+    NoteOn(MidiChannel, Note, u16, u8, u16),
+    NotePressure(MidiChannel, Note, u32),
+    /// Channel, note, controller index, value
+    RegisteredPerNoteController(MidiChannel, Note, u8, u32),
+    /// Channel, note, controller index, value
+    AssignablePerNoteController(MidiChannel, Note, u8, u32),
+    /// Channel, note, option flags (detach, reset)
+    PerNoteManagement(MidiChannel, Note, u8),
+    ControlChange(MidiChannel, Control, u32),
+    /// RPN - Channel, bank, index, value
+    RegisteredController(MidiChannel, U7, U7, u32),
+    /// NRPN - Channel, bank, index, value
+    AssignableController(MidiChannel, U7, U7, u32),
+    /// Channel, bank, index, signed increment
+    RelativeRegisteredController(MidiChannel, U7, U7, i32),
+    /// Channel, bank, index, signed increment
+    RelativeAssignableController(MidiChannel, U7, U7, i32),
+    /// Bank is only sent if present
+    ProgramChange(MidiChannel, Program, Option<U14>),
+    ChannelPressure(MidiChannel, u32),
+    /// Centered on 0x8000_0000
+    PitchBend(MidiChannel, u32),
+    PerNotePitchBend(MidiChannel, Note, u32),
+}
+
+const REGISTERED_PER_NOTE_CONTROLLER: u8 = 0x0;
+const ASSIGNABLE_PER_NOTE_CONTROLLER: u8 = 0x1;
+const REGISTERED_CONTROLLER: u8 = 0x2;
+const ASSIGNABLE_CONTROLLER: u8 = 0x3;
+const RELATIVE_REGISTERED_CONTROLLER: u8 = 0x4;
+const RELATIVE_ASSIGNABLE_CONTROLLER: u8 = 0x5;
+const PER_NOTE_PITCH_BEND: u8 = 0x6;
+const NOTE_OFF: u8 = 0x8;
+const NOTE_ON: u8 = 0x9;
+const NOTE_PRESSURE: u8 = 0xA;
+const CONTROL_CHANGE: u8 = 0xB;
+const PROGRAM_CHANGE: u8 = 0xC;
+const CHANNEL_PRESSURE: u8 = 0xD;
+const PITCH_BEND: u8 = 0xE;
+const PER_NOTE_MANAGEMENT: u8 = 0xF;
+
+/// Program Change option flag: bank select is valid
+const BANK_VALID: u8 = 0x01;
+
+const CC_BANK_SELECT_MSB: u8 = 0;
+const CC_BANK_SELECT_LSB: u8 = 32;
+
+impl From<Midi2Message> for Ump {
+    fn from(message: Midi2Message) -> Self {
+        use Midi2Message::*;
+        let (status, ch, index1, index2, data) = match message {
+            NoteOff(ch, note, vel, attr_type, attr) => (NOTE_OFF, ch, note as u8, attr_type, (vel as u32) << 16 | attr as u32),
+            NoteOn(ch, note, vel, attr_type, attr) => (NOTE_ON, ch, note as u8, attr_type, (vel as u32) << 16 | attr as u32),
+            NotePressure(ch, note, value) => (NOTE_PRESSURE, ch, note as u8, 0, value),
+            RegisteredPerNoteController(ch, note, index, value) => (REGISTERED_PER_NOTE_CONTROLLER, ch, note as u8, index, value),
+            AssignablePerNoteController(ch, note, index, value) => (ASSIGNABLE_PER_NOTE_CONTROLLER, ch, note as u8, index, value),
+            PerNoteManagement(ch, note, flags) => (PER_NOTE_MANAGEMENT, ch, note as u8, flags, 0),
+            ControlChange(ch, cc, value) => (CONTROL_CHANGE, ch, cc.0, 0, value),
+            RegisteredController(ch, bank, index, value) => (REGISTERED_CONTROLLER, ch, bank.0, index.0, value),
+            AssignableController(ch, bank, index, value) => (ASSIGNABLE_CONTROLLER, ch, bank.0, index.0, value),
+            RelativeRegisteredController(ch, bank, index, value) => (RELATIVE_REGISTERED_CONTROLLER, ch, bank.0, index.0, value as u32),
+            RelativeAssignableController(ch, bank, index, value) => (RELATIVE_ASSIGNABLE_CONTROLLER, ch, bank.0, index.0, value as u32),
+            ProgramChange(ch, program, Some(bank)) => {
+                let (lsb, msb): (U7, U7) = bank.into();
+                (PROGRAM_CHANGE, ch, 0, BANK_VALID, u32::from_be_bytes([program.0, 0, msb.0, lsb.0]))
+            }
+            ProgramChange(ch, program, None) => (PROGRAM_CHANGE, ch, 0, 0, (program.0 as u32) << 24),
+            ChannelPressure(ch, value) => (CHANNEL_PRESSURE, ch, 0, 0, value),
+            PitchBend(ch, value) => (PITCH_BEND, ch, 0, 0, value),
+            PerNotePitchBend(ch, note, value) => (PER_NOTE_PITCH_BEND, ch, note as u8, 0, value),
+        };
+        let mut ump = Ump::with_header(MessageType::Midi2ChannelVoice, [status << 4 | (ch.0 & 0x0F), index1, index2]);
+        ump.words[1] = data;
+        ump
+    }
+}
+
+impl TryFrom<Ump> for Midi2Message {
+    type Error = MidiError;
+
+    fn try_from(ump: Ump) -> Result<Self, Self::Error> {
+        use Midi2Message::*;
+        if ump.message_type() != MessageType::Midi2ChannelVoice {
+            return Err(MidiError::BadUmp(ump.words[0]));
+        }
+        let [status, index1, index2] = ump.word0_bytes();
+        let ch = MidiChannel(status & 0x0F);
+        let data = ump.words[1];
+        let note = || Note::try_from(u8::from(U7::cull(index1)));
+        Ok(match status >> 4 {
+            NOTE_OFF => NoteOff(ch, note()?, (data >> 16) as u16, index2, data as u16),
+            NOTE_ON => NoteOn(ch, note()?, (data >> 16) as u16, index2, data as u16),
+            NOTE_PRESSURE => NotePressure(ch, note()?, data),
+            REGISTERED_PER_NOTE_CONTROLLER => RegisteredPerNoteController(ch, note()?, index2, data),
+            ASSIGNABLE_PER_NOTE_CONTROLLER => AssignablePerNoteController(ch, note()?, index2, data),
+            PER_NOTE_MANAGEMENT => PerNoteManagement(ch, note()?, index2),
+            CONTROL_CHANGE => ControlChange(ch, U7::cull(index1), data),
+            REGISTERED_CONTROLLER => RegisteredController(ch, U7::cull(index1), U7::cull(index2), data),
+            ASSIGNABLE_CONTROLLER => AssignableController(ch, U7::cull(index1), U7::cull(index2), data),
+            RELATIVE_REGISTERED_CONTROLLER => RelativeRegisteredController(ch, U7::cull(index1), U7::cull(index2), data as i32),
+            RELATIVE_ASSIGNABLE_CONTROLLER => RelativeAssignableController(ch, U7::cull(index1), U7::cull(index2), data as i32),
+            PROGRAM_CHANGE => {
+                let [program, _, msb, lsb] = data.to_be_bytes();
+                let bank = if index2 & BANK_VALID != 0 {
+                    Some(U14::from((U7::cull(lsb), U7::cull(msb))))
+                } else {
+                    None
+                };
+                ProgramChange(ch, U7::cull(program), bank)
+            }
+            CHANNEL_PRESSURE => ChannelPressure(ch, data),
+            PITCH_BEND => PitchBend(ch, data),
+            PER_NOTE_PITCH_BEND => PerNotePitchBend(ch, note()?, data),
+            _ => return Err(MidiError::BadUmp(ump.words[0])),
+        })
+    }
+}
+
+/// Min-Center-Max upscaling, as per the UMP spec
+/// Preserves the minimum, center and maximum values of the source range
+pub fn scale_up(value: u32, src_bits: u8, dst_bits: u8) -> u32 {
+    let scale_bits = dst_bits - src_bits;
+    let shifted = value << scale_bits;
+    let src_center = 1 << (src_bits - 1);
+    if value <= src_center {
+        return shifted;
+    }
+    // expand bit repeat pattern of lower bits
+    let repeat_bits = src_bits - 1;
+    let repeat_mask = (1 << repeat_bits) - 1;
+    let mut repeat_value = value & repeat_mask;
+    if scale_bits > repeat_bits {
+        repeat_value <<= scale_bits - repeat_bits;
+    } else {
+        repeat_value >>= repeat_bits - scale_bits;
+    }
+    let mut result = shifted;
+    while repeat_value != 0 {
+        result |= repeat_value;
+        repeat_value >>= repeat_bits;
+    }
+    result
+}
+
+/// Downscaling is a simple bit shift, as per the UMP spec
+pub fn scale_down(value: u32, src_bits: u8, dst_bits: u8) -> u32 {
+    value >> (src_bits - dst_bits)
+}
+
+fn up7_16(value: U7) -> u16 {
+    scale_up(value.0 as u32, 7, 16) as u16
+}
+
+fn up7_32(value: U7) -> u32 {
+    scale_up(value.0 as u32, 7, 32)
+}
+
+fn down16_7(value: u16) -> U7 {
+    U7::cull(scale_down(value as u32, 16, 7) as u8)
+}
+
+fn down32_7(value: u32) -> U7 {
+    U7::cull(scale_down(value, 32, 7) as u8)
+}
+
+fn down32_14(value: u32) -> U14 {
+    U14::cull(scale_down(value, 32, 14) as u16)
+}
+
+/// MIDI 1.0 to MIDI 2.0 Protocol translation
+/// Only applies to channel voice messages
+impl TryFrom<MidiMessage> for Midi2Message {
+    type Error = MidiError;
+
+    fn try_from(message: MidiMessage) -> Result<Self, Self::Error> {
+        Ok(match message {
+            // Note On with zero velocity is a Note Off with default velocity
+            MidiMessage::NoteOn(ch, note, U7(0)) => Midi2Message::NoteOff(ch, note, up7_16(U7(0x40)), 0, 0),
+            MidiMessage::NoteOn(ch, note, vel) => Midi2Message::NoteOn(ch, note, up7_16(vel), 0, 0),
+            MidiMessage::NoteOff(ch, note, vel) => Midi2Message::NoteOff(ch, note, up7_16(vel), 0, 0),
+            MidiMessage::NotePressure(ch, note, pres) => Midi2Message::NotePressure(ch, note, up7_32(pres)),
+            MidiMessage::ChannelPressure(ch, pres) => Midi2Message::ChannelPressure(ch, up7_32(pres)),
+            MidiMessage::ProgramChange(ch, program) => Midi2Message::ProgramChange(ch, program, None),
+            MidiMessage::ControlChange(ch, cc, value) => Midi2Message::ControlChange(ch, cc, up7_32(value)),
+            MidiMessage::PitchBend(ch, bend) => Midi2Message::PitchBend(ch, scale_up(u16::from(bend) as u32, 14, 32)),
+            _ => return Err(MidiError::Untranslatable),
+        })
+    }
+}
+
+impl Midi2Message {
+    /// MIDI 2.0 to MIDI 1.0 Protocol translation
+    /// Some messages expand to multiple MIDI 1.0 messages (RPN, Program Change with bank)
+    /// Per-note controllers, per-note pitch bend and relative controllers have no MIDI 1.0 equivalent
+    pub fn to_midi1(&self) -> Result<PacketList, MidiError> {
+        use MidiMessage::*;
+        let cc = |ch: MidiChannel, cc: u8, value: U7| ControlChange(ch, U7(cc), value);
+        let mut messages: Vec<MidiMessage, 4> = Vec::new();
+        let mut push = |msg| messages.push(msg).map_err(|_| MidiError::BufferFull);
+        match *self {
+            Midi2Message::NoteOff(ch, note, vel, ..) => push(NoteOff(ch, note, down16_7(vel)))?,
+            Midi2Message::NoteOn(ch, note, vel, ..) => {
+                // a zero velocity would turn the Note On into a Note Off
+                push(NoteOn(ch, note, down16_7(vel).max(U7(1))))?
+            }
+            Midi2Message::NotePressure(ch, note, value) => push(NotePressure(ch, note, down32_7(value)))?,
+            Midi2Message::ControlChange(ch, control, value) => push(ControlChange(ch, control, down32_7(value)))?,
+            Midi2Message::RegisteredController(ch, bank, index, value) => {
+                let (lsb, msb) = down32_14(value).into();
+                push(cc(ch, CC_RPN_MSB, bank))?;
+                push(cc(ch, CC_RPN_LSB, index))?;
+                push(cc(ch, CC_DATA_ENTRY_MSB, msb))?;
+                push(cc(ch, CC_DATA_ENTRY_LSB, lsb))?;
+            }
+            Midi2Message::AssignableController(ch, bank, index, value) => {
+                let (lsb, msb) = down32_14(value).into();
+                push(cc(ch, CC_NRPN_MSB, bank))?;
+                push(cc(ch, CC_NRPN_LSB, index))?;
+                push(cc(ch, CC_DATA_ENTRY_MSB, msb))?;
+                push(cc(ch, CC_DATA_ENTRY_LSB, lsb))?;
+            }
+            Midi2Message::ProgramChange(ch, program, bank) => {
+                if let Some(bank) = bank {
+                    let (lsb, msb) = bank.into();
+                    push(cc(ch, CC_BANK_SELECT_MSB, msb))?;
+                    push(cc(ch, CC_BANK_SELECT_LSB, lsb))?;
+                }
+                push(ProgramChange(ch, program))?;
+            }
+            Midi2Message::ChannelPressure(ch, value) => push(ChannelPressure(ch, down32_7(value)))?,
+            Midi2Message::PitchBend(ch, value) => push(PitchBend(ch, Bend::from(down32_14(value))))?,
+            _ => return Err(MidiError::Untranslatable),
+        }
+        Ok(PacketList::from_iter(messages.into_iter().map(Packet::from)))
+    }
+}
+
+/// USB-MIDI 1.0 Event Packet to UMP
+/// Cable number becomes the UMP group
+/// Channel voice messages are kept as MIDI 1.0 Protocol (MT 0x2)
+impl TryFrom<Packet> for Ump {
+    type Error = MidiError;
+
+    fn try_from(packet: Packet) -> Result<Self, Self::Error> {
+        let group = U4::cull(packet.cable_number());
+        let payload = packet.payload();
+        let ump = match packet.code_index_number() {
+            CodeIndexNumber::Sysex => {
+                if payload[0] == SYSEX_START {
+                    Ump::sysex7(SysexStatus::Start, &payload[1..])?
+                } else {
+                    Ump::sysex7(SysexStatus::Continue, payload)?
+                }
+            }
+            CodeIndexNumber::SystemCommonLen1 if payload[0] == SYSEX_END => Ump::sysex7(SysexStatus::End, &[])?,
+            CodeIndexNumber::SysexEndsNext2 | CodeIndexNumber::SysexEndsNext3 => {
+                let body = &payload[..payload.len() - 1];
+                if body[0] == SYSEX_START {
+                    Ump::sysex7(SysexStatus::Complete, &body[1..])?
+                } else {
+                    Ump::sysex7(SysexStatus::End, body)?
+                }
+            }
+            CodeIndexNumber::MiscFunction | CodeIndexNumber::CableEvents => return Err(MidiError::BadPacket(packet)),
+            _ => {
+                let mut header = [0; 3];
+                header[..payload.len()].copy_from_slice(payload);
+                if is_channel_status(header[0]) {
+                    Ump::with_header(MessageType::Midi1ChannelVoice, header)
+                } else {
+                    Ump::with_header(MessageType::System, header)
+                }
+            }
+        };
+        Ok(ump.with_group(group))
+    }
+}
+
+/// UMP to USB-MIDI 1.0 Event Packet
+/// Only System, MIDI 1.0 Channel Voice and sysex UMPs that fit a single packet can be converted,
+/// use a `UmpSplitter` to convert any sysex UMP.
+/// MIDI 2.0 Channel Voice messages need to be translated using `Midi2Message::to_midi1()`.
+impl TryFrom<Ump> for Packet {
+    type Error = MidiError;
+
+    fn try_from(ump: Ump) -> Result<Self, Self::Error> {
+        let cable = u8::from(ump.group());
+        let mut bytes = [0; 4];
+        match ump.message_type() {
+            MessageType::System | MessageType::Midi1ChannelVoice => {
+                let header = ump.word0_bytes();
+                let status = Status::try_from(header[0])?;
+                let len = status.expected_len() as usize;
+                bytes[0] = CodeIndexNumber::from(status) as u8;
+                bytes[1..1 + len].copy_from_slice(&header[..len]);
+            }
+            MessageType::Data64 => {
+                let body = ump.sysex7_body();
+                let len = body.len();
+                match (ump.sysex7_status(), len) {
+                    (Some(SysexStatus::Start), 2) => {
+                        bytes[0] = CodeIndexNumber::Sysex as u8;
+                        bytes[1] = SYSEX_START;
+                        bytes[2..4].copy_from_slice(&body);
+                    }
+                    (Some(SysexStatus::Continue), 3) => {
+                        bytes[0] = CodeIndexNumber::Sysex as u8;
+                        bytes[1..4].copy_from_slice(&body);
+                    }
+                    (Some(SysexStatus::End), 0..=2) => {
+                        bytes[0] = CodeIndexNumber::end_sysex(len as u8 + 1)? as u8;
+                        bytes[1..1 + len].copy_from_slice(&body);
+                        bytes[1 + len] = SYSEX_END;
+                    }
+                    (Some(SysexStatus::Complete), 0..=1) => {
+                        bytes[0] = CodeIndexNumber::end_sysex(len as u8 + 2)? as u8;
+                        bytes[1] = SYSEX_START;
+                        bytes[2..2 + len].copy_from_slice(&body);
+                        bytes[2 + len] = SYSEX_END;
+                    }
+                    _ => return Err(MidiError::SysexOutOfBounds),
+                }
+            }
+            _ => return Err(MidiError::BadUmp(ump.words[0])),
+        }
+        Ok(Packet::from_raw(bytes).with_cable_num(cable))
+    }
+}
+
+/// Sysex data bytes held by a `UmpSplitter` between UMPs, as they did not fill a packet
+const SPLIT_CARRY: usize = 2;
+
+/// UMPs to USB-MIDI 1.0 Event Packets, splitting 7-bit sysex UMPs into as many packets as needed
+/// Sysex data bytes that do not fill a packet are carried over to the next UMP of the sysex.
+/// Other UMPs are converted one to one. Use one splitter per group.
+#[derive(Default, Debug)]
+pub struct UmpSplitter {
+    carry: Vec<u8, SPLIT_CARRY>,
+    in_sysex: bool,
+}
+
+impl UmpSplitter {
+    /// Packets completed by UMP, up to 3 for a sysex UMP
+    pub fn split(&mut self, ump: Ump) -> Result<PacketList, MidiError> {
+        let status = match ump.sysex7_status() {
+            Some(status) => status,
+            None => return Ok(PacketList::single(Packet::try_from(ump)?)),
+        };
+        let mut bytes: Vec<u8, { SPLIT_CARRY + SYSEX7_MAX_BYTES + 2 }> = Vec::new();
+        match status {
+            SysexStatus::Start | SysexStatus::Complete => {
+                // drop unterminated sysex
+                self.carry.clear();
+                let _ = bytes.push(SYSEX_START);
+            }
+            _ if !self.in_sysex => return Err(MidiError::InvalidSysex),
+            _ => {
+                let _ = bytes.extend_from_slice(&self.carry);
+                self.carry.clear();
+            }
+        }
+        let _ = bytes.extend_from_slice(&ump.sysex7_body());
+        let end = matches!(status, SysexStatus::End | SysexStatus::Complete);
+        if end {
+            let _ = bytes.push(SYSEX_END);
+        }
+        self.in_sysex = !end;
+
+        let cable = u8::from(ump.group());
+        let mut packets = PacketList::default();
+        let mut chunks = bytes.chunks(3).peekable();
+        while let Some(chunk) = chunks.next() {
+            let last = chunks.peek().is_none();
+            let cin = match (last, end) {
+                (true, true) => CodeIndexNumber::end_sysex(chunk.len() as u8)?,
+                (true, false) if chunk.len() < 3 => {
+                    let _ = self.carry.extend_from_slice(chunk);
+                    break;
+                }
+                _ => CodeIndexNumber::Sysex,
+            };
+            let mut raw = [cin as u8, 0, 0, 0];
+            raw[1..1 + chunk.len()].copy_from_slice(chunk);
+            let _ = packets.push(Packet::from_raw(raw).with_cable_num(cable));
+        }
+        Ok(packets)
+    }
+}
+
+/// USB-MIDI 1.0 Event Packets to UMPs, packing sysex data bytes into 7-bit sysex UMPs
+/// A sysex UMP is produced once it is full and more data follows, or when the sysex ends.
+/// Other packets are converted one to one. Use one packer per cable.
+#[derive(Default, Debug)]
+pub struct UmpPacker {
+    body: Vec<u8, SYSEX7_MAX_BYTES>,
+    in_sysex: bool,
+    // a Start UMP was produced for current sysex
+    started: bool,
+}
+
+impl UmpPacker {
+    /// UMPs completed by packet, up to 2 for a sysex packet
+    pub fn pack(&mut self, packet: Packet) -> Result<Vec<Ump, 2>, MidiError> {
+        let mut umps = Vec::new();
+        let payload = packet.payload();
+        let sysex = match packet.code_index_number() {
+            CodeIndexNumber::Sysex | CodeIndexNumber::SysexEndsNext2 | CodeIndexNumber::SysexEndsNext3 => true,
+            CodeIndexNumber::SystemCommonLen1 => payload[0] == SYSEX_END,
+            _ => false,
+        };
+        if !sysex {
+            let _ = umps.push(Ump::try_from(packet)?);
+            return Ok(umps);
+        }
+
+        let group = U4::cull(packet.cable_number());
+        for &byte in payload {
+            match byte {
+                SYSEX_START => {
+                    self.body.clear();
+                    self.in_sysex = true;
+                    self.started = false;
+                }
+                _ if !self.in_sysex => return Err(MidiError::InvalidSysex),
+                SYSEX_END => {
+                    let status = if self.started { SysexStatus::End } else { SysexStatus::Complete };
+                    let _ = umps.push(Ump::sysex7(status, &self.body)?.with_group(group));
+                    self.body.clear();
+                    self.in_sysex = false;
+                }
+                _ => {
+                    if self.body.is_full() {
+                        let status = if self.started { SysexStatus::Continue } else { SysexStatus::Start };
+                        let _ = umps.push(Ump::sysex7(status, &self.body)?.with_group(group));
+                        self.body.clear();
+                        self.started = true;
+                    }
+                    let _ = self.body.push(byte);
+                }
+            }
+        }
+        Ok(umps)
+    }
+}
+
+/// MIDI 1.0 message to UMP (group 0)
+/// Channel voice messages are kept as MIDI 1.0 Protocol (MT 0x2)
+impl TryFrom<MidiMessage> for Ump {
+    type Error = MidiError;
+
+    fn try_from(message: MidiMessage) -> Result<Self, Self::Error> {
+        Ump::try_from(Packet::from(message))
+    }
+}
+
+/// UMP to MIDI 1.0 message
+/// MIDI 2.0 Channel Voice messages are translated if they map to a single MIDI 1.0 message
+impl TryFrom<Ump> for MidiMessage {
+    type Error = MidiError;
+
+    fn try_from(ump: Ump) -> Result<Self, Self::Error> {
+        if ump.message_type() == MessageType::Midi2ChannelVoice {
+            let packets = Midi2Message::try_from(ump)?.to_midi1()?;
+            return match packets.as_slice() {
+                [packet] => MidiMessage::try_from(*packet),
+                _ => Err(MidiError::Untranslatable),
+            };
+        }
+        MidiMessage::try_from(Packet::try_from(ump)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+
+    #[test]
+    fn scale_min_center_max() {
+        assert_eq!(0, scale_up(0, 7, 16));
+        assert_eq!(0x8000, scale_up(0x40, 7, 16));
+        assert_eq!(0xFFFF, scale_up(0x7F, 7, 16));
+        assert_eq!(0xFFFF_FFFF, scale_up(0x7F, 7, 32));
+        assert_eq!(0x8000_0000, scale_up(0x2000, 14, 32));
+        assert_eq!(0xFFFF_FFFF, scale_up(0x3FFF, 14, 32));
+    }
+
+    #[test]
+    fn scale_roundtrip() {
+        for v in 0..=0x7F {
+            assert_eq!(v, scale_down(scale_up(v, 7, 16), 16, 7));
+            assert_eq!(v, scale_down(scale_up(v, 7, 32), 32, 7));
+        }
+        for v in 0..=0x3FFF {
+            assert_eq!(v, scale_down(scale_up(v, 14, 32), 32, 14));
+        }
+    }
+
+    #[test]
+    fn note_on_zero_velocity() {
+        let m2 = Midi2Message::try_from(MidiMessage::NoteOn(channel(1), Note::C4, U7(0))).unwrap();
+        assert_eq!(Midi2Message::NoteOff(channel(1), Note::C4, 0x8000, 0, 0), m2);
+    }
+
+    #[test]
+    fn midi2_roundtrip() {
+        let m1 = MidiMessage::ControlChange(channel(3), U7(74), U7(99));
+        let ump = Ump::from(Midi2Message::try_from(m1).unwrap());
+        assert_eq!(MessageType::Midi2ChannelVoice, ump.message_type());
+        assert_eq!(2, ump.words().len());
+        assert_eq!(m1, MidiMessage::try_from(ump).unwrap());
+    }
+
+    #[test]
+    fn program_change_with_bank() {
+        // bank MSB 2, LSB 9
+        let pc = Midi2Message::ProgramChange(channel(1), U7(5), Some(U14(0x109)));
+        let ump = Ump::from(pc);
+        assert_eq!(pc, Midi2Message::try_from(ump).unwrap());
+        let packets = pc.to_midi1().unwrap();
+        assert_eq!(3, packets.len());
+        assert_eq!(MidiMessage::ControlChange(channel(1), U7(0), U7(2)), MidiMessage::try_from(packets[0]).unwrap());
+        assert_eq!(MidiMessage::ControlChange(channel(1), U7(32), U7(9)), MidiMessage::try_from(packets[1]).unwrap());
+        assert_eq!(MidiMessage::ProgramChange(channel(1), U7(5)), MidiMessage::try_from(packets[2]).unwrap());
+    }
+
+    #[test]
+    fn packet_roundtrip() {
+        let packets = [
+            Packet::from(MidiMessage::NoteOn(channel(2), Note::A4, U7(100))).with_cable_num(3),
+            Packet::from(MidiMessage::TimingClock),
+            Packet::from(MidiMessage::SongPositionPointer(U7(1), U7(2))),
+            Packet::from(MidiMessage::SysexBegin(0x42, 0x30)),
+            Packet::from(MidiMessage::SysexCont(0x04, 0x10, 0x11)),
+            Packet::from(MidiMessage::SysexEnd),
+            Packet::from(MidiMessage::SysexEnd1(0x01)),
+            Packet::from(MidiMessage::SysexEnd2(0x01, 0x02)),
+            Packet::from(MidiMessage::SysexEmpty),
+            Packet::from(MidiMessage::SysexSingleByte(0x7E)),
+        ];
+        for packet in packets {
+            let ump = Ump::try_from(packet).unwrap();
+            assert_eq!(packet, Packet::try_from(ump).unwrap());
+        }
+    }
+
+    #[test]
+    fn sysex7_body() {
+        let ump = Ump::sysex7(SysexStatus::Start, &[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(Some(SysexStatus::Start), ump.sysex7_status());
+        assert_eq!(&[1, 2, 3, 4, 5], ump.sysex7_body().as_slice());
+        assert!(Ump::sysex7(SysexStatus::Start, &[0; 7]).is_err());
+    }
+
+    #[test]
+    fn sysex_split_and_pack() {
+        let data: [u8; 14] = core::array::from_fn(|i| i as u8);
+        let umps = [
+            Ump::sysex7(SysexStatus::Start, &data[..6]).unwrap(),
+            Ump::sysex7(SysexStatus::Continue, &data[6..12]).unwrap(),
+            Ump::sysex7(SysexStatus::End, &data[12..]).unwrap(),
+        ];
+        let mut splitter = UmpSplitter::default();
+        let mut packets = PacketList::default();
+        for (ump, len) in umps.iter().zip([2, 2, 2]) {
+            let split = splitter.split(*ump).unwrap();
+            assert_eq!(len, split.len());
+            packets.extend(split.iter().copied());
+        }
+
+        let mut bytes: Vec<u8, 32> = Vec::new();
+        for packet in packets.iter() {
+            bytes.extend_from_slice(packet.payload()).unwrap();
+        }
+        assert_eq!(SYSEX_START, bytes[0]);
+        assert_eq!(&data, &bytes[1..15]);
+        assert_eq!(SYSEX_END, bytes[15]);
+        assert_eq!(CodeIndexNumber::SystemCommonLen1, packets[5].code_index_number());
+
+        let mut packer = UmpPacker::default();
+        let mut packed: Vec<Ump, 4> = Vec::new();
+        for packet in packets.iter() {
+            packed.extend(packer.pack(*packet).unwrap());
+        }
+        assert_eq!(&umps, packed.as_slice());
+    }
+
+    #[test]
+    fn sysex_split_complete() {
+        let mut splitter = UmpSplitter::default();
+        let split = splitter.split(Ump::sysex7(SysexStatus::Complete, &[1, 2, 3, 4, 5, 6]).unwrap()).unwrap();
+        assert_eq!(3, split.len());
+        assert_eq!(CodeIndexNumber::SysexEndsNext2, split[2].code_index_number());
+        assert_eq!(Some(MidiError::InvalidSysex), splitter.split(Ump::sysex7(SysexStatus::End, &[]).unwrap()).err());
+
+        let mut packer = UmpPacker::default();
+        let note = Packet::from(MidiMessage::NoteOn(channel(1), Note::C4, U7(100)));
+        assert_eq!(&[Ump::try_from(note).unwrap()], packer.pack(note).unwrap().as_slice());
+        assert_eq!(Err(MidiError::InvalidSysex), packer.pack(Packet::from(MidiMessage::SysexEnd1(0x01))));
+    }
+
+    #[test]
+    fn bad_ump_header() {
+        let ump = Ump::from(Midi2Message::try_from(MidiMessage::NoteOn(channel(1), Note::C4, U7(100))).unwrap());
+        assert_eq!(Err(MidiError::BadUmp(ump.words()[0])), Packet::try_from(ump));
+    }
+}
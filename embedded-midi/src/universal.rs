@@ -0,0 +1,221 @@
+//! Universal System Exclusive messages, not tied to a manufacturer
+//! Device identification (Identity Request / Reply) and General MIDI mode.
+//! Sysex messages are complete byte sequences, including SYSEX_START and SYSEX_END markers.
+
+use crate::status::{SYSEX_END, SYSEX_START};
+use crate::MidiError;
+
+pub const UNIVERSAL_NON_REALTIME: u8 = 0x7E;
+pub const UNIVERSAL_REALTIME: u8 = 0x7F;
+
+/// Device ID addressing every device
+pub const ALL_CALL: u8 = 0x7F;
+
+const GENERAL_INFORMATION: u8 = 0x06;
+const IDENTITY_REQUEST: u8 = 0x01;
+const IDENTITY_REPLY: u8 = 0x02;
+
+const GENERAL_MIDI: u8 = 0x09;
+const GENERAL_MIDI_ON: u8 = 0x01;
+const GENERAL_MIDI_OFF: u8 = 0x02;
+
+/// Prefix of three byte manufacturer IDs
+const EXTENDED_MANUFACTURER: u8 = 0x00;
+
+/// Identity Reply data following the manufacturer ID: family, model (LSB first) and version
+const IDENTITY_DATA_LEN: usize = 8;
+
+/// Length of an Identity Reply with an extended manufacturer ID
+pub const IDENTITY_REPLY_MAX_LEN: usize = 5 + 3 + IDENTITY_DATA_LEN + 1;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ManufacturerId {
+    Short(u8),
+    /// Two bytes following the 0x00 prefix
+    Extended(u8, u8),
+}
+
+impl ManufacturerId {
+    fn len(&self) -> usize {
+        match self {
+            ManufacturerId::Short(_) => 1,
+            ManufacturerId::Extended(..) => 3,
+        }
+    }
+}
+
+fn checked_header(sysex: &[u8], sub_id1: u8, sub_id2: u8, min_len: usize) -> Result<(), MidiError> {
+    if sysex.len() < min_len {
+        return Err(MidiError::TruncatedData);
+    }
+    match sysex {
+        [SYSEX_START, UNIVERSAL_NON_REALTIME, _, id1, id2, ..] if *id1 == sub_id1 && *id2 == sub_id2 => {}
+        _ => return Err(MidiError::InvalidSysex),
+    }
+    if sysex[sysex.len() - 1] != SYSEX_END {
+        return Err(MidiError::InvalidSysex);
+    }
+    Ok(())
+}
+
+/// Ask devices to identify themselves, use ALL_CALL to reach any device
+pub fn identity_request(device_id: u8) -> [u8; 6] {
+    [SYSEX_START, UNIVERSAL_NON_REALTIME, device_id & 0x7F, GENERAL_INFORMATION, IDENTITY_REQUEST, SYSEX_END]
+}
+
+/// Parse Identity Request, returns the device ID it is addressed to
+pub fn parse_identity_request(sysex: &[u8]) -> Result<u8, MidiError> {
+    checked_header(sysex, GENERAL_INFORMATION, IDENTITY_REQUEST, 6)?;
+    if sysex.len() != 6 {
+        return Err(MidiError::InvalidSysex);
+    }
+    Ok(sysex[2])
+}
+
+/// Turn General MIDI mode of device on or off
+pub fn general_midi(device_id: u8, on: bool) -> [u8; 6] {
+    let mode = if on { GENERAL_MIDI_ON } else { GENERAL_MIDI_OFF };
+    [SYSEX_START, UNIVERSAL_NON_REALTIME, device_id & 0x7F, GENERAL_MIDI, mode, SYSEX_END]
+}
+
+/// Parse General MIDI On / Off message, returns true for On
+pub fn parse_general_midi(sysex: &[u8]) -> Result<bool, MidiError> {
+    let on = sysex.get(4) == Some(&GENERAL_MIDI_ON);
+    checked_header(sysex, GENERAL_MIDI, if on { GENERAL_MIDI_ON } else { GENERAL_MIDI_OFF }, 6)?;
+    if sysex.len() != 6 {
+        return Err(MidiError::InvalidSysex);
+    }
+    Ok(on)
+}
+
+/// Identity of a device, as sent in reply to an Identity Request
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IdentityReply {
+    pub device_id: u8,
+    pub manufacturer: ManufacturerId,
+    pub family: u16,
+    pub model: u16,
+    pub version: [u8; 4],
+}
+
+impl IdentityReply {
+    /// Write Identity Reply message to buffer, returns its length
+    pub fn write(&self, buffer: &mut [u8]) -> Result<usize, MidiError> {
+        let len = 5 + self.manufacturer.len() + IDENTITY_DATA_LEN + 1;
+        if buffer.len() < len {
+            return Err(MidiError::BufferFull);
+        }
+        buffer[..5].copy_from_slice(&[SYSEX_START, UNIVERSAL_NON_REALTIME, self.device_id & 0x7F, GENERAL_INFORMATION, IDENTITY_REPLY]);
+        let data = match self.manufacturer {
+            ManufacturerId::Short(id) => {
+                buffer[5] = id & 0x7F;
+                6
+            }
+            ManufacturerId::Extended(id0, id1) => {
+                buffer[5..8].copy_from_slice(&[EXTENDED_MANUFACTURER, id0 & 0x7F, id1 & 0x7F]);
+                8
+            }
+        };
+        buffer[data..data + 4].copy_from_slice(&[
+            self.family as u8 & 0x7F, (self.family >> 7) as u8 & 0x7F,
+            self.model as u8 & 0x7F, (self.model >> 7) as u8 & 0x7F,
+        ]);
+        for (dst, src) in buffer[data + 4..data + IDENTITY_DATA_LEN].iter_mut().zip(self.version.iter()) {
+            *dst = src & 0x7F;
+        }
+        buffer[len - 1] = SYSEX_END;
+        Ok(len)
+    }
+
+    /// Parse Identity Reply message, with short or extended manufacturer ID
+    pub fn parse(sysex: &[u8]) -> Result<Self, MidiError> {
+        checked_header(sysex, GENERAL_INFORMATION, IDENTITY_REPLY, 6)?;
+        let (manufacturer, data) = if sysex[5] == EXTENDED_MANUFACTURER {
+            if sysex.len() < 8 {
+                return Err(MidiError::TruncatedData);
+            }
+            (ManufacturerId::Extended(sysex[6], sysex[7]), 8)
+        } else {
+            (ManufacturerId::Short(sysex[5]), 6)
+        };
+        if sysex.len() != data + IDENTITY_DATA_LEN + 1 {
+            return Err(MidiError::InvalidSysex);
+        }
+        let data = &sysex[data..data + IDENTITY_DATA_LEN];
+        Ok(IdentityReply {
+            device_id: sysex[2],
+            manufacturer,
+            family: data[0] as u16 | (data[1] as u16) << 7,
+            model: data[2] as u16 | (data[3] as u16) << 7,
+            version: [data[4], data[5], data[6], data[7]],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_request_reply() {
+        assert_eq!([0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7], identity_request(ALL_CALL));
+        assert_eq!(Ok(0x10), parse_identity_request(&identity_request(0x10)));
+        assert!(parse_identity_request(&[0xF0, 0x7E, 0x10, 0x06, 0x02, 0xF7]).is_err());
+    }
+
+    #[test]
+    fn short_manufacturer() {
+        let reply = IdentityReply {
+            device_id: 0x10,
+            manufacturer: ManufacturerId::Short(0x42),
+            family: 0x0102,
+            model: 0x0203,
+            version: [1, 2, 3, 4],
+        };
+        let mut buffer = [0; IDENTITY_REPLY_MAX_LEN];
+        let len = reply.write(&mut buffer).unwrap();
+        assert_eq!(15, len);
+        assert_eq!(&[0xF0, 0x7E, 0x10, 0x06, 0x02, 0x42, 0x02, 0x02, 0x03, 0x04, 1, 2, 3, 4, 0xF7], &buffer[..len]);
+        assert_eq!(Ok(reply), IdentityReply::parse(&buffer[..len]));
+    }
+
+    #[test]
+    fn extended_manufacturer() {
+        let reply = IdentityReply {
+            device_id: 0x7F,
+            manufacturer: ManufacturerId::Extended(0x20, 0x6B),
+            family: 2,
+            model: 0x3FFF,
+            version: [0, 1, 0, 0],
+        };
+        let mut buffer = [0; IDENTITY_REPLY_MAX_LEN];
+        let len = reply.write(&mut buffer).unwrap();
+        assert_eq!(IDENTITY_REPLY_MAX_LEN, len);
+        assert_eq!(&[0xF0, 0x7E, 0x7F, 0x06, 0x02, 0x00, 0x20, 0x6B, 2, 0, 0x7F, 0x7F], &buffer[..12]);
+        assert_eq!(Ok(reply), IdentityReply::parse(&buffer[..len]));
+        assert_eq!(Err(MidiError::BufferFull), reply.write(&mut buffer[..len - 1]));
+    }
+
+    #[test]
+    fn reject_wrong_data_length() {
+        // short manufacturer, one data byte missing
+        let short = [0xF0, 0x7E, 0x10, 0x06, 0x02, 0x42, 0, 0, 0, 0, 1, 2, 3, 0xF7];
+        assert_eq!(Err(MidiError::InvalidSysex), IdentityReply::parse(&short));
+        // extended manufacturer, one data byte too many
+        let extended = [0xF0, 0x7E, 0x10, 0x06, 0x02, 0x00, 0x20, 0x6B, 0, 0, 0, 0, 1, 2, 3, 4, 5, 0xF7];
+        assert_eq!(Err(MidiError::InvalidSysex), IdentityReply::parse(&extended));
+        // extended prefix without the ID bytes
+        assert_eq!(Err(MidiError::TruncatedData), IdentityReply::parse(&[0xF0, 0x7E, 0x10, 0x06, 0x02, 0x00, 0xF7]));
+    }
+
+    #[test]
+    fn general_midi_mode() {
+        assert_eq!([0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7], general_midi(ALL_CALL, true));
+        assert_eq!(Ok(true), parse_general_midi(&general_midi(0x10, true)));
+        assert_eq!(Ok(false), parse_general_midi(&general_midi(0x10, false)));
+        assert!(parse_general_midi(&[0xF0, 0x7E, 0x10, 0x09, 0x03, 0xF7]).is_err());
+        assert!(parse_general_midi(&[0xF0, 0x7E, 0x10, 0x09, 0x01, 0x00, 0xF7]).is_err());
+    }
+}
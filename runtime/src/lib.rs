@@ -17,7 +17,7 @@ mod mutex;
 mod waker_set;
 
 use cortex_m::peripheral::SYST;
-pub use time::{now, now_millis, delay_until, delay, delay_cycles, run_scheduled, SysInstant};
+pub use time::{now, now_millis, now_micros, delay_until, delay, delay_cycles, run_scheduled, SysInstant};
 pub use exec::{spawn, /*repeat,*/ process_queue};
 pub use spin::{Mutex as SpinMutex, MutexGuard as SpinMutexGuard};
 
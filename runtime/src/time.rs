@@ -58,6 +58,10 @@ pub fn now_millis() -> u64 {
     (now() - SysClock::zero()).to_millis()
 }
 
+pub fn now_micros() -> u64 {
+    (now() - SysClock::zero()).to_micros()
+}
+
 const MAX_RVR: u32 = 0x00FF_FFFF;
 
 impl SysClock {
@@ -1,5 +1,6 @@
-use midi::{Note,  note_off, note_on, Velocity, PacketList, MidiInterface, MidiChannel};
-use crate::{devices, midi_send};
+use midi::{Note,  note_off, note_on, Velocity, PacketList, MidiChannel};
+use crate::devices;
+use crate::port::routing::{midi_send, PORT_BEATSTEP};
 use alloc::vec::Vec;
 
 use devices::arturia::beatstep;
@@ -21,9 +22,6 @@ impl InnerState {}
 
 static BLINKY_BEAT: Local<InnerState> = Local::uninit("BLINKY_BEAT");
 
-/// MIDI Interface to BeatStep through MIDI USB Coprocessor
-const IF_BEATSTEP: MidiInterface = MidiInterface::Serial(1);
-
 pub fn start_app(channel: MidiChannel, notes: &[Note]) {
     BLINKY_BEAT.init_static(InnerState {
         channel,
@@ -33,13 +31,13 @@ pub fn start_app(channel: MidiChannel, notes: &[Note]) {
         loop {
             let z = unsafe { BLINKY_BEAT.raw_mut() };
             for sysex in devices::arturia::beatstep::beatstep_set(PadNote(Pad(0), z.channel, Note::C1m, SwitchMode::Gate)) {
-                midi_send(IF_BEATSTEP, sysex.collect());
+                midi_send(PORT_BEATSTEP, sysex.collect());
             }
             for (note, ref mut on) in &mut z.notes {
                 if *on {
-                    midi_send(IF_BEATSTEP, PacketList::single(note_on(MidiChannel(0), *note, Velocity::MAX).unwrap().into()));
+                    midi_send(PORT_BEATSTEP, PacketList::single(note_on(MidiChannel(0), *note, Velocity::MAX).unwrap().into()));
                 } else {
-                    midi_send(IF_BEATSTEP, PacketList::single(note_off(MidiChannel(0), *note, Velocity::MIN).unwrap().into()));
+                    midi_send(PORT_BEATSTEP, PacketList::single(note_off(MidiChannel(0), *note, Velocity::MIN).unwrap().into()));
                 }
                 *on = !*on
             }
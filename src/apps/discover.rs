@@ -0,0 +1,67 @@
+//! Identifies standard MIDI devices connected to any port, using Universal Identity Request
+//! Also answers Identity Requests with our own identity, and logs General MIDI mode changes.
+//!
+use alloc::vec::Vec;
+
+use midi::{PacketList, PortId};
+use midi::universal::{identity_request, parse_general_midi, parse_identity_request, IdentityReply, ManufacturerId,
+                      ALL_CALL, IDENTITY_REPLY_MAX_LEN};
+
+use runtime::{Local, spawn};
+
+use crate::port::routing::{midi_send, PORT_BEATSTEP, PORT_DW6000, PORT_USB};
+use crate::sysex::{collect_sysex, sysex_packets};
+
+const INTERFACES: [PortId; 3] = [PORT_USB, PORT_BEATSTEP, PORT_DW6000];
+
+/// Long enough for any Universal message handled here, longer sysex are ignored
+const SYSEX_BUFFER_LEN: usize = 32;
+
+/// Sent in reply to Identity Requests, manufacturer ID 0x7D is reserved for non-commercial use
+const IDENTITY: IdentityReply = IdentityReply {
+    device_id: 0x10,
+    manufacturer: ManufacturerId::Short(0x7D),
+    family: 0,
+    model: 666,
+    version: [0, 1, 0, 0],
+};
+
+/// Sysex messages are collected per interface
+static BUFFERS: Local<[Vec<u8>; 3]> = Local::uninit("DISCOVER_BUFFERS");
+
+pub fn start_app() {
+    BUFFERS.init_static([(); 3].map(|_| Vec::with_capacity(SYSEX_BUFFER_LEN)));
+
+    for interface in INTERFACES {
+        midi_send(interface, sysex_packets(&identity_request(ALL_CALL)));
+    }
+
+    info!("Device Discovery Active");
+}
+
+/// Logs identity of devices replying on interface, replies to Identity Requests
+/// Called from interrupt handlers, replies are sent from a task
+pub fn identify(interface: PortId, packets: &PacketList) {
+    if let Some(idx) = INTERFACES.iter().position(|i| *i == interface) {
+        let buffer = &mut unsafe { BUFFERS.raw_mut() }[idx];
+        for packet in packets.iter() {
+            if !collect_sysex(buffer, *packet) {
+                continue;
+            }
+            if let Ok(reply) = IdentityReply::parse(buffer) {
+                info!("Found device on {:?}: {:?}", interface, reply);
+            } else if let Ok(device_id) = parse_identity_request(buffer) {
+                if device_id == ALL_CALL || device_id == IDENTITY.device_id {
+                    spawn(async move {
+                        let mut reply = [0; IDENTITY_REPLY_MAX_LEN];
+                        if let Ok(len) = IDENTITY.write(&mut reply) {
+                            midi_send(interface, sysex_packets(&reply[..len]));
+                        }
+                    });
+                }
+            } else if let Ok(on) = parse_general_midi(buffer) {
+                info!("General MIDI {} on {:?}", if on { "on" } else { "off" }, interface);
+            }
+        }
+    }
+}
@@ -1,8 +1,9 @@
 //! Sends MIDI to Korg DW-6000 acccording to messages
 //!
-use midi::{MidiMessage, Note, program_change, MidiError, U7, MidiInterface, PacketList, channel};
+use midi::{MidiMessage, Note, program_change, MidiError, U7, PacketList, channel, AsyncReceive};
 
-use crate::{devices, midi, MIDI_DIN_1_RX, MIDI_DIN_2_RX, midi_send, sysex};
+use crate::{devices, midi, MIDI_DIN_1_RX, MIDI_DIN_2, sysex};
+use crate::port::routing::{midi_send, PORT_DW6000};
 use alloc::vec::Vec;
 use core::convert::TryFrom;
 use core::slice;
@@ -11,6 +12,7 @@ use devices::korg::dw6000::*;
 use num_enum::TryFromPrimitive;
 use num::{Integer};
 use crate::apps::lfo::{Lfo, Waveform};
+use midi::clock::ClockTracker;
 
 use crate::devices::korg::dw6000;
 
@@ -21,11 +23,8 @@ use crate::sysex::{capture_sysex, SysexCapture};
 
 const SHORT_PRESS_MS: u64 = 250;
 
-/// MIDI Interface to DW6000
-const IF_DW6000: MidiInterface = MidiInterface::Serial(0);
-
-/// MIDI Interface to BeatStep through MIDI USB Coprocessor
-const IF_BEATSTEP: MidiInterface = MidiInterface::Serial(1);
+/// LFO2 cycle length in quarter notes, while BeatStep clock is playing
+const LFO2_SYNC_BEATS: f32 = 1.0;
 
 static DW6_CTRL: Shared<Dw6ControlInner> = Shared::uninit("DW6_CTRL");
 
@@ -40,17 +39,30 @@ pub fn start_app() {
         bank: None,
         lfo2: Lfo::default(),
         lfo2_param: None,
+        clock: ClockTracker::default(),
     });
 
     DW6_DUMP.init_static(Vec::with_capacity(32));
 
     MIDI_DIN_1_RX.init_static(packets_from_beatstep);
-    MIDI_DIN_2_RX.init_static(packets_from_dw_6000);
+
+    // DW-6000 dumps
+    spawn(async move {
+        let mut dw6000 = &MIDI_DIN_2;
+        loop {
+            match dw6000.recv().await {
+                Ok(packet) => packet_from_dw_6000(packet).await,
+                Err(err) => error!("{}", err),
+            }
+        }
+    });
 
     spawn(async move {
         loop {
             // LFO2 modulation
             let mut state = DW6_CTRL.lock().await;
+            let Dw6ControlInner { lfo2, clock, .. } = &mut *state;
+            lfo2.sync_to_clock(clock, LFO2_SYNC_BEATS);
             if let Some(lfo2_param) = state.lfo2_param.map(Dw6Param::from) {
                 if let Some(root) = state.mod_dump.get(&lfo2_param).cloned() {
                     let max = lfo2_param.max_value();
@@ -63,7 +75,7 @@ pub fn start_app() {
                     if let Some(dump) = &state.current_dump {
                         set_param_value(lfo2_param, mod_value, dump.as_slice());
                         let sysex = param_set_sysex(lfo2_param, dump);
-                        midi_send(IF_DW6000, sysex.collect());
+                        midi_send(PORT_DW6000, sysex.collect());
                     }
                 }
             }
@@ -74,7 +86,7 @@ pub fn start_app() {
     // periodic DW-6000 dump sync
     spawn(async move {
         loop {
-            midi_send(IF_DW6000, dump_request_sysex().collect());
+            midi_send(PORT_DW6000, dump_request_sysex().collect());
             if runtime::delay(250.millis()).await.is_err() { panic!("Sysex dump request loop interrupted"); }
         }
     });
@@ -180,6 +192,8 @@ struct Dw6ControlInner {
     bank: Option<u8>,
     lfo2: Lfo,
     lfo2_param: Option<Lfo2Dest>,
+    // BeatStep transport & tempo
+    clock: ClockTracker,
     // arp_enabled: bool,
     // arp_mode: ArpMode,
     // arp_oct: u8, // 1..4
@@ -192,24 +206,22 @@ impl Dw6ControlInner {
 }
 
 fn note_page(note: Note) -> Option<KnobPage> {
-    KnobPage::try_from(note as u8).ok()
+    KnobPage::try_from(u8::from(note)).ok()
 }
 
 fn toggle_page(note: Note) -> Option<TogglePage> {
-    TogglePage::try_from(note as u8).ok()
+    TogglePage::try_from(u8::from(note)).ok()
 }
 
 fn note_bank(note: Note) -> Option<u8> {
-    let note_u8 = note as u8;
-    match note_u8.div_rem(&8) {
+    match u8::from(note).div_rem(&8) {
         (1, n) => Some(n),
         _ => None,
     }
 }
 
 fn note_prog(note: Note) -> Option<u8> {
-    let note_u8 = note as u8;
-    match note_u8.div_rem(&8) {
+    match u8::from(note).div_rem(&8) {
         (0, n) => Some(n),
         _ => None,
     }
@@ -234,7 +246,7 @@ impl Dw6ControlInner {
 
     fn send_param_value(&mut self, param: Dw6Param) -> Result<(), MidiError> {
         if let Some(dump) = &self.current_dump {
-            midi_send(IF_DW6000, param_set_sysex(param, dump).into());
+            midi_send(PORT_DW6000, param_set_sysex(param, dump).into());
         }
         Ok(())
     }
@@ -245,16 +257,18 @@ fn toggle_param(param: Dw6Param, dump: &mut Vec<u8>) -> Result<(), MidiError> {
     let mut value = get_param_value(param, dump.as_slice());
     value ^= 1;
     set_param_value(param, value, dump.as_mut_slice());
-    midi_send(IF_DW6000, param_set_sysex(param, dump.as_slice()).into());
+    midi_send(PORT_DW6000, param_set_sysex(param, dump.as_slice()).into());
     // context.strings.push(format!("{:?}\n{:.2}", param, value));
     Ok(())
 }
 
 fn packets_from_beatstep(packets: PacketList) {
+    // timestamp on reception, for clock messages
+    let now_micros = runtime::now_micros();
     spawn(async move {
         for packet in packets.0.into_iter() {
             if let Ok(msg) = MidiMessage::try_from(packet) {
-                if let Err(err) = msg_from_beatstep(msg).await {
+                if let Err(err) = msg_from_beatstep(msg, now_micros).await {
                     error!("{}", err);
                 }
             }
@@ -262,7 +276,7 @@ fn packets_from_beatstep(packets: PacketList) {
     });
 }
 
-async fn msg_from_beatstep(msg: MidiMessage) -> Result<bool, MidiError> {
+async fn msg_from_beatstep(msg: MidiMessage, now_micros: u64) -> Result<bool, MidiError> {
     let mut state = DW6_CTRL.lock().await;
     match msg {
         MidiMessage::NoteOn(_, note, _) => {
@@ -270,8 +284,9 @@ async fn msg_from_beatstep(msg: MidiMessage) -> Result<bool, MidiError> {
                 state.bank = Some(bank)
             } else if let Some(prog) = note_prog(note) {
                 if let Some(bank) = state.bank {
+                    // DW-6000 has 64 programs, no Bank Select needed
                     let pc = program_change(channel(1), (bank * 8) + prog)?;
-                    midi_send(IF_DW6000, PacketList::single(pc.into()));
+                    midi_send(PORT_DW6000, PacketList::single(pc.into()));
                 }
             }
             if let Some(page) = note_page(note) {
@@ -310,7 +325,7 @@ async fn msg_from_beatstep(msg: MidiMessage) -> Result<bool, MidiError> {
                     *root = value.0
                 } else if let Some(dump) = &mut state.current_dump {
                     set_param_value(param, value.into(), dump.as_mut_slice());
-                    midi_send(IF_DW6000, param_set_sysex(param, dump).into());
+                    midi_send(PORT_DW6000, param_set_sysex(param, dump).into());
                     // context.packets.clear();
                     // context.packets.extend(param_to_sysex(param, dump));
                     // context.strings.push(format!("{:?}\n{:?}", param, get_param_value(param, dump)));
@@ -348,6 +363,10 @@ async fn msg_from_beatstep(msg: MidiMessage) -> Result<bool, MidiError> {
                     }
                 }
             }
+        MidiMessage::TimingClock | MidiMessage::Start | MidiMessage::Continue | MidiMessage::Stop
+        | MidiMessage::SongPositionPointer(..) => {
+            state.clock.advance(&msg, now_micros);
+        }
         _ => {}
     }
     Ok(true)
@@ -377,23 +396,19 @@ fn cc_to_ctl_param(cc: midi::Control, page: KnobPage) -> Option<CtlParam> {
     }
 }
 
-pub fn packets_from_dw_6000(packets: PacketList) {
-    spawn(async {
-        for packet in packets.0.into_iter() {
-            // lock sysex buffer
-            let buffer = unsafe { DW6_DUMP.raw_mut() };
-            if let Ok(msg) = MidiMessage::try_from(packet) {
-                match capture_sysex(buffer, msg) {
-                    Ok(SysexCapture::Captured) =>
-                    if let Err(err) = from_dw6000_dump(buffer).await {
-                        error!("{}", err);
-                    }
-                    Ok(SysexCapture::Pending) => {}#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-                    Err(err) => warn!("sysex capture error")
-                }
+async fn packet_from_dw_6000(packet: midi::Packet) {
+    // only used by DW-6000 receive task
+    let buffer = unsafe { DW6_DUMP.raw_mut() };
+    if let Ok(msg) = MidiMessage::try_from(packet) {
+        match capture_sysex(buffer, msg) {
+            Ok(SysexCapture::Captured) =>
+            if let Err(err) = from_dw6000_dump(buffer).await {
+                error!("{}", err);
             }
+            Ok(SysexCapture::Pending) => {}
+            Err(_err) => warn!("sysex capture error")
         }
-    });
+    }
 }
 
 async fn from_dw6000_dump(dump: &[u8]) -> Result<bool, MidiError> {
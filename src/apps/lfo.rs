@@ -1,5 +1,6 @@
 // use nanorand::{WyRand, RNG};
 use crate::{CPU_FREQ};
+use midi::clock::{ClockTracker, CLOCKS_PER_BEAT};
 
 use num_enum::{FromPrimitive};
 use core::f32;
@@ -38,6 +39,8 @@ pub struct Lfo {
     // between 0 and 1
     amount: f32,
     wave: Waveform,
+    // position in cycle from 0 to 1 given by MIDI clock, None when free running
+    clock_phase: Option<f32>,
 }
 
 impl Default for Lfo {
@@ -47,6 +50,7 @@ impl Default for Lfo {
             period: 0.0,
             amount: 0.0,
             wave: Default::default(),
+            clock_phase: None,
         }
     }
 }
@@ -56,39 +60,41 @@ const F_CPU_FREQ: f32 = CPU_FREQ as f32;
 // Yes, these computations are HORRIBLY INEFFICIENT and naive. IJDGAF.
 impl Lfo {
     pub fn mod_value(&mut self, froot: f32/*, chaos: &mut WyRand*/) -> f32 {
-        let now = runtime::now_millis();
-        let time = now - self.offset_ms;
+        let phase = self.clock_phase.unwrap_or_else(|| self.free_phase());
         (froot + match self.wave {
             Waveform::Triangle => {
-                let timex = time as f32 % self.period;
-                let half = self.period / 2.0;
-                let mut modulation = timex / half;
-                if timex > half {
+                let mut modulation = phase * 2.0;
+                if phase > 0.5 {
                     modulation = 1.0 - modulation;
                 }
                 (modulation - 0.5) * 2.0 * self.amount
             }
             Waveform::Sine => {
-                let timex = time as f32 / self.period;
-                timex.sin() * self.amount
+                (phase * 2.0 * f32::consts::PI).sin() * self.amount
             }
             Waveform::Square => {
-                let timex = time as f32 % self.period;
-                let half = self.period / 2.0;
-                (if timex > half { 1.0 } else { -1.0 }) * self.amount
+                (if phase > 0.5 { 1.0 } else { -1.0 }) * self.amount
             }
             Waveform::Saw => {
-                let timex = time as f32 / self.period;
-                ((1.0 - timex.fract()) - 0.5) * 2.0 * self.amount
+                ((1.0 - phase) - 0.5) * 2.0 * self.amount
             }
             Waveform::RevSaw => {
-                let timex = time as f32 / self.period;
-                (timex.fract() - 0.5) * 2.0 * self.amount
+                (phase - 0.5) * 2.0 * self.amount
             }
             // Waveform::Random => ((chaos.generate_range::<u32>(0, u32::MAX) as f32 / u32::MAX as f32) - 0.5) * 2.0 * self.amount
         }).max(0.0).min(1.0)
     }
 
+    /// Position in cycle from uptime, sine cycles are computed in radians
+    fn free_phase(&self) -> f32 {
+        let time = (runtime::now_millis() - self.offset_ms) as f32;
+        let cycle = match self.wave {
+            Waveform::Sine => self.period * 2.0 * f32::consts::PI,
+            _ => self.period,
+        };
+        (time / cycle).fract()
+    }
+
     pub fn get_amount(&self) -> f32 {
         self.amount
     }
@@ -106,6 +112,18 @@ impl Lfo {
         self.period = F_CPU_FREQ / rate;
     }
 
+    /// Follow incoming MIDI clock, one cycle every `beats` quarter notes
+    /// Position in cycle is taken from the clock song position, so tempo jitter does not make the output jump
+    /// LFO runs free again at its own rate when clock is not playing
+    pub fn sync_to_clock(&mut self, clock: &ClockTracker, beats: f32) {
+        self.clock_phase = if clock.is_playing() {
+            let beat = (clock.position_clocks() / CLOCKS_PER_BEAT) as f32 + clock.beat_phase(runtime::now_micros());
+            Some((beat / beats).fract())
+        } else {
+            None
+        };
+    }
+
     pub fn get_waveform(&self) -> Waveform {
         self.wave
     }
@@ -2,4 +2,5 @@ pub mod dw6_control;
 pub mod blinky_beat;
 pub mod lfo;
 pub mod bounce;
+pub mod discover;
 
@@ -5,6 +5,7 @@
 #![allow(unused)]
 #![allow(clippy::upper_case_acronyms)]
 use midi::{U7, U4, Note, Program, Control, MidiChannel, MidiError};
+use midi::theory::ScaleKind;
 use alloc::vec::Vec;
 
 use crate::sysex::Token::{Seq, Cap, Val};
@@ -86,7 +87,7 @@ pub fn beatstep_set(param: Param) -> Vec<SysexSeq> {
             vec![
                 parameter_set(MODE, ccode, PadMode::Note as u8),
                 parameter_set(0x02, ccode, channel.0),
-                parameter_set(0x03, ccode, note as u8),
+                parameter_set(0x03, ccode, u8::from(note)),
                 parameter_set(0x06, ccode, switch as u8),
             ]
         }
@@ -139,7 +140,7 @@ pub fn beatstep_set(param: Param) -> Vec<SysexSeq> {
             vec![parameter_set(CURVE, 0x03, vel_curve as u8)],
 
         Param::StepNote(stepnum, note) =>
-            vec![parameter_set(STEP_NOTE, stepnum.0, note as u8)],
+            vec![parameter_set(STEP_NOTE, stepnum.0, u8::from(note))],
         Param::StepEnabled(stepnum, bool) =>
             vec![parameter_set(STEP_ENABLED, stepnum.0, if bool { 1 } else { 0 })],
         Param::SeqChannel(channel) =>
@@ -278,6 +279,22 @@ pub enum SeqScale {
     User,
 }
 
+impl SeqScale {
+    /// Scale played by the sequencer, `user_mask` being the user scale programmed on the device
+    pub fn scale_kind(&self, user_mask: u16) -> ScaleKind {
+        match self {
+            SeqScale::Chromatic => ScaleKind::Chromatic,
+            SeqScale::Major => ScaleKind::Major,
+            SeqScale::Minor => ScaleKind::Minor,
+            SeqScale::Dorian => ScaleKind::Dorian,
+            SeqScale::Mixolydian => ScaleKind::Mixolydian,
+            SeqScale::HarmonicMinor => ScaleKind::HarmonicMinor,
+            SeqScale::Blues => ScaleKind::Blues,
+            SeqScale::User => ScaleKind::User(user_mask),
+        }
+    }
+}
+
 #[derive(Debug)]
 #[repr(u8)]
 pub enum SeqMode {
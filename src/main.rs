@@ -60,13 +60,15 @@ pub type Handle = u16;
 pub static NEXT_HANDLE: AtomicU16 = AtomicU16::new(0);
 
 use crate::port::serial::{SerialMidi};
+use crate::port::AsyncPort;
+use crate::port::routing::{PORT_BEATSTEP, PORT_DW6000, PORT_USB};
 
 // use crate::display::gui::{self, Display};
 
-use midi::{MidiError, Receive, Transmit};
+use midi::{Receive, Route};
 use usb_device::bus;
 
-use midi::{MidiInterface, MidiBinding, channel, Note, PacketList};
+use midi::{MidiBinding, channel, Note, PacketList};
 
 // use ili9341::Ili9341;
 // use display_interface_spi::SPIInterface;
@@ -104,7 +106,7 @@ use hal::{interrupt};
 
 use runtime::allocator::CortexMSafeAlloc;
 use runtime::{Local, Shared, spawn};
-use crate::apps::{blinky_beat, bounce, dw6_control};
+use crate::apps::{blinky_beat, bounce, discover, dw6_control};
 
 use crate::filter::{print_message, print_packets};
 use crate::pac::{CorePeripherals, Peripherals};
@@ -122,12 +124,16 @@ static ONBOARD_LED: Shared<PC13<Output<PushPull>>> = Shared::uninit("LED");
 
 static MIDI_USB_1_RX: Local<fn(PacketList)> = Local::uninit("MIDI_USB_1_RX");
 static MIDI_DIN_1_RX: Local<fn(PacketList)> = Local::uninit("MIDI_DIN_1_RX");
-static MIDI_DIN_2_RX: Local<fn(PacketList)> = Local::uninit("MIDI_DIN_2_RX");
 
 static MIDI_USB_1_PORT: Local<port::usb::UsbMidi> = Local::uninit("MIDI_USB_1_PORT");
 static MIDI_DIN_1_PORT: Local<SerialMidi<pac::USART1>> = Local::uninit("MIDI_DIN_1_PORT");
 static MIDI_DIN_2_PORT: Local<SerialMidi<pac::USART2>> = Local::uninit("MIDI_DIN_2_PORT");
 
+/// Awaitable side of MIDI ports, for apps
+pub static MIDI_USB_1: AsyncPort = AsyncPort::new(pac::Interrupt::OTG_FS);
+pub static MIDI_DIN_1: AsyncPort = AsyncPort::new(pac::Interrupt::USART1);
+pub static MIDI_DIN_2: AsyncPort = AsyncPort::new(pac::Interrupt::USART2);
+
 // display: gui::Display<Ili9341<SPIInterface<Spi<hal::stm32::SPI1, (PA5<Alternate<hal::gpio::AF5>>, NoMiso, PA7<Alternate<hal::gpio::AF5>>)>, PB0<Output<PushPull>>, PA4<Output<PushPull>>>, PA6<Output<PushPull>>>, Rgb565>,
 
 #[entry]
@@ -203,7 +209,7 @@ fn main() -> ! {
         &clocks,
     ).unwrap();
     uart1.listen(serial::Event::Rxne);
-    MIDI_DIN_1_PORT.init_static(SerialMidi::new(uart1));
+    MIDI_DIN_1_PORT.init_static(SerialMidi::new(uart1).with_clock(runtime::now_millis));
     info!("OK: SerialMidi 1");
 
     let dw_tx = gpioa.pa2;
@@ -215,7 +221,7 @@ fn main() -> ! {
         &clocks,
     ).unwrap();
     uart2.listen(serial::Event::Rxne);
-    MIDI_DIN_2_PORT.init_static(SerialMidi::new(uart2));
+    MIDI_DIN_2_PORT.init_static(SerialMidi::new(uart2).with_clock(runtime::now_millis));
     info!("OK: SerialMidi 2");
 
     let usb = USB::new(
@@ -241,52 +247,14 @@ fn main() -> ! {
     let chaos = nanorand::WyRand::new_seed(0);
     info!("OK: Chaos");
 
-    /*    let mut midi_router = Router::default();
-
-        let _usb_echo = midi_router
-            .echo(MidiInterface::USB(0))
-            .filter(|cx| print_message(cx))
-            .add();
-
-        let _serial_print = midi_router
-            .from(IF_DW6000)
-            .filter(|cx| print_message(cx))
-            .add();
-
-        let _usb_print = midi_router
-            .to(MidiInterface::USB(0))
-            .filter(|cx| print_packets(cx))
-            .add();
-
-        let _usb_print_in = midi_router
-            .from(MidiInterface::USB(0))
-            .filter(|cx| print_packets(cx))
-            .add();*/
-
-    // let _evo_match = midi_router
-    //     .from(MidiInterface::Serial(0))
-    //     .filter(SysexCapture(dsi_evolver::program_parameter_matcher()))
-    //     .filter(PrintTags)
-    //     .add();
-    //
-    // let _evo_match = midi_router
-    //     .from(MidiInterface::Serial(0))
-    //     .filter(capture_sysex(dsi_evolver::program_parameter_matcher()))
-    //     .filter(print_tag())
-    //     .add();
-
-    // let _bstep_2_dw = midi_router
-    //     .link(MidiInterface::USB(0), MidiInterface::Serial(0))
-    //     .add();
-
-    // let mut init_ctx = InitContext {
-    //     din_1_dispatch: PacketDispatch::new(MidiInterface::Serial(0)),
-    //     din_2_dispatch: PacketDispatch::new(MidiInterface::Serial(1)),
-    //     usb_1_dispatch: PacketDispatch::new(MidiInterface::USB(0)),
-    // };
+    port::routing::start([
+        Route::from(PORT_USB).filter(|cx| print_packets(&cx.packets).unwrap_or(true)),
+        Route::link(PORT_USB, PORT_DW6000),
+    ]).unwrap();
 
     info!("Router OK");
 
+    discover::start_app();
     dw6_control::start_app();
     bounce::start_app();
     blinky_beat::start_app(channel(1), &[Note::C1m, Note::Cs1m, Note::B1m, Note::G0]);
@@ -347,9 +315,14 @@ unsafe fn OTG_FS() {
     let mut usb = unsafe { MIDI_USB_1_PORT.raw_mut() };
     if usb.poll() {
         while let Some(packet) = usb.receive().unwrap() {
+            discover::identify(PORT_USB, &PacketList::single(packet));
+            MIDI_USB_1.received(packet);
             // TODO passthru?
         }
     }
+    if let Err(err) = MIDI_USB_1.flush(usb) {
+        warn!("USB flush failed {:?}", err);
+    }
     pac::NVIC::unmask(pac::Interrupt::OTG_FS);
 }
 
@@ -364,16 +337,21 @@ unsafe fn USART1() {
         match bstep.receive() {
             Ok(Some(packet)) => {
                 debug!("MIDI from beatstep {:?}", packet);
+                discover::identify(PORT_BEATSTEP, &PacketList::single(packet));
                 (MIDI_DIN_1_RX)(PacketList::single(packet));
+                MIDI_DIN_1.received(packet);
                 continue;
             }
             Err(e) => {
-                warn!("Error serial read {:?}", e);
+                warn!("Error serial read {:?}, {:?}", e, bstep.stats());
                 break;
             }
             _ => { break; }
         }
     }
+    if let Err(err) = MIDI_DIN_1.flush(bstep) {
+        warn!("Serial flush failed {:?}", err);
+    }
     pac::NVIC::unmask(pac::Interrupt::USART1);
 }
 
@@ -381,33 +359,28 @@ unsafe fn USART1() {
 unsafe fn USART2() {
     pac::NVIC::mask(pac::Interrupt::USART2);
 
-    let dw6000 = unsafe { MIDI_DIN_1_PORT.raw_mut() };
+    let dw6000 = unsafe { MIDI_DIN_2_PORT.raw_mut() };
 
     if let Err(err) = dw6000.flush() {
         warn!("Serial flush failed {:?}", err);
     }
 
-    while let Ok(Some(packet)) = dw6000.receive() {
-        (MIDI_DIN_1_RX)(PacketList::single(packet));
+    loop {
+        let packet = match dw6000.receive() {
+            Ok(Some(packet)) => packet,
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Error serial read {:?}, {:?}", e, dw6000.stats());
+                break;
+            }
+        };
+        discover::identify(PORT_DW6000, &PacketList::single(packet));
+        MIDI_DIN_2.received(packet);
     }
-    pac::NVIC::unmask(pac::Interrupt::USART2);
-}
-
-// TODO split output queues (one task per interface)
-// #[task(shared = [usb_midi, dw6000, beatstep], capacity = 128, priority = 2)]
-fn midi_send(destination: MidiInterface, packets: PacketList) {
-    let z = match destination {
-        // includes BeatStep
-        MidiInterface::USB(_) => unsafe { MIDI_USB_1_PORT.raw_mut() }.transmit(packets),
-        MidiInterface::Serial(1) => unsafe { MIDI_DIN_1_PORT.raw_mut() }.transmit(packets),
-        MidiInterface::Serial(2) => unsafe { MIDI_DIN_2_PORT.raw_mut() }.transmit(packets),
-        dest => {
-            Err(MidiError::UnknownInterface(destination))
-        }
-    };
-    if let Err(err) = z {
-        info!("Failed to send MIDI: {:?}", destination)
+    if let Err(err) = MIDI_DIN_2.flush(dw6000) {
+        warn!("Serial flush failed {:?}", err);
     }
+    pac::NVIC::unmask(pac::Interrupt::USART2);
 }
 
 // Update the UI - using
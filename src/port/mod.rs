@@ -1,2 +1,106 @@
+pub mod routing;
 pub mod serial;
-pub mod usb;
\ No newline at end of file
+pub mod usb;
+
+use core::future::Future;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use hal::pac::{Interrupt, NVIC};
+use runtime::SpinMutex;
+
+use midi::{AsyncReceive, AsyncTransmit, MidiError, Packet, PacketList, PacketQueue, Transmit};
+
+/// Packets buffered in each direction, must be a power of two
+const ASYNC_QUEUE_LEN: usize = 32;
+
+/// Async side of a MIDI port
+/// The port itself stays owned by its interrupt handler, which calls `received()` and `flush()`
+pub struct AsyncPort {
+    rx: PacketQueue<ASYNC_QUEUE_LEN>,
+    tx: PacketQueue<ASYNC_QUEUE_LEN>,
+    // packet the port had no room for, only used from interrupt handler
+    pending: SpinMutex<Option<Packet>>,
+    // set once a task awaits received packets, until then they are not queued
+    listening: AtomicBool,
+    dropped: AtomicU32,
+    interrupt: Interrupt,
+}
+
+impl AsyncPort {
+    /// Interrupt is pended when packets are sent, to get them flushed to the port
+    pub const fn new(interrupt: Interrupt) -> Self {
+        AsyncPort {
+            rx: PacketQueue::new(),
+            tx: PacketQueue::new(),
+            pending: SpinMutex::new(None),
+            listening: AtomicBool::new(false),
+            dropped: AtomicU32::new(0),
+            interrupt,
+        }
+    }
+
+    /// Hand a received packet to the waiting task, from interrupt handler
+    /// Packets are ignored until a task listens, and counted as dropped if the task does not keep up
+    pub fn received(&self, packet: Packet) {
+        if self.listening.load(Ordering::Relaxed) && self.rx.push(packet).is_err() {
+            // no logging, this runs in interrupt context
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Queue a packet without waiting, from thread mode
+    /// Packet is dropped and Err(BufferFull) is returned if the TX queue is full
+    pub fn try_send(&self, packet: Packet) -> Result<(), MidiError> {
+        let result = self.tx.push(packet);
+        // queue is drained by interrupt handler
+        NVIC::pend(self.interrupt);
+        result
+    }
+
+    /// Received packets dropped because the RX queue was full
+    pub fn dropped(&self) -> u32 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Transmit packets sent by tasks, from interrupt handler
+    /// Stops when port is full, remaining packets are sent on next interrupt
+    pub fn flush(&self, port: &mut impl Transmit) -> Result<(), MidiError> {
+        let mut pending = self.pending.lock();
+        while let Some(packet) = pending.take().or_else(|| self.tx.pop()) {
+            match port.transmit(PacketList::single(packet)) {
+                Err(MidiError::BufferFull) => {
+                    *pending = Some(packet);
+                    break;
+                }
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AsyncReceive for &'static AsyncPort {
+    fn recv(&mut self) -> impl Future<Output=Result<Packet, MidiError>> {
+        let port = *self;
+        port.listening.store(true, Ordering::Relaxed);
+        async move {
+            let mut rx = &port.rx;
+            rx.recv().await
+        }
+    }
+}
+
+impl AsyncTransmit for &'static AsyncPort {
+    fn send(&mut self, packets: PacketList) -> impl Future<Output=Result<(), MidiError>> {
+        let port = *self;
+        async move {
+            let mut tx = &port.tx;
+            for packet in packets.iter() {
+                tx.send(PacketList::single(*packet)).await?;
+                // queue is drained by interrupt handler
+                NVIC::pend(port.interrupt);
+            }
+            Ok(())
+        }
+    }
+}
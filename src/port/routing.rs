@@ -0,0 +1,98 @@
+//! Packet routing between MIDI ports
+//! Interrupt handlers only touch the lock-free `AsyncPort` queues,
+//! the port registry and router are only used from tasks, in thread mode.
+
+use midi::{AsyncReceive, MidiError, MidiPorts, MidiRegistry, PacketList, PortDirection, PortId, PortInfo, Route, Router};
+
+use runtime::{Local, spawn};
+
+use crate::{MIDI_DIN_1, MIDI_DIN_2, MIDI_USB_1};
+use crate::port::AsyncPort;
+
+/// Host computer
+pub const PORT_USB: PortId = PortId::Usb(0);
+
+/// BeatStep through MIDI USB Coprocessor
+pub const PORT_BEATSTEP: PortId = PortId::Serial(1);
+
+/// Korg DW-6000
+pub const PORT_DW6000: PortId = PortId::Serial(2);
+
+/// Name and async side of each port
+static PORTS: [(PortId, &str, &AsyncPort); 3] = [
+    (PORT_USB, "USB", &MIDI_USB_1),
+    (PORT_BEATSTEP, "BeatStep", &MIDI_DIN_1),
+    (PORT_DW6000, "DW-6000", &MIDI_DIN_2),
+];
+
+const MAX_ROUTES: usize = 8;
+
+static REGISTRY: Local<MidiRegistry<4>> = Local::uninit("MIDI_REGISTRY");
+static ROUTER: Local<Router<MAX_ROUTES>> = Local::uninit("MIDI_ROUTER");
+
+/// Register the `Out` port of each interface, add routes and start a routing task per route source
+/// Ports read by apps must not be route sources, an async port has a single receiving task
+pub fn start(routes: impl IntoIterator<Item=Route>) -> Result<(), MidiError> {
+    let registry = REGISTRY.init_static(MidiRegistry::default().with_clock(runtime::now_millis));
+    for (port_id, name, _) in PORTS.iter() {
+        registry.acquire_port(PortInfo::new(*port_id, PortDirection::Out).with_name(*name))?;
+    }
+
+    let router = ROUTER.init_static(Router::default());
+    for route in routes {
+        router.add(route)?;
+    }
+    for (port_id, _, port) in PORTS.iter() {
+        if router.routes().any(|(_, route)| route.source() == *port_id) {
+            spawn(route_from(*port_id, *port));
+        }
+    }
+    Ok(())
+}
+
+/// Send packets to a port, from tasks
+pub fn midi_send(destination: PortId, packets: PacketList) {
+    let result = match REGISTRY.find_port(destination, PortDirection::Out) {
+        Some(handle) => packets.iter().try_for_each(|packet| {
+            // registry port buffer can be shorter than a sysex dump
+            if REGISTRY.space(&handle)? == 0 {
+                flush();
+            }
+            REGISTRY.write(&handle, *packet)
+        }),
+        None => Err(MidiError::InvalidPort),
+    };
+    flush();
+    if let Err(err) = result {
+        info!("Failed to send MIDI to {:?}: {:?}", destination, err)
+    }
+}
+
+/// Dispatch packets received from port to the destinations of its routes
+async fn route_from(source: PortId, mut port: &'static AsyncPort) {
+    loop {
+        match port.recv().await {
+            Ok(packet) => {
+                if let Err(err) = ROUTER.dispatch(source, &PacketList::single(packet), &*REGISTRY) {
+                    warn!("Routing from {:?} failed: {:?}", source, err);
+                }
+                flush();
+            }
+            Err(err) => error!("{}", err),
+        }
+    }
+}
+
+/// Move packets from registry `Out` ports to async ports, for interrupt handlers to transmit
+/// Packets an async port has no room for are dropped and counted in registry port stats
+fn flush() {
+    for (port_id, _, port) in PORTS.iter() {
+        if let Some(handle) = REGISTRY.find_port(*port_id, PortDirection::Out) {
+            while let Ok(Some(packet)) = REGISTRY.read(&handle) {
+                if let Err(err) = port.try_send(packet) {
+                    let _ = REGISTRY.record_error(&handle, &err);
+                }
+            }
+        }
+    }
+}
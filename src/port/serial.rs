@@ -2,105 +2,66 @@
 
 use embedded_hal::serial::{Write, Read};
 
-use hal::serial::{Event, TxISR, RxISR, CommonPins, Listen};
+use hal::serial::{CommonPins, Rx, Serial, Tx, TxListen};
 
-use heapless::spsc::Queue;
-use midi::{Packet, MidiError, Receive, Transmit, PacketList};
+use midi::{Packet, MidiError, Receive, Transmit, PacketList, PortStats, SerialMidiPort};
 
 // TODO use DMA? https://github.com/stm32-rs/stm32f4xx-hal/blob/master/examples/rtic-serial-dma-rx-idle.rs
 
+/// Serial MIDI port driven by UART interrupts
 pub struct SerialMidi<UART: CommonPins> {
-    pub uart: hal::serial::Serial<UART>,
-    pub tx_fifo: Queue<u8, 64>,
-    parser: midi::PacketParser,
-    last_status: Option<u8>,
+    port: SerialMidiPort<Rx<UART>, Tx<UART>>,
 }
 
 impl<UART> SerialMidi<UART> where
     UART: CommonPins,
-    hal::serial::Serial<UART>: TxISR + Write<u8> + Listen,
+    Tx<UART>: TxListen + Write<u8>,
 {
-    pub fn new(uart: hal::serial::Serial<UART>) -> Self {
+    pub fn new(uart: Serial<UART>) -> Self {
+        let (tx, rx) = uart.split();
         SerialMidi {
-            uart,
-            tx_fifo: Queue::new(),
-            parser: midi::PacketParser::default(),
-            last_status: None,
+            port: SerialMidiPort::new(rx, tx).with_cable_num(1),
         }
     }
 
-    pub fn flush(&mut self) -> Result<(), MidiError> {
-        'write_bytes:
-        loop {
-            if self.uart.is_tx_empty() {
-                if let Some(byte) = self.tx_fifo.dequeue() {
-                    self.uart.write(byte)?;
-                    continue 'write_bytes;
-                } else {
-                    self.uart.unlisten(Event::Txe)
-                }
-            } else {
-                self.uart.listen(Event::Txe)
-            }
-            return Ok(());
-        }
+    /// Clock used to timestamp port activity, e.g. millis since boot
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.port = self.port.with_clock(clock);
+        self
     }
 
-    fn write_all(&mut self, payload: &[u8]) -> Result<(), MidiError> {
-        for byte in payload {
-            self.write_byte(*byte)?
-        }
-        Ok(())
+    pub fn stats(&self) -> PortStats {
+        self.port.stats()
     }
 
-    fn write_byte(&mut self, byte: u8) -> Result<(), MidiError> {
-        self.tx_fifo.enqueue(byte).map_err(|_| MidiError::BufferFull)?;
+    /// Write pending bytes, listening for TX empty interrupt until all bytes are written
+    pub fn flush(&mut self) -> Result<(), MidiError> {
+        self.port.flush()?;
+        if self.port.is_flushed() {
+            self.port.tx_mut().unlisten()
+        } else {
+            self.port.tx_mut().listen()
+        }
         Ok(())
     }
 }
 
 impl<UART> Receive for SerialMidi<UART> where
     UART: CommonPins,
-    hal::serial::Serial<UART>: RxISR + Read<u8>,
+    Rx<UART>: Read<u8>,
 {
     fn receive(&mut self) -> Result<Option<Packet>, MidiError> {
-        if self.uart.is_rx_not_empty() {
-            let byte = self.uart.read()?;
-            let packet = self.parser.advance(byte)?;
-            if let Some(packet) = packet {
-                return Ok(Some(packet.with_cable_num(1)));
-            }
-        }
-        Ok(None)
+        self.port.receive()
     }
 }
 
 impl<UART> Transmit for SerialMidi<UART> where
     UART: CommonPins,
-    hal::serial::Serial<UART>: TxISR + Write<u8> + Listen,
+    Tx<UART>: TxListen + Write<u8>,
 {
     fn transmit(&mut self, packets: PacketList) -> Result<(), MidiError> {
-        for packet in packets.iter() {
-            let mut payload = packet.payload();
-
-            if midi::is_channel_status(payload[0]) {
-                // Apply MIDI "running status"
-                if let Some(last_status) = self.last_status {
-                    if payload[0] == last_status {
-                        // same status as last time, chop out status byte
-                        payload = &payload[1..];
-                    } else {
-                        // take note of new status
-                        self.last_status = Some(payload[0])
-                    }
-                }
-            } else {
-                // non-repeatable status or no status (sysex)
-                self.last_status = None
-            }
-            self.write_all(payload)?;
-        }
+        let result = self.port.transmit(packets);
         self.flush()?;
-        Ok(())
+        result
     }
 }
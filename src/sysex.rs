@@ -1,4 +1,4 @@
-use midi::{Packet, MidiMessage, PacketList};
+use midi::{Packet, MidiMessage, PacketList, PacketParser};
 use alloc::vec::Vec;
 
 use midi::MidiMessage::{SysexEnd2, SysexEnd1, SysexEnd, SysexBegin, SysexCont, SysexEmpty, SysexSingleByte};
@@ -7,6 +7,7 @@ use core::convert::TryFrom;
 use heapless::spsc::Queue;
 use alloc::collections::BTreeMap;
 use core::iter::FromIterator;
+use core::slice;
 use crate::sysex::SysexCapture::{Pending, Captured};
 use crate::sysex::SysexError::{BufferOverflow, SpuriousContinuation, SpuriousEnd};
 
@@ -171,25 +172,23 @@ impl Iterator for SysexSeq {
             if self.tok_idx >= self.tokens.len() {
                 break;
             }
-            match &self.tokens[self.tok_idx] {
-                Token::Seq(slice) => {
-                    if self.window.enqueue(slice[self.byte_idx]).is_err() {
-                        break;
-                    }
-                    self.byte_idx += 1;
-                    if self.byte_idx == slice.len() {
-                        self.tok_idx += 1;
-                        self.byte_idx = 0;
-                    }
-                }
-                Token::Val(val) => {
-                    if self.window.enqueue(*val).is_err() {
-                        break;
-                    }
-                    self.tok_idx += 1;
-                }
-                _ => {}
+            let slice: &[u8] = match &self.tokens[self.tok_idx] {
+                Token::Seq(slice) => slice,
+                Token::Buf(buf) => buf.as_slice(),
+                Token::Val(val) => slice::from_ref(val),
+                // nothing to send for matcher-only tokens
+                _ => &[],
             };
+            if self.byte_idx < slice.len() {
+                if self.window.enqueue(slice[self.byte_idx]).is_err() {
+                    break;
+                }
+                self.byte_idx += 1;
+            }
+            if self.byte_idx >= slice.len() {
+                self.tok_idx += 1;
+                self.byte_idx = 0;
+            }
         }
         if !start && self.window.len() < 3 {
             // mark as done
@@ -334,3 +333,35 @@ impl SysexMatcher {
         false
     }
 }
+
+/// Appends bytes of a sysex packet to buffer, including SYSEX_START and SYSEX_END markers
+/// Returns true once the message is complete. Messages longer than the buffer capacity are dropped.
+pub fn collect_sysex(buffer: &mut Vec<u8>, packet: Packet) -> bool {
+    let complete = match MidiMessage::try_from(packet) {
+        Ok(SysexBegin(..)) => {
+            buffer.clear();
+            false
+        }
+        Ok(SysexSingleByte(_) | SysexEmpty) => {
+            buffer.clear();
+            true
+        }
+        Ok(SysexCont(..)) if !buffer.is_empty() => false,
+        Ok(SysexEnd | SysexEnd1(_) | SysexEnd2(..)) if !buffer.is_empty() => true,
+        // not part of a sysex, realtime messages may be interleaved
+        _ => return false,
+    };
+    let payload = packet.payload();
+    if buffer.len() + payload.len() > buffer.capacity() {
+        buffer.clear();
+        return false;
+    }
+    buffer.extend_from_slice(payload);
+    complete
+}
+
+/// Packets of a complete sysex message, including SYSEX_START and SYSEX_END markers
+pub fn sysex_packets(sysex: &[u8]) -> PacketList {
+    let mut parser = PacketParser::default();
+    sysex.iter().filter_map(|byte| parser.advance(*byte).ok().flatten()).collect()
+}